@@ -0,0 +1,161 @@
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anyhow::{anyhow, Context, Result};
+use spl_token_2022::{extension::StateWithExtensions, state::Mint};
+
+use crate::RaydiumIntegration;
+
+impl RaydiumIntegration {
+    /// Returns the decimals of an SPL Token or Token-2022 mint.
+    ///
+    /// Decimals never change, so a mint cached by a pool snapshot is used regardless of its age.
+    pub fn get_mint_decimals(&self, mint: Pubkey) -> Result<u8> {
+        let account = match self.cache.get_any(&mint) {
+            Some(account) => account,
+            None => self
+                .client_rpc
+                .get_account(&mint)
+                .context("failed to fetch mint account")?,
+        };
+
+        if account.owner != spl_token::id() && account.owner != spl_token_2022::id() {
+            return Err(anyhow!("account {mint} is not a token mint"));
+        }
+
+        // Token-2022 mints share the SPL Token layout followed by extensions.
+        let mint_data = StateWithExtensions::<Mint>::unpack(&account.data)
+            .context("failed to unpack mint account")?;
+        Ok(mint_data.base.decimals)
+    }
+
+    /// Converts an amount in display units (e.g. "1000.5") into base units of the mint.
+    pub fn parse_amount(&self, mint: Pubkey, value: &str) -> Result<u64> {
+        parse_ui_amount(value, self.get_mint_decimals(mint)?)
+    }
+
+    /// Formats an amount in base units of the mint in display units.
+    pub fn format_amount(&self, mint: Pubkey, amount: u64) -> Result<String> {
+        Ok(format_ui_amount(amount, self.get_mint_decimals(mint)?))
+    }
+}
+
+/// Parses an amount in display units into base units without going through floating point.
+pub fn parse_ui_amount(value: &str, decimals: u8) -> Result<u64> {
+    let value = value.trim();
+    let (integer, fraction) = value.split_once('.').unwrap_or((value, ""));
+
+    let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if (integer.is_empty() && fraction.is_empty()) || !is_digits(integer) || !is_digits(fraction) {
+        return Err(anyhow!("invalid amount: {value}"));
+    }
+
+    // Trailing zeros beyond the mint decimals do not change the amount.
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.len() > usize::from(decimals) {
+        return Err(anyhow!(
+            "amount {value} has more than {decimals} decimal places"
+        ));
+    }
+
+    let digits = format!(
+        "{integer}{fraction:0<width$}",
+        width = usize::from(decimals)
+    );
+    let digits = digits.trim_start_matches('0');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+
+    digits
+        .parse::<u64>()
+        .map_err(|_| anyhow!("amount {value} too large for u64"))
+}
+
+/// Formats an amount in base units in display units, trimming trailing zeros.
+pub fn format_ui_amount(amount: u64, decimals: u8) -> String {
+    let decimals = usize::from(decimals);
+    let digits = format!("{amount:0>width$}", width = decimals + 1);
+    let (integer, fraction) = digits.split_at(digits.len() - decimals);
+
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        integer.to_string()
+    } else {
+        format!("{integer}.{fraction}")
+    }
+}
+
+/// Scales a value in base units (e.g. a valuation) to display units.
+pub fn scale_ui_value(value: f64, decimals: u8) -> f64 {
+    value / 10f64.powi(i32::from(decimals))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_rejects_empty_amounts() {
+        assert!(parse_ui_amount("", 6).is_err());
+        assert!(parse_ui_amount("  ", 6).is_err());
+        assert!(parse_ui_amount(".", 6).is_err());
+    }
+
+    #[test]
+    fn parse_accepts_missing_integer_or_fraction() {
+        assert_eq!(parse_ui_amount("1.", 6).unwrap(), 1_000_000);
+        assert_eq!(parse_ui_amount(".5", 6).unwrap(), 500_000);
+        assert_eq!(parse_ui_amount("0.000001", 6).unwrap(), 1);
+        assert!(parse_ui_amount("1000.5", 0).is_err());
+        assert_eq!(parse_ui_amount("42", 0).unwrap(), 42);
+    }
+
+    #[test]
+    fn parse_rejects_invalid_characters() {
+        assert!(parse_ui_amount("-1", 6).is_err());
+        assert!(parse_ui_amount("1.2.3", 6).is_err());
+        assert!(parse_ui_amount("1e6", 6).is_err());
+        assert!(parse_ui_amount("1,5", 6).is_err());
+    }
+
+    #[test]
+    fn parse_rejects_too_many_decimal_places() {
+        assert!(parse_ui_amount("0.0000001", 6).is_err());
+        // Trailing zeros beyond the decimals do not add precision.
+        assert_eq!(parse_ui_amount("0.1000000", 6).unwrap(), 100_000);
+    }
+
+    #[test]
+    fn parse_rejects_u64_overflow() {
+        assert_eq!(
+            parse_ui_amount("18446744073709551615", 0).unwrap(),
+            u64::MAX
+        );
+        assert!(parse_ui_amount("18446744073709551616", 0).is_err());
+        assert!(parse_ui_amount("18446744073709.551616", 6).is_err());
+    }
+
+    #[test]
+    fn format_trims_trailing_zeros() {
+        assert_eq!(format_ui_amount(0, 6), "0");
+        assert_eq!(format_ui_amount(1, 6), "0.000001");
+        assert_eq!(format_ui_amount(1_500_000, 6), "1.5");
+        assert_eq!(format_ui_amount(2_000_000, 6), "2");
+        assert_eq!(format_ui_amount(42, 0), "42");
+    }
+
+    #[test]
+    fn format_round_trips_through_parse() {
+        for (amount, decimals) in [
+            (0, 9),
+            (1, 9),
+            (123_456_789, 9),
+            (1_000_000_000, 9),
+            (u64::MAX, 0),
+            (u64::MAX, 9),
+            (u64::MAX, 19),
+        ] {
+            let formatted = format_ui_amount(amount, decimals);
+            assert_eq!(parse_ui_amount(&formatted, decimals).unwrap(), amount);
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anyhow::{anyhow, Result};
+use raydium_cp_swap::states::PoolState;
+use spl_token::native_mint;
+
+use crate::{
+    events::SentTransaction,
+    route::{other_mint, quote_route, Route, RoutePool, TransferFees},
+    RaydiumIntegration,
+};
+
+/// Profitable round trip from a mint back to itself through two or three pools.
+#[derive(Debug, Clone)]
+pub struct ArbitrageOpportunity {
+    /// Swaps from the start mint back to it, whose minimum output covers the input and the
+    /// transaction fee.
+    pub route: Route,
+    /// Output less input of the round trip, net of trade and transfer fees.
+    pub gross_profit: u64,
+    /// Transaction fee including the priority fee in lamports.
+    pub network_fee: u64,
+    /// Transaction fee valued in the start mint.
+    pub network_fee_in_mint: u64,
+    /// Gross profit less the transaction fee, in the start mint.
+    pub net_profit: u64,
+}
+
+impl RaydiumIntegration {
+    /// Finds profitable round trips starting and ending in `mint_a`, most profitable first.
+    ///
+    /// Cycles go through two pools of `mint_a` and `mint_b` under different AMM configs, or
+    /// through a pool of `mint_a` and `mint_b` and pools of a third token in either direction.
+    /// Each cycle is sized to the input amount with the largest profit, net of trade and
+    /// transfer fees. The transaction fee is valued in `mint_a` at the price of its deepest
+    /// pool with SOL.
+    pub fn find_arbitrage(
+        &self,
+        mint_a: Pubkey,
+        mint_b: Pubkey,
+    ) -> Result<Vec<ArbitrageOpportunity>> {
+        if mint_a == mint_b {
+            return Err(anyhow!("arbitrage mints are the same"));
+        }
+
+        let pools_a = self.get_pools_with_mint(mint_a)?;
+        let pools_b = self.get_pools_with_mint(mint_b)?;
+
+        let pair: Vec<Pubkey> = pools_a
+            .iter()
+            .filter(|(_, data)| other_mint(data, &mint_a) == mint_b)
+            .map(|(pool_state, _)| *pool_state)
+            .collect();
+
+        let mut cycles = Vec::new();
+        for first in &pair {
+            // Buy in one fee tier and sell in another.
+            for second in &pair {
+                if first != second {
+                    cycles.push(vec![*first, *second]);
+                }
+            }
+            // Close the cycle through a third token, in both directions.
+            for (middle, middle_data) in &pools_b {
+                let mint_c = other_mint(middle_data, &mint_b);
+                if mint_c == mint_a {
+                    continue;
+                }
+                for (last, last_data) in &pools_a {
+                    if other_mint(last_data, &mint_a) == mint_c {
+                        cycles.push(vec![*first, *middle, *last]);
+                        cycles.push(vec![*last, *middle, *first]);
+                    }
+                }
+            }
+        }
+
+        let pool_data: HashMap<Pubkey, PoolState> = pools_a.into_iter().chain(pools_b).collect();
+        let mut mints: Vec<Pubkey> = pool_data
+            .values()
+            .flat_map(|data| [data.token_0_mint, data.token_1_mint])
+            .collect();
+        mints.sort();
+        mints.dedup();
+
+        let pools = self.get_route_pools(&pool_data)?;
+        let sol_price = sol_price(&mint_a, &pools)
+            .ok_or_else(|| anyhow!("no pool of {mint_a} and SOL to value transaction fees"))?;
+        let transfer_fees = self.get_transfer_fees(&mints)?;
+
+        let mut opportunities = Vec::new();
+        for cycle in &cycles {
+            let Some(hops) = cycle
+                .iter()
+                .map(|key| pools.get(key))
+                .collect::<Option<Vec<&RoutePool>>>()
+            else {
+                continue;
+            };
+            let Some(route) = size_cycle(mint_a, &hops, &transfer_fees) else {
+                continue;
+            };
+
+            let gross_profit = route.amount_out - route.amount_in;
+            let network_fee = self.get_transaction_fee(&self.create_route_instructions(&route)?)?;
+            let network_fee_in_mint = (network_fee as f64 * sol_price).ceil() as u64;
+            if gross_profit <= network_fee_in_mint {
+                continue;
+            }
+
+            opportunities.push(ArbitrageOpportunity {
+                route: bound_to_profit(route, network_fee_in_mint),
+                gross_profit,
+                network_fee,
+                network_fee_in_mint,
+                net_profit: gross_profit - network_fee_in_mint,
+            });
+        }
+        opportunities.sort_by(|a, b| b.net_profit.cmp(&a.net_profit));
+
+        Ok(opportunities)
+    }
+
+    /// Swaps along an opportunity's cycle in a single transaction, which fails unless the
+    /// output covers the input and the transaction fee.
+    pub fn execute_arbitrage(&self, opportunity: &ArbitrageOpportunity) -> Result<SentTransaction> {
+        if self.multisig.is_some() {
+            return Err(anyhow!(
+                "arbitrage cannot be executed through multisig proposals"
+            ));
+        }
+        self.swap_route(&opportunity.route)
+    }
+}
+
+/// Returns the cycle quoted at the input amount with the largest gross profit, if any is
+/// profitable before the transaction fee.
+///
+/// The profit of a chain of constant-product swaps is concave in the input amount, so the
+/// maximum is found by ternary search up to the first pool's input reserve.
+fn size_cycle(mint: Pubkey, hops: &[&RoutePool], transfer_fees: &TransferFees) -> Option<Route> {
+    let first = hops.first()?;
+    let reserve_in = if mint == first.keys.token_0_mint {
+        first.market.liquidity.token_0_amount
+    } else {
+        first.market.liquidity.token_1_amount
+    };
+
+    // Intermediate hops pass on their whole output since the cycle is atomic.
+    let quote = |amount_in: u64| quote_route(mint, mint, amount_in, hops, 0.0, transfer_fees).ok();
+    let profit = |amount_in: u64| {
+        quote(amount_in).map_or(i128::MIN, |route| {
+            i128::from(route.amount_out) - i128::from(amount_in)
+        })
+    };
+
+    if reserve_in == 0 {
+        return None;
+    }
+    let (mut low, mut high) = (1, reserve_in);
+    while high - low > 2 {
+        let third = (high - low) / 3;
+        let (left, right) = (low + third, high - third);
+        if profit(left) < profit(right) {
+            low = left + 1;
+        } else {
+            high = right;
+        }
+    }
+
+    let amount_in = (low..=high).max_by_key(|&amount_in| profit(amount_in))?;
+    if profit(amount_in) <= 0 {
+        return None;
+    }
+    quote(amount_in)
+}
+
+/// Requires the cycle's output to cover its input and the transaction fee.
+fn bound_to_profit(mut route: Route, network_fee_in_mint: u64) -> Route {
+    let minimum_amount_out = route.amount_in + network_fee_in_mint;
+    route.minimum_amount_out = minimum_amount_out;
+    if let Some(last_hop) = route.hops.last_mut() {
+        last_hop.minimum_amount_out = minimum_amount_out;
+    }
+    route
+}
+
+/// Returns the price of a lamport in base units of the mint from its deepest pool with SOL.
+fn sol_price(mint: &Pubkey, pools: &HashMap<Pubkey, RoutePool>) -> Option<f64> {
+    if *mint == native_mint::id() {
+        return Some(1.0);
+    }
+
+    pools
+        .values()
+        .filter_map(|pool| {
+            let liquidity = &pool.market.liquidity;
+            let (mint_amount, sol_amount) = if pool.keys.token_0_mint == *mint
+                && pool.keys.token_1_mint == native_mint::id()
+            {
+                (liquidity.token_0_amount, liquidity.token_1_amount)
+            } else if pool.keys.token_1_mint == *mint && pool.keys.token_0_mint == native_mint::id()
+            {
+                (liquidity.token_1_amount, liquidity.token_0_amount)
+            } else {
+                return None;
+            };
+            (sol_amount > 0).then_some((sol_amount, mint_amount as f64 / sol_amount as f64))
+        })
+        .max_by_key(|(sol_amount, _)| *sol_amount)
+        .map(|(_, price)| price)
+}
+
+#[cfg(test)]
+mod tests {
+    use raydium_cp_swap::states::AmmConfig;
+
+    use super::*;
+    use crate::{instructions::PoolKeys, market::Market, PoolLiquidity};
+
+    /// Pool of the mints with the given reserves and trade fee rate, in millionths.
+    fn route_pool(mints: (Pubkey, Pubkey), reserves: (u64, u64), trade_fee_rate: u64) -> RoutePool {
+        let mut pool_data = PoolState::default();
+        pool_data.token_0_mint = mints.0;
+        pool_data.token_1_mint = mints.1;
+        RoutePool {
+            keys: PoolKeys::from_pool_state(
+                &raydium_cp_swap::id(),
+                Pubkey::new_unique(),
+                &pool_data,
+            ),
+            market: Market {
+                liquidity: PoolLiquidity {
+                    token_0_amount: reserves.0,
+                    token_1_amount: reserves.1,
+                    lp_supply: 1_000_000,
+                },
+                amm_config: AmmConfig {
+                    trade_fee_rate,
+                    ..AmmConfig::default()
+                },
+                decimals_0: 6,
+                decimals_1: 6,
+            },
+        }
+    }
+
+    #[test]
+    fn sizes_cycle_at_maximum_profit() {
+        let (mint_a, mint_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        // `mint_b` costs a quarter of `mint_a` at 0.1% in the first pool and sells at par at 1%
+        // in the second.
+        let cheap = route_pool((mint_a, mint_b), (1_000_000, 4_000_000), 1_000);
+        let dear = route_pool((mint_a, mint_b), (1_000_000, 1_000_000), 10_000);
+        let transfer_fees = TransferFees::from_mint_accounts(0, []).unwrap();
+
+        // The cycle acts as one constant-product swap with output k * a / (d + e * a), whose
+        // profit is largest at a = (sqrt(k * d) - d) / e.
+        let (fee_1, fee_2) = (0.999, 0.99);
+        let k = fee_1 * fee_2 * 4e6 * 1e6;
+        let d = 1e6 * 1e6;
+        let e = fee_1 * (1e6 + fee_2 * 4e6);
+        let optimum = ((k * d).sqrt() - d) / e;
+        let max_profit = k * optimum / (d + e * optimum) - optimum;
+        assert_eq!(optimum.round(), 199_591.0);
+        assert_eq!(max_profit.floor(), 197_391.0);
+
+        let route = size_cycle(mint_a, &[&cheap, &dear], &transfer_fees).unwrap();
+        assert_eq!(route.input_mint, mint_a);
+        assert_eq!(route.output_mint, mint_a);
+        assert_eq!(route.hops.len(), 2);
+        assert_eq!(route.hops[1].amount_in, route.hops[0].amount_out);
+
+        // Rounding of the fees and outputs flattens the maximum over a few hundred units.
+        assert!((route.amount_in as f64 - optimum).abs() < optimum * 0.01);
+        let profit = (route.amount_out - route.amount_in) as f64;
+        assert!(profit <= max_profit && profit >= max_profit - 2.0);
+    }
+
+    #[test]
+    fn unprofitable_cycle_is_not_sized() {
+        let (mint_a, mint_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let cheap = route_pool((mint_a, mint_b), (1_000_000, 4_000_000), 1_000);
+        let dear = route_pool((mint_a, mint_b), (1_000_000, 1_000_000), 10_000);
+        let transfer_fees = TransferFees::from_mint_accounts(0, []).unwrap();
+
+        assert!(size_cycle(mint_a, &[&dear, &cheap], &transfer_fees).is_none());
+
+        let same = route_pool((mint_a, mint_b), (1_000_000, 1_000_000), 2_500);
+        assert!(size_cycle(mint_a, &[&dear, &same], &transfer_fees).is_none());
+    }
+}
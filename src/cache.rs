@@ -0,0 +1,362 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use anchor_client::{
+    anchor_lang::AccountDeserialize,
+    solana_client::{rpc_client::RpcClient, rpc_config::RpcAccountInfoConfig},
+    solana_sdk::{account::Account, pubkey::Pubkey},
+};
+use anyhow::{anyhow, Context, Result};
+use raydium_cp_swap::states::{AmmConfig, PoolState};
+use solana_account_decoder::UiAccountEncoding;
+use solana_program::instruction::Instruction;
+use spl_token_2022::extension::StateWithExtensions;
+
+use crate::{PoolLiquidity, RaydiumIntegration};
+
+/// Largest number of accounts fetched by one `getMultipleAccounts` request.
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+/// Account as of the context slot it was fetched at.
+#[derive(Debug, Clone)]
+struct CachedAccount {
+    account: Account,
+    slot: u64,
+    fetched_at: Instant,
+}
+
+/// Cache of fetched accounts keyed by address.
+///
+/// Accounts fetched together share the context slot of the request, and cached accounts are
+/// only reused together if they were fetched at the same slot, so a set of accounts is always a
+/// consistent snapshot. Entries older than `max_age` are fetched again. Every request asks for a
+/// context slot at least as recent as the latest one seen, so a load-balanced RPC endpoint never
+/// serves state older than what was already observed.
+#[derive(Debug)]
+pub struct AccountCache {
+    max_age: Duration,
+    entries: RefCell<HashMap<Pubkey, CachedAccount>>,
+    /// Accounts related to each pool, which never change for a pool.
+    pools: RefCell<HashMap<Pubkey, PoolAccounts>>,
+    /// Latest context slot seen.
+    slot: Cell<u64>,
+}
+
+/// Accounts fetched with a pool's state for a snapshot.
+#[derive(Debug, Clone, Copy)]
+struct PoolAccounts {
+    token_0_vault: Pubkey,
+    token_1_vault: Pubkey,
+    token_0_mint: Pubkey,
+    token_1_mint: Pubkey,
+    amm_config: Pubkey,
+}
+
+/// Pool state, fee configuration and reserves read at a single slot.
+#[derive(Debug, Clone)]
+pub struct PoolSnapshot {
+    /// Context slot the accounts were read at.
+    pub slot: u64,
+    /// Pool's state account.
+    pub pool_state: Pubkey,
+    /// Pool's state.
+    pub pool_data: PoolState,
+    /// AMM config the pool belongs to.
+    pub amm_config: AmmConfig,
+    /// Token 0 vault balance, including accrued protocol and fund fees.
+    pub token_0_vault_amount: u64,
+    /// Token 1 vault balance, including accrued protocol and fund fees.
+    pub token_1_vault_amount: u64,
+    /// Pool liquidity, excluding accrued protocol and fund fees.
+    pub liquidity: PoolLiquidity,
+}
+
+impl PoolSnapshot {
+    /// Creates a snapshot from accounts read at the slot, computing the liquidity from the vault
+    /// balances.
+    pub(crate) fn new(
+        slot: u64,
+        pool_state: Pubkey,
+        pool_data: PoolState,
+        amm_config: AmmConfig,
+        token_0_vault_amount: u64,
+        token_1_vault_amount: u64,
+    ) -> Self {
+        Self {
+            slot,
+            pool_state,
+            amm_config,
+            token_0_vault_amount,
+            token_1_vault_amount,
+            liquidity: liquidity_without_fees(
+                &pool_data,
+                token_0_vault_amount,
+                token_1_vault_amount,
+            ),
+            pool_data,
+        }
+    }
+}
+
+impl AccountCache {
+    /// Creates an empty cache reusing accounts for at most `max_age`.
+    pub fn new(max_age: Duration) -> Self {
+        Self {
+            max_age,
+            entries: RefCell::new(HashMap::new()),
+            pools: RefCell::new(HashMap::new()),
+            slot: Cell::new(0),
+        }
+    }
+
+    /// Returns the latest context slot seen.
+    pub fn slot(&self) -> u64 {
+        self.slot.get()
+    }
+
+    /// Returns the accounts as of a single context slot, from the cache if all of them are fresh
+    /// and were fetched together, otherwise with one request.
+    pub fn get_multiple_accounts(
+        &self,
+        rpc: &RpcClient,
+        addresses: &[Pubkey],
+    ) -> Result<(u64, Vec<Option<Account>>)> {
+        if let Some(cached) = self.get_cached(addresses) {
+            return Ok(cached);
+        }
+        if addresses.len() > MAX_MULTIPLE_ACCOUNTS {
+            return Err(anyhow!(
+                "cannot fetch more than {MAX_MULTIPLE_ACCOUNTS} accounts at a single slot"
+            ));
+        }
+
+        let response = rpc
+            .get_multiple_accounts_with_config(
+                addresses,
+                RpcAccountInfoConfig {
+                    encoding: Some(UiAccountEncoding::Base64),
+                    commitment: Some(rpc.commitment()),
+                    min_context_slot: Some(self.slot.get()),
+                    ..RpcAccountInfoConfig::default()
+                },
+            )
+            .context("failed to fetch accounts")?;
+        let slot = response.context.slot;
+        self.slot.set(self.slot.get().max(slot));
+
+        let fetched_at = Instant::now();
+        let mut entries = self.entries.borrow_mut();
+        for (address, account) in addresses.iter().zip(&response.value) {
+            match account {
+                // Never replace an account with an older version of it.
+                Some(account)
+                    if entries
+                        .get(address)
+                        .map_or(true, |entry| entry.slot <= slot) =>
+                {
+                    entries.insert(
+                        *address,
+                        CachedAccount {
+                            account: account.clone(),
+                            slot,
+                            fetched_at,
+                        },
+                    );
+                }
+                Some(_) => {}
+                None => {
+                    entries.remove(address);
+                }
+            }
+        }
+
+        Ok((slot, response.value))
+    }
+
+    /// Returns any cached version of the account regardless of its age, for data that never
+    /// changes such as mint decimals.
+    pub fn get_any(&self, address: &Pubkey) -> Option<Account> {
+        self.entries
+            .borrow()
+            .get(address)
+            .map(|entry| entry.account.clone())
+    }
+
+    /// Drops the cached accounts, e.g. after sending a transaction that writes to them.
+    pub fn invalidate<'a>(&self, addresses: impl IntoIterator<Item = &'a Pubkey>) {
+        let mut entries = self.entries.borrow_mut();
+        for address in addresses {
+            entries.remove(address);
+        }
+    }
+
+    /// Drops every cached account.
+    pub fn clear(&self) {
+        self.entries.borrow_mut().clear();
+    }
+
+    fn get_cached(&self, addresses: &[Pubkey]) -> Option<(u64, Vec<Option<Account>>)> {
+        let entries = self.entries.borrow();
+        let mut slot = None;
+        let mut accounts = Vec::with_capacity(addresses.len());
+
+        for address in addresses {
+            let entry = entries.get(address)?;
+            if entry.fetched_at.elapsed() > self.max_age
+                || *slot.get_or_insert(entry.slot) != entry.slot
+            {
+                return None;
+            }
+            accounts.push(Some(entry.account.clone()));
+        }
+
+        Some((slot?, accounts))
+    }
+}
+
+impl Default for AccountCache {
+    /// Creates a cache that fetches every set of accounts again, still in one request at a
+    /// single slot.
+    fn default() -> Self {
+        Self::new(Duration::ZERO)
+    }
+}
+
+impl RaydiumIntegration {
+    /// Reuses fetched accounts for at most `max_age`.
+    pub fn with_cache_max_age(mut self, max_age: Duration) -> Self {
+        self.cache = AccountCache::new(max_age);
+        self
+    }
+
+    /// Reads the pool state, its AMM config, vaults and mints at a single slot.
+    ///
+    /// Once the pool's accounts are known, all of them are fetched with one request, or taken
+    /// from the cache if they are fresh.
+    pub fn get_pool_snapshot(&self, pool_state: Pubkey) -> Result<PoolSnapshot> {
+        let known = self.cache.pools.borrow().get(&pool_state).copied();
+        let accounts = match known {
+            Some(accounts) => accounts,
+            None => {
+                let (_, fetched) = self
+                    .cache
+                    .get_multiple_accounts(&self.client_rpc, &[pool_state])?;
+                let pool_data = self.decode_pool_state(pool_state, fetched[0].as_ref())?;
+                let accounts = PoolAccounts {
+                    token_0_vault: pool_data.token_0_vault,
+                    token_1_vault: pool_data.token_1_vault,
+                    token_0_mint: pool_data.token_0_mint,
+                    token_1_mint: pool_data.token_1_mint,
+                    amm_config: pool_data.amm_config,
+                };
+                self.cache.pools.borrow_mut().insert(pool_state, accounts);
+                accounts
+            }
+        };
+
+        let addresses = [
+            pool_state,
+            accounts.amm_config,
+            accounts.token_0_vault,
+            accounts.token_1_vault,
+            accounts.token_0_mint,
+            accounts.token_1_mint,
+        ];
+        let (slot, fetched) = self
+            .cache
+            .get_multiple_accounts(&self.client_rpc, &addresses)?;
+
+        let pool_data = self.decode_pool_state(pool_state, fetched[0].as_ref())?;
+        let amm_config = match &fetched[1] {
+            Some(account) => AmmConfig::try_deserialize(&mut account.data.as_slice())
+                .context("failed to deserialize amm config")?,
+            None => return Err(anyhow!("amm config {} not found", accounts.amm_config)),
+        };
+        let fetched_vault_amount = |index: usize| -> Result<u64> {
+            vault_amount(
+                fetched[index]
+                    .as_ref()
+                    .ok_or(anyhow!("vault {} not found", addresses[index]))?,
+            )
+        };
+
+        Ok(PoolSnapshot::new(
+            slot,
+            pool_state,
+            pool_data,
+            amm_config,
+            fetched_vault_amount(2)?,
+            fetched_vault_amount(3)?,
+        ))
+    }
+
+    /// Drops the cached accounts an instruction set writes to.
+    pub(crate) fn invalidate_written_accounts(&self, instructions: &[Instruction]) {
+        self.cache.invalidate(
+            instructions
+                .iter()
+                .flat_map(|instruction| &instruction.accounts)
+                .filter(|meta| meta.is_writable)
+                .map(|meta| &meta.pubkey),
+        );
+    }
+
+    fn decode_pool_state(
+        &self,
+        pool_state: Pubkey,
+        account: Option<&Account>,
+    ) -> Result<PoolState> {
+        let account = account.ok_or(anyhow!("pool {pool_state} not found"))?;
+        if account.owner != self.program.id() {
+            return Err(anyhow!("account {pool_state} is not owned by the program"));
+        }
+        PoolState::try_deserialize(&mut account.data.as_slice())
+            .context("failed to deserialize pool state")
+    }
+}
+
+/// Returns the pool liquidity for the vault balances, excluding accrued protocol and fund fees.
+pub(crate) fn liquidity_without_fees(
+    pool_data: &PoolState,
+    token_0_vault_amount: u64,
+    token_1_vault_amount: u64,
+) -> PoolLiquidity {
+    let (token_0_amount, token_1_amount) =
+        pool_data.vault_amount_without_fee(token_0_vault_amount, token_1_vault_amount);
+
+    PoolLiquidity {
+        token_0_amount,
+        token_1_amount,
+        lp_supply: pool_data.lp_supply,
+    }
+}
+
+/// Returns the pool liquidity from its token 0 and token 1 vault accounts, in that order.
+pub(crate) fn vault_liquidity(
+    pool_data: &PoolState,
+    vault_accounts: &[Option<Account>],
+) -> Result<PoolLiquidity> {
+    let [Some(token_0_vault_account), Some(token_1_vault_account)] = vault_accounts else {
+        return Err(anyhow!("failed to fetch vault accounts"));
+    };
+
+    Ok(liquidity_without_fees(
+        pool_data,
+        vault_amount(token_0_vault_account)?,
+        vault_amount(token_1_vault_account)?,
+    ))
+}
+
+/// Returns the balance of a pool vault.
+pub(crate) fn vault_amount(account: &Account) -> Result<u64> {
+    // Vaults of Token-2022 mints share the SPL Token layout followed by extensions.
+    Ok(
+        StateWithExtensions::<spl_token_2022::state::Account>::unpack(&account.data)
+            .context("failed to unpack vault account")?
+            .base
+            .amount,
+    )
+}
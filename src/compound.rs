@@ -0,0 +1,566 @@
+use std::{error::Error, fmt};
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anyhow::{anyhow, Context, Result};
+use raydium_cp_swap::curve::TradeDirection;
+use spl_token::native_mint;
+
+use crate::{
+    cache::PoolSnapshot, events::SentTransaction, instructions::PoolKeys, PoolLiquidity,
+    RaydiumIntegration,
+};
+
+/// Lamports kept back from compounding native SOL unless configured otherwise (0.05 SOL).
+pub const DEFAULT_COMPOUND_SOL_RESERVE: u64 = 50_000_000;
+
+/// Swap of the excess side of idle balances bringing them to the pool's ratio.
+#[derive(Debug, Clone, Copy)]
+pub struct BalancingSwap {
+    /// Mint of the token in excess, which is sold.
+    pub input_mint: Pubkey,
+    /// Amount of the excess token sold.
+    pub amount_in: u64,
+    /// Quoted amount of the other token received.
+    pub amount_out: u64,
+}
+
+/// Deposit of the owner's idle token balances into a pool.
+#[derive(Debug, Clone)]
+pub struct CompoundPlan {
+    /// Pool's state account.
+    pub pool_state: Pubkey,
+    /// Mint of token 0.
+    pub token_0_mint: Pubkey,
+    /// Mint of token 1.
+    pub token_1_mint: Pubkey,
+    /// Decimals of token 0.
+    pub token_0_decimals: u8,
+    /// Decimals of token 1.
+    pub token_1_decimals: u8,
+    /// Decimals of the LP mint.
+    pub lp_decimals: u8,
+    /// Owner's idle balance of token 0.
+    pub token_0_amount: u64,
+    /// Owner's idle balance of token 1.
+    pub token_1_amount: u64,
+    /// Swap balancing the idle balances before the deposit, if requested and needed.
+    pub swap: Option<BalancingSwap>,
+    /// Largest amount of LP tokens the balances deposit, after the swap if any.
+    pub lp_amount: u64,
+}
+
+/// Idle balances compounded into a pool.
+#[derive(Debug)]
+pub struct Compounding {
+    /// Plan the compounding followed.
+    pub plan: CompoundPlan,
+    /// Balancing swap, if one was sent.
+    pub swap: Option<SentTransaction>,
+    /// Deposit of the balances.
+    pub deposit: SentTransaction,
+    /// Amount of LP tokens deposited.
+    pub lp_amount: u64,
+}
+
+/// Error of a compounding whose balancing swap confirmed but whose deposit failed.
+///
+/// The swap has already moved the balances, so it is returned for the caller to record.
+#[derive(Debug)]
+pub struct DepositAfterSwapError {
+    /// Balancing swap that was sent.
+    pub swap: SentTransaction,
+    /// Error of the deposit.
+    pub error: anyhow::Error,
+}
+
+impl fmt::Display for DepositAfterSwapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "balancing swap {} was sent but the deposit failed",
+            self.swap.signature
+        )
+    }
+}
+
+impl Error for DepositAfterSwapError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&*self.error)
+    }
+}
+
+/// Pool whose compounding failed.
+#[derive(Debug)]
+pub struct CompoundFailure {
+    /// Pool's state account.
+    pub pool_state: Pubkey,
+    /// Balancing swap, if it was sent before the failure.
+    pub swap: Option<SentTransaction>,
+    /// Error of the compounding.
+    pub error: anyhow::Error,
+}
+
+impl CompoundFailure {
+    /// Records the error of compounding a pool, keeping the balancing swap if one was sent.
+    pub fn new(pool_state: Pubkey, error: anyhow::Error) -> Self {
+        match error.downcast::<DepositAfterSwapError>() {
+            Ok(DepositAfterSwapError { swap, error }) => Self {
+                pool_state,
+                swap: Some(swap),
+                error,
+            },
+            Err(error) => Self {
+                pool_state,
+                swap: None,
+                error,
+            },
+        }
+    }
+}
+
+/// Outcome of compounding every pool where the owner holds LP tokens.
+#[derive(Debug, Default)]
+pub struct CompoundSummary {
+    /// Pools whose idle balances were deposited.
+    pub compounded: Vec<Compounding>,
+    /// Pools whose compounding failed.
+    pub failed: Vec<CompoundFailure>,
+}
+
+impl RaydiumIntegration {
+    /// Sets the lamports kept back from compounding native SOL, for transaction fees and rent.
+    pub fn with_compound_sol_reserve(mut self, lamports: u64) -> Self {
+        self.compound_sol_reserve = lamports;
+        self
+    }
+
+    /// Plans the largest deposit of the owner's idle balances of the pool tokens.
+    ///
+    /// With `balance`, the side in excess of the pool's ratio is first swapped for the other
+    /// token so that both balances can be deposited. The idle balance of native SOL is the
+    /// owner's lamports less the reserve set with `with_compound_sol_reserve`, since deposits and
+    /// swaps wrap SOL from lamports.
+    pub fn plan_compound(&self, pool_state: Pubkey, balance: bool) -> Result<CompoundPlan> {
+        let pool = self.get_pool_snapshot(pool_state)?;
+        let pool_data = &pool.pool_data;
+
+        let keys = PoolKeys::from_pool_state(&self.program.id(), pool_state, pool_data)
+            .owner_keys(&self.owner());
+        let token_0_amount = self.idle_balance(&pool_data.token_0_mint, &keys.creator_token_0)?;
+        let token_1_amount = self.idle_balance(&pool_data.token_1_mint, &keys.creator_token_1)?;
+
+        let swap = if balance {
+            balancing_swap(&pool, token_0_amount, token_1_amount)?
+        } else {
+            None
+        };
+
+        // Balances too small for a single LP token are left idle.
+        let (amount_0, amount_1, liquidity) =
+            balances_after_swap(&pool, token_0_amount, token_1_amount, swap);
+        let lp_amount = self
+            .calculate_lp_amount(amount_0, amount_1, &liquidity)
+            .unwrap_or(0);
+
+        Ok(CompoundPlan {
+            pool_state,
+            token_0_mint: pool_data.token_0_mint,
+            token_1_mint: pool_data.token_1_mint,
+            token_0_decimals: pool_data.mint_0_decimals,
+            token_1_decimals: pool_data.mint_1_decimals,
+            lp_decimals: pool_data.lp_mint_decimals,
+            token_0_amount,
+            token_1_amount,
+            swap,
+            lp_amount,
+        })
+    }
+
+    /// Deposits the owner's idle balances of the pool tokens, returning `None` if they are too
+    /// small for a single LP token.
+    ///
+    /// With `balance`, the balancing swap is sent first and the deposit is sized to the
+    /// balances it actually left. If the deposit then fails, the error is a
+    /// [`DepositAfterSwapError`] holding the sent swap.
+    pub fn compound(&self, pool_state: Pubkey, balance: bool) -> Result<Option<Compounding>> {
+        let plan = self.plan_compound(pool_state, balance)?;
+        if plan.lp_amount == 0 {
+            return Ok(None);
+        }
+
+        let Some(balancing) = plan.swap else {
+            let lp_amount = plan.lp_amount;
+            let deposit = self.deposit_idle_balances(pool_state, lp_amount)?;
+            return Ok(Some(Compounding {
+                plan,
+                swap: None,
+                deposit,
+                lp_amount,
+            }));
+        };
+
+        // A proposed swap has not run when the deposit is sized.
+        if self.multisig.is_some() {
+            return Err(anyhow!(
+                "balancing swaps cannot be compounded through multisig proposals"
+            ));
+        }
+        let swap = self
+            .swap(pool_state, balancing.input_mint, balancing.amount_in)
+            .context("failed to send balancing swap")?;
+        match self.deposit_after_swap(pool_state) {
+            Ok((deposit, lp_amount)) => Ok(Some(Compounding {
+                plan,
+                swap: Some(swap),
+                deposit,
+                lp_amount,
+            })),
+            Err(error) => Err(DepositAfterSwapError { swap, error }.into()),
+        }
+    }
+
+    /// Deposits the balances left by a balancing swap, re-sizing the deposit to them.
+    fn deposit_after_swap(&self, pool_state: Pubkey) -> Result<(SentTransaction, u64)> {
+        let lp_amount = self.plan_compound(pool_state, false)?.lp_amount;
+        if lp_amount == 0 {
+            return Err(anyhow!(
+                "balances after the balancing swap are too small to deposit"
+            ));
+        }
+        let deposit = self.deposit_idle_balances(pool_state, lp_amount)?;
+        Ok((deposit, lp_amount))
+    }
+
+    /// Deposits `lp_amount` LP tokens worth of the owner's balances of the pool tokens.
+    fn deposit_idle_balances(&self, pool_state: Pubkey, lp_amount: u64) -> Result<SentTransaction> {
+        let keys = self.get_pool_keys(pool_state)?;
+        let pool_data = self.get_pool_snapshot(pool_state)?.pool_data;
+        self.add_liquidity(
+            keys.pool_state,
+            keys.pool_authority,
+            keys.lp_mint,
+            pool_data.token_0_mint,
+            pool_data.token_1_mint,
+            keys.token_0_vault,
+            keys.token_1_vault,
+            keys.creator_token_0,
+            keys.creator_token_1,
+            keys.creator_lp_ata,
+            lp_amount,
+        )
+    }
+
+    /// Compounds the owner's idle balances into every pool where it holds LP tokens.
+    ///
+    /// A failing pool is reported in the summary and does not stop the others. Pools whose
+    /// balances are too small for a single LP token are left out.
+    pub fn compound_all(&self, balance: bool) -> Result<CompoundSummary> {
+        let mut summary = CompoundSummary::default();
+        for position in self.get_lp_positions()? {
+            let pool_state = position.pool_state;
+            match self.compound(pool_state, balance) {
+                Ok(compounding) => summary.compounded.extend(compounding),
+                Err(error) => summary.failed.push(CompoundFailure::new(pool_state, error)),
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Returns the owner's idle balance of a pool token.
+    fn idle_balance(&self, mint: &Pubkey, account: &Pubkey) -> Result<u64> {
+        if *mint != native_mint::id() {
+            return self.get_token_balance(account);
+        }
+        let lamports = self
+            .client_rpc
+            .get_balance(&self.owner())
+            .context("failed to get SOL balance")?;
+        Ok(lamports.saturating_sub(self.compound_sol_reserve))
+    }
+}
+
+/// Returns the swap bringing idle balances to the pool's ratio, if they are not at it.
+///
+/// Selling the excess token moves the balances and the reserves towards each other, so the
+/// smallest amount leaving the balances at or below the pool's ratio after the swap is found
+/// by binary search.
+fn balancing_swap(
+    pool: &PoolSnapshot,
+    token_0_amount: u64,
+    token_1_amount: u64,
+) -> Result<Option<BalancingSwap>> {
+    let liquidity = &pool.liquidity;
+    let (reserve_0, reserve_1) = (
+        u128::from(liquidity.token_0_amount),
+        u128::from(liquidity.token_1_amount),
+    );
+    if reserve_0 == 0 || reserve_1 == 0 {
+        return Err(anyhow!("pool has no liquidity"));
+    }
+
+    let (balance_0, balance_1) = (u128::from(token_0_amount), u128::from(token_1_amount));
+    let (direction, input_mint, excess, other, reserve_in, reserve_out) =
+        if balance_0 * reserve_1 > balance_1 * reserve_0 {
+            (
+                TradeDirection::ZeroForOne,
+                pool.pool_data.token_0_mint,
+                balance_0,
+                balance_1,
+                reserve_0,
+                reserve_1,
+            )
+        } else if balance_1 * reserve_0 > balance_0 * reserve_1 {
+            (
+                TradeDirection::OneForZero,
+                pool.pool_data.token_1_mint,
+                balance_1,
+                balance_0,
+                reserve_1,
+                reserve_0,
+            )
+        } else {
+            return Ok(None);
+        };
+
+    let quote_out = |amount_in: u64| -> Result<u64> {
+        if amount_in == 0 {
+            return Ok(0);
+        }
+        Ok(pool.quote(direction, amount_in)?.amount_out)
+    };
+    let in_excess = |amount_in: u64| -> Result<bool> {
+        let amount_in_u128 = u128::from(amount_in);
+        let amount_out = u128::from(quote_out(amount_in)?);
+        Ok((excess - amount_in_u128) * (reserve_out - amount_out)
+            > (other + amount_out) * (reserve_in + amount_in_u128))
+    };
+
+    let (mut low, mut high) = (0, u64::try_from(excess)?);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        if in_excess(middle)? {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+
+    let amount_out = quote_out(low)?;
+    if amount_out == 0 {
+        return Ok(None);
+    }
+    Ok(Some(BalancingSwap {
+        input_mint,
+        amount_in: low,
+        amount_out,
+    }))
+}
+
+/// Returns the balances and the pool liquidity once the balancing swap, if any, has moved both.
+fn balances_after_swap(
+    pool: &PoolSnapshot,
+    token_0_amount: u64,
+    token_1_amount: u64,
+    swap: Option<BalancingSwap>,
+) -> (u64, u64, PoolLiquidity) {
+    let liquidity = pool.liquidity;
+    match swap {
+        Some(swap) if swap.input_mint == pool.pool_data.token_0_mint => (
+            token_0_amount - swap.amount_in,
+            token_1_amount + swap.amount_out,
+            PoolLiquidity {
+                token_0_amount: liquidity.token_0_amount + swap.amount_in,
+                token_1_amount: liquidity.token_1_amount - swap.amount_out,
+                ..liquidity
+            },
+        ),
+        Some(swap) => (
+            token_0_amount + swap.amount_out,
+            token_1_amount - swap.amount_in,
+            PoolLiquidity {
+                token_0_amount: liquidity.token_0_amount - swap.amount_out,
+                token_1_amount: liquidity.token_1_amount + swap.amount_in,
+                ..liquidity
+            },
+        ),
+        None => (token_0_amount, token_1_amount, liquidity),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anchor_client::solana_sdk::signature::Signature;
+    use raydium_cp_swap::states::{AmmConfig, PoolState};
+
+    use super::*;
+
+    /// Pool with a 0.25% trade fee and the given reserves.
+    fn pool(reserve_0: u64, reserve_1: u64) -> PoolSnapshot {
+        let mut pool_data = PoolState::default();
+        pool_data.token_0_mint = Pubkey::new_unique();
+        pool_data.token_1_mint = Pubkey::new_unique();
+        pool_data.lp_supply = 1_000_000_000;
+        let amm_config = AmmConfig {
+            trade_fee_rate: 2_500,
+            ..AmmConfig::default()
+        };
+        PoolSnapshot::new(
+            0,
+            Pubkey::new_unique(),
+            pool_data,
+            amm_config,
+            reserve_0,
+            reserve_1,
+        )
+    }
+
+    /// Returns how far the balances exceed the pool's ratio on the swap's input side, once the
+    /// swap has moved both.
+    fn excess(pool: &PoolSnapshot, balances: (u64, u64), swap: BalancingSwap) -> i128 {
+        let (amount_0, amount_1, liquidity) =
+            balances_after_swap(pool, balances.0, balances.1, Some(swap));
+        let (amount_0, amount_1) = (i128::from(amount_0), i128::from(amount_1));
+        let (reserve_0, reserve_1) = (
+            i128::from(liquidity.token_0_amount),
+            i128::from(liquidity.token_1_amount),
+        );
+        if swap.input_mint == pool.pool_data.token_0_mint {
+            amount_0 * reserve_1 - amount_1 * reserve_0
+        } else {
+            amount_1 * reserve_0 - amount_0 * reserve_1
+        }
+    }
+
+    /// Checks that the swap brings the balances to the pool's ratio and that selling one token
+    /// less would leave them in excess.
+    fn assert_smallest_balancing_swap(
+        pool: &PoolSnapshot,
+        balances: (u64, u64),
+        swap: BalancingSwap,
+    ) {
+        let direction = if swap.input_mint == pool.pool_data.token_0_mint {
+            TradeDirection::ZeroForOne
+        } else {
+            TradeDirection::OneForZero
+        };
+        assert_eq!(
+            pool.quote(direction, swap.amount_in).unwrap().amount_out,
+            swap.amount_out
+        );
+        assert!(excess(pool, balances, swap) <= 0);
+
+        let smaller = BalancingSwap {
+            amount_in: swap.amount_in - 1,
+            amount_out: pool
+                .quote(direction, swap.amount_in - 1)
+                .unwrap()
+                .amount_out,
+            ..swap
+        };
+        assert!(excess(pool, balances, smaller) > 0);
+    }
+
+    #[test]
+    fn balanced_balances_need_no_swap() {
+        let pool = pool(1_000_000_000, 2_000_000_000);
+        assert!(balancing_swap(&pool, 1_000_000, 2_000_000)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn sells_excess_token_0() {
+        let pool = pool(1_000_000_000, 2_000_000_000);
+        let balances = (5_000_000, 1_000_000);
+
+        let swap = balancing_swap(&pool, balances.0, balances.1)
+            .unwrap()
+            .unwrap();
+        assert_eq!(swap.input_mint, pool.pool_data.token_0_mint);
+        assert_smallest_balancing_swap(&pool, balances, swap);
+
+        let (amount_0, amount_1, liquidity) =
+            balances_after_swap(&pool, balances.0, balances.1, Some(swap));
+        assert_eq!(amount_0, balances.0 - swap.amount_in);
+        assert_eq!(amount_1, balances.1 + swap.amount_out);
+        assert_eq!(
+            liquidity.token_0_amount,
+            pool.liquidity.token_0_amount + swap.amount_in
+        );
+        assert_eq!(
+            liquidity.token_1_amount,
+            pool.liquidity.token_1_amount - swap.amount_out
+        );
+    }
+
+    #[test]
+    fn sells_excess_token_1() {
+        let pool = pool(3_000_000_000, 1_000_000_000);
+        let balances = (0, 40_000_000);
+
+        let swap = balancing_swap(&pool, balances.0, balances.1)
+            .unwrap()
+            .unwrap();
+        assert_eq!(swap.input_mint, pool.pool_data.token_1_mint);
+        assert_smallest_balancing_swap(&pool, balances, swap);
+
+        let (amount_0, amount_1, liquidity) =
+            balances_after_swap(&pool, balances.0, balances.1, Some(swap));
+        assert_eq!(amount_0, swap.amount_out);
+        assert_eq!(amount_1, balances.1 - swap.amount_in);
+        assert_eq!(
+            liquidity.token_1_amount,
+            pool.liquidity.token_1_amount + swap.amount_in
+        );
+    }
+
+    #[test]
+    fn dust_excess_is_not_swapped() {
+        // Token 0 is worth a millionth of token 1, so the whole excess buys nothing.
+        let pool = pool(1_000_000_000_000, 1_000_000);
+        assert!(balancing_swap(&pool, 3, 0).unwrap().is_none());
+    }
+
+    #[test]
+    fn pool_without_liquidity_cannot_be_balanced() {
+        let pool = pool(0, 1_000_000_000);
+        assert!(balancing_swap(&pool, 1_000, 0).is_err());
+    }
+
+    fn sent_swap() -> SentTransaction {
+        SentTransaction {
+            signature: Signature::new_unique(),
+            events: Vec::new(),
+            proposal: None,
+        }
+    }
+
+    #[test]
+    fn failure_keeps_swap_sent_before_deposit() {
+        let swap = sent_swap();
+        let signature = swap.signature;
+        let error = anyhow::Error::from(DepositAfterSwapError {
+            swap,
+            error: anyhow!("insufficient balance"),
+        });
+        assert!(error.to_string().contains(&signature.to_string()));
+        assert!(format!("{error:#}").contains("insufficient balance"));
+
+        let failure = CompoundFailure::new(Pubkey::new_unique(), error);
+        assert_eq!(failure.swap.map(|swap| swap.signature), Some(signature));
+        assert_eq!(failure.error.to_string(), "insufficient balance");
+    }
+
+    #[test]
+    fn failure_without_swap() {
+        let failure = CompoundFailure::new(
+            Pubkey::new_unique(),
+            anyhow!("pool has no liquidity").context("failed to plan compounding"),
+        );
+        assert!(failure.swap.is_none());
+        assert_eq!(
+            format!("{:#}", failure.error),
+            "failed to plan compounding: pool has no liquidity"
+        );
+    }
+}
@@ -0,0 +1,436 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anchor_client::{
+    solana_sdk::{
+        commitment_config::{CommitmentConfig, CommitmentLevel},
+        compute_budget::ComputeBudgetInstruction,
+        pubkey::Pubkey,
+    },
+    Cluster,
+};
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use solana_program::instruction::Instruction;
+
+use crate::{
+    ledger::serde_display, signer::SignerSource, RaydiumIntegration, DEFAULT_SLIPPAGE_BPS,
+};
+
+/// Configuration file used when no path is given.
+pub const DEFAULT_CONFIG_FILE: &str = "./raydium.toml";
+
+/// Maximum number of accounts accepted by `getRecentPrioritizationFees`.
+const MAX_PRIORITIZATION_FEE_ACCOUNTS: usize = 128;
+
+/// Policy for the compute unit price added to every sent transaction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(tag = "policy", rename_all = "snake_case")]
+pub enum PriorityFee {
+    /// No compute unit price is set.
+    #[default]
+    None,
+    /// Fixed compute unit price in micro-lamports.
+    Fixed { micro_lamports: u64 },
+    /// Percentile of the fees recently paid to write the same accounts, capped at a maximum.
+    Recent {
+        percentile: u8,
+        max_micro_lamports: u64,
+    },
+}
+
+/// Program and fee receiver accounts the integration talks to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProgramIds {
+    /// Raydium CP-AMM program.
+    #[serde(with = "serde_display")]
+    pub raydium_cp_swap: Pubkey,
+    /// Account receiving the pool creation fee.
+    #[serde(with = "serde_display")]
+    pub create_pool_fee_receiver: Pubkey,
+}
+
+impl Default for ProgramIds {
+    fn default() -> Self {
+        Self {
+            raydium_cp_swap: raydium_cp_swap::id(),
+            create_pool_fee_receiver: raydium_cp_swap::create_pool_fee_reveiver::id(),
+        }
+    }
+}
+
+/// Named profile from the configuration file; unset values keep the previous layer's value.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    /// Cluster name (devnet, mainnet, localnet) or RPC URL.
+    pub url: Option<String>,
+    /// Websocket URL, derived from the RPC URL if not set.
+    pub ws_url: Option<String>,
+    /// Commitment used for queries and confirmations.
+    pub commitment: Option<CommitmentLevel>,
+    /// Payer signer: keypair file path, `env:VAR`, `keystore:PATH` or signing service URL.
+    pub keypair: Option<SignerSource>,
+    /// Slippage applied to deposits, withdrawals and swaps in basis points.
+    pub slippage_bps: Option<u64>,
+    /// Compute unit price policy for sent transactions.
+    pub priority_fee: Option<PriorityFee>,
+    /// Program and fee receiver accounts.
+    pub programs: Option<ProgramIds>,
+}
+
+impl Profile {
+    /// Reads overrides from the `RAYDIUM_*` environment variables.
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            url: env::var("RAYDIUM_URL").ok(),
+            ws_url: env::var("RAYDIUM_WS_URL").ok(),
+            commitment: env::var("RAYDIUM_COMMITMENT")
+                .ok()
+                .map(|value| CommitmentLevel::from_str(&value))
+                .transpose()
+                .map_err(|e| anyhow!("invalid RAYDIUM_COMMITMENT: {e}"))?,
+            keypair: env::var("RAYDIUM_KEYPAIR")
+                .ok()
+                .map(|value| value.parse())
+                .transpose()
+                .context("invalid RAYDIUM_KEYPAIR")?,
+            slippage_bps: env::var("RAYDIUM_SLIPPAGE_BPS")
+                .ok()
+                .map(|value| value.parse())
+                .transpose()
+                .context("invalid RAYDIUM_SLIPPAGE_BPS")?,
+            priority_fee: env::var("RAYDIUM_PRIORITY_FEE")
+                .ok()
+                .map(|value| {
+                    value
+                        .parse()
+                        .map(|micro_lamports| PriorityFee::Fixed { micro_lamports })
+                })
+                .transpose()
+                .context("invalid RAYDIUM_PRIORITY_FEE")?,
+            programs: None,
+        })
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    /// Profile used when none is selected explicitly.
+    pub default_profile: Option<String>,
+    /// Profiles by name.
+    #[serde(default)]
+    pub profiles: HashMap<String, Profile>,
+}
+
+impl ConfigFile {
+    /// Reads and parses a TOML configuration file.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let data = fs::read_to_string(path).context("failed to read config file")?;
+        toml::from_str(&data).context("failed to parse config file")
+    }
+}
+
+/// Resolved settings used to create the integration.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Cluster RPC URL and the websocket URL derived from it.
+    pub cluster: Cluster,
+    /// Websocket URL replacing the one derived from the cluster.
+    pub ws_url: Option<String>,
+    /// Commitment used for queries and confirmations.
+    pub commitment: CommitmentConfig,
+    /// Source of the payer signer.
+    pub keypair: SignerSource,
+    /// Slippage applied to deposits, withdrawals and swaps in basis points.
+    pub slippage_bps: u64,
+    /// Compute unit price policy for sent transactions.
+    pub priority_fee: PriorityFee,
+    /// Program and fee receiver accounts.
+    pub program_ids: ProgramIds,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            cluster: Cluster::Devnet,
+            ws_url: None,
+            commitment: CommitmentConfig::confirmed(),
+            keypair: SignerSource::File(PathBuf::from("./devnet.json")),
+            slippage_bps: DEFAULT_SLIPPAGE_BPS,
+            priority_fee: PriorityFee::None,
+            program_ids: ProgramIds::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Resolves the settings from a profile of the configuration file and the environment.
+    ///
+    /// The profile is taken from the argument, `RAYDIUM_PROFILE` or the file's default profile.
+    /// A missing file at the default path is treated as empty.
+    pub fn load(path: Option<&Path>, profile: Option<&str>) -> Result<Self> {
+        let file = match path {
+            Some(path) => ConfigFile::open(path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                ConfigFile::open(DEFAULT_CONFIG_FILE)?
+            }
+            None => ConfigFile::default(),
+        };
+
+        let profile_name = profile
+            .map(str::to_string)
+            .or_else(|| env::var("RAYDIUM_PROFILE").ok())
+            .or(file.default_profile.clone());
+
+        let mut config = Config::default();
+        if let Some(name) = profile_name {
+            let profile = file
+                .profiles
+                .get(&name)
+                .ok_or(anyhow!("profile {name} not found in config file"))?;
+            config.apply(profile)?;
+        }
+        config.apply(&Profile::from_env()?)?;
+
+        Ok(config)
+    }
+
+    /// Overrides the settings with the values set in the profile.
+    ///
+    /// The URL and websocket URL are kept apart, so a websocket URL set by an earlier layer
+    /// survives a URL set by a later one. `resolved_cluster` combines them once every layer is
+    /// applied.
+    pub fn apply(&mut self, profile: &Profile) -> Result<()> {
+        if let Some(url) = &profile.url {
+            self.cluster = Cluster::from_str(url)?;
+        }
+        if let Some(ws_url) = &profile.ws_url {
+            self.ws_url = Some(ws_url.clone());
+        }
+        if let Some(commitment) = profile.commitment {
+            self.commitment = CommitmentConfig { commitment };
+        }
+        if let Some(keypair) = &profile.keypair {
+            self.keypair = keypair.clone();
+        }
+        if let Some(slippage_bps) = profile.slippage_bps {
+            self.slippage_bps = slippage_bps;
+        }
+        if let Some(priority_fee) = profile.priority_fee {
+            self.priority_fee = priority_fee;
+        }
+        if let Some(program_ids) = profile.programs {
+            self.program_ids = program_ids;
+        }
+        Ok(())
+    }
+
+    /// Returns the cluster with the websocket URL, if set, replacing the derived one.
+    pub fn resolved_cluster(&self) -> Cluster {
+        match &self.ws_url {
+            Some(ws_url) => Cluster::Custom(self.cluster.url().to_string(), ws_url.clone()),
+            None => self.cluster.clone(),
+        }
+    }
+}
+
+impl RaydiumIntegration {
+    /// Sets the compute unit price policy for sent transactions.
+    pub fn with_priority_fee(mut self, priority_fee: PriorityFee) -> Self {
+        self.priority_fee = priority_fee;
+        self
+    }
+
+    /// Creates the compute budget instructions for the priority fee policy.
+    pub(crate) fn priority_fee_instructions(
+        &self,
+        instructions: &[Instruction],
+    ) -> Result<Vec<Instruction>> {
+        let recent_fees = match self.priority_fee.recent_fee_accounts(instructions) {
+            Some(accounts) => self
+                .client_rpc
+                .get_recent_prioritization_fees(&accounts)
+                .context("failed to get recent prioritization fees")?
+                .into_iter()
+                .map(|fee| fee.prioritization_fee)
+                .collect(),
+            None => Vec::new(),
+        };
+
+        Ok(self.priority_fee.compute_budget_instructions(recent_fees))
+    }
+}
+
+impl PriorityFee {
+    /// Returns the accounts whose recent prioritization fees the policy is based on, or `None`
+    /// if it does not depend on recent fees.
+    pub(crate) fn recent_fee_accounts(&self, instructions: &[Instruction]) -> Option<Vec<Pubkey>> {
+        match self {
+            Self::Recent { .. } => Some(prioritization_fee_accounts(instructions)),
+            Self::None | Self::Fixed { .. } => None,
+        }
+    }
+
+    /// Creates the compute budget instructions for the policy from the recent prioritization
+    /// fees fetched for `recent_fee_accounts`.
+    pub(crate) fn compute_budget_instructions(&self, recent_fees: Vec<u64>) -> Vec<Instruction> {
+        let micro_lamports = match *self {
+            Self::None => return Vec::new(),
+            Self::Fixed { micro_lamports } => micro_lamports,
+            Self::Recent {
+                percentile,
+                max_micro_lamports,
+            } => percentile_fee(recent_fees, percentile).min(max_micro_lamports),
+        };
+
+        vec![ComputeBudgetInstruction::set_compute_unit_price(
+            micro_lamports,
+        )]
+    }
+}
+
+/// Returns the accounts whose recent fees set the price, since fees are local to the accounts a
+/// transaction writes to.
+fn prioritization_fee_accounts(instructions: &[Instruction]) -> Vec<Pubkey> {
+    let mut writable: Vec<Pubkey> = instructions
+        .iter()
+        .flat_map(|ix| ix.accounts.iter())
+        .filter(|meta| meta.is_writable)
+        .map(|meta| meta.pubkey)
+        .collect();
+    writable.sort();
+    writable.dedup();
+    writable.truncate(MAX_PRIORITIZATION_FEE_ACCOUNTS);
+    writable
+}
+
+/// Returns the percentile of recent prioritization fees, or zero if there are none.
+fn percentile_fee(mut fees: Vec<u64>, percentile: u8) -> u64 {
+    fees.sort_unstable();
+    match fees.len() {
+        0 => 0,
+        len => fees[(len - 1) * usize::from(percentile.min(100)) / 100],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Applies the layers in the order `Config::load` and the command line do.
+    fn layered(file: &str, env: Profile, cli: Profile) -> Config {
+        let file: ConfigFile = toml::from_str(file).unwrap();
+        let mut config = Config::default();
+        config.apply(&file.profiles["main"]).unwrap();
+        config.apply(&env).unwrap();
+        config.apply(&cli).unwrap();
+        config
+    }
+
+    #[test]
+    fn later_layers_override_earlier_ones() {
+        let config = layered(
+            r#"
+            [profiles.main]
+            url = "mainnet"
+            commitment = "finalized"
+            slippage_bps = 100
+            priority_fee = { policy = "fixed", micro_lamports = 1000 }
+            "#,
+            Profile {
+                slippage_bps: Some(50),
+                priority_fee: Some(PriorityFee::Fixed {
+                    micro_lamports: 2_000,
+                }),
+                ..Profile::default()
+            },
+            Profile {
+                slippage_bps: Some(25),
+                ..Profile::default()
+            },
+        );
+
+        assert_eq!(config.cluster.url(), Cluster::Mainnet.url());
+        assert_eq!(config.commitment, CommitmentConfig::finalized());
+        assert_eq!(config.slippage_bps, 25);
+        assert_eq!(
+            config.priority_fee,
+            PriorityFee::Fixed {
+                micro_lamports: 2_000
+            }
+        );
+    }
+
+    #[test]
+    fn file_ws_url_survives_env_url() {
+        let config = layered(
+            r#"
+            [profiles.main]
+            url = "mainnet"
+            ws_url = "wss://ws.example.com"
+            "#,
+            Profile {
+                url: Some("https://rpc.example.com".to_string()),
+                ..Profile::default()
+            },
+            Profile::default(),
+        );
+
+        let cluster = config.resolved_cluster();
+        assert_eq!(cluster.url(), "https://rpc.example.com");
+        assert_eq!(cluster.ws_url(), "wss://ws.example.com");
+    }
+
+    #[test]
+    fn cli_ws_url_applies_to_file_url() {
+        let config = layered(
+            r#"
+            [profiles.main]
+            url = "https://rpc.example.com"
+            "#,
+            Profile::default(),
+            Profile {
+                ws_url: Some("wss://ws.example.com".to_string()),
+                ..Profile::default()
+            },
+        );
+
+        let cluster = config.resolved_cluster();
+        assert_eq!(cluster.url(), "https://rpc.example.com");
+        assert_eq!(cluster.ws_url(), "wss://ws.example.com");
+    }
+
+    #[test]
+    fn ws_url_is_derived_without_override() {
+        let config = layered(
+            r#"
+            [profiles.main]
+            url = "https://rpc.example.com"
+            "#,
+            Profile::default(),
+            Profile::default(),
+        );
+
+        let derived = Cluster::from_str("https://rpc.example.com").unwrap();
+        assert_eq!(config.resolved_cluster().ws_url(), derived.ws_url());
+    }
+
+    #[test]
+    fn percentile_of_recent_fees() {
+        let fees = vec![5, 1, 3, 2, 4];
+        assert_eq!(percentile_fee(fees.clone(), 0), 1);
+        // The index is rounded down: (5 - 1) * 50 / 100 = 2 and (5 - 1) * 90 / 100 = 3.
+        assert_eq!(percentile_fee(fees.clone(), 50), 3);
+        assert_eq!(percentile_fee(fees.clone(), 90), 4);
+        assert_eq!(percentile_fee(fees.clone(), 100), 5);
+        assert_eq!(percentile_fee(fees, 200), 5);
+        assert_eq!(percentile_fee(Vec::new(), 75), 0);
+    }
+}
@@ -0,0 +1,344 @@
+use anchor_client::{
+    anchor_lang::Discriminator,
+    solana_sdk::{pubkey::Pubkey, signature::Signature},
+};
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
+
+use crate::{multisig::MultisigProposal, RaydiumIntegration};
+
+/// Prefix of the log lines carrying Anchor event data.
+const PROGRAM_DATA_PREFIX: &str = "Program data: ";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwapEvent {
+    /// Pool's state account.
+    pub pool_id: Pubkey,
+    /// Input vault balance before the swap.
+    pub input_vault_before: u64,
+    /// Output vault balance before the swap.
+    pub output_vault_before: u64,
+    /// Amount of input tokens transferred into the pool, excluding transfer fees.
+    pub input_amount: u64,
+    /// Amount of output tokens transferred out of the pool, excluding transfer fees.
+    pub output_amount: u64,
+    /// Token-2022 transfer fee charged on the input amount.
+    pub input_transfer_fee: u64,
+    /// Token-2022 transfer fee charged on the output amount.
+    pub output_transfer_fee: u64,
+    /// Whether the swap had an exact input amount.
+    pub base_input: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LpChangeType {
+    Deposit,
+    Withdraw,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LpChangeEvent {
+    /// Pool's state account.
+    pub pool_id: Pubkey,
+    /// LP supply before the change.
+    pub lp_amount_before: u64,
+    /// Token 0 vault balance before the change.
+    pub token_0_vault_before: u64,
+    /// Token 1 vault balance before the change.
+    pub token_1_vault_before: u64,
+    /// Amount of token 0 deposited or withdrawn, excluding transfer fees.
+    pub token_0_amount: u64,
+    /// Amount of token 1 deposited or withdrawn, excluding transfer fees.
+    pub token_1_amount: u64,
+    /// Token-2022 transfer fee charged on token 0.
+    pub token_0_transfer_fee: u64,
+    /// Token-2022 transfer fee charged on token 1.
+    pub token_1_transfer_fee: u64,
+    /// Whether liquidity was deposited or withdrawn.
+    pub change_type: LpChangeType,
+}
+
+/// Event emitted by the Raydium CP-AMM program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolEvent {
+    Swap(SwapEvent),
+    LpChange(LpChangeEvent),
+}
+
+impl PoolEvent {
+    /// Decodes event data, returning `None` for events of other types.
+    pub fn decode(data: &[u8]) -> Result<Option<Self>> {
+        let Some((discriminator, data)) = data.split_first_chunk::<8>() else {
+            return Ok(None);
+        };
+        let mut reader = Reader(data);
+
+        let event = if *discriminator == raydium_cp_swap::states::SwapEvent::DISCRIMINATOR {
+            PoolEvent::Swap(SwapEvent {
+                pool_id: reader.pubkey()?,
+                input_vault_before: reader.u64()?,
+                output_vault_before: reader.u64()?,
+                input_amount: reader.u64()?,
+                output_amount: reader.u64()?,
+                input_transfer_fee: reader.u64()?,
+                output_transfer_fee: reader.u64()?,
+                base_input: reader.u8()? != 0,
+            })
+        } else if *discriminator == raydium_cp_swap::states::LpChangeEvent::DISCRIMINATOR {
+            PoolEvent::LpChange(LpChangeEvent {
+                pool_id: reader.pubkey()?,
+                lp_amount_before: reader.u64()?,
+                token_0_vault_before: reader.u64()?,
+                token_1_vault_before: reader.u64()?,
+                token_0_amount: reader.u64()?,
+                token_1_amount: reader.u64()?,
+                token_0_transfer_fee: reader.u64()?,
+                token_1_transfer_fee: reader.u64()?,
+                change_type: match reader.u8()? {
+                    0 => LpChangeType::Deposit,
+                    1 => LpChangeType::Withdraw,
+                    other => return Err(anyhow!("unknown lp change type {other}")),
+                },
+            })
+        } else {
+            return Ok(None);
+        };
+
+        Ok(Some(event))
+    }
+}
+
+/// Transaction sent and confirmed by the integration.
+#[derive(Debug)]
+pub struct SentTransaction {
+    /// Signature of the transaction.
+    pub signature: Signature,
+    /// Events emitted by the Raydium CP-AMM program, in order.
+    pub events: Vec<PoolEvent>,
+    /// Multisig proposal created instead of running the operation, if the integration has a
+    /// multisig attached.
+    pub proposal: Option<MultisigProposal>,
+}
+
+impl RaydiumIntegration {
+    /// Decodes the Raydium CP-AMM events from a confirmed transaction's log messages.
+    pub fn get_transaction_events(
+        &self,
+        transaction: &EncodedConfirmedTransactionWithStatusMeta,
+    ) -> Result<Vec<PoolEvent>> {
+        transaction_events(&self.program.id(), transaction)
+    }
+}
+
+/// Decodes the events emitted by the program from a confirmed transaction's log messages.
+pub(crate) fn transaction_events(
+    program_id: &Pubkey,
+    transaction: &EncodedConfirmedTransactionWithStatusMeta,
+) -> Result<Vec<PoolEvent>> {
+    let meta = transaction
+        .transaction
+        .meta
+        .as_ref()
+        .ok_or(anyhow!("transaction has no status meta"))?;
+    let logs: Option<Vec<String>> = meta.log_messages.clone().into();
+
+    parse_events(program_id, &logs.unwrap_or_default())
+}
+
+/// Decodes the events emitted directly by the program from transaction log messages.
+///
+/// Events logged by other programs, including programs invoked by this one, are ignored.
+pub fn parse_events(program_id: &Pubkey, logs: &[String]) -> Result<Vec<PoolEvent>> {
+    let program_id = program_id.to_string();
+    let mut invocations: Vec<&str> = Vec::new();
+    let mut events = Vec::new();
+
+    for line in logs {
+        if let Some(data) = line.strip_prefix(PROGRAM_DATA_PREFIX) {
+            if invocations.last() != Some(&program_id.as_str()) {
+                continue;
+            }
+            let data = STANDARD
+                .decode(data)
+                .map_err(|e| anyhow!("failed to decode event data: {e}"))?;
+            events.extend(PoolEvent::decode(&data)?);
+            continue;
+        }
+
+        // Track the invocation stack from "Program <id> invoke [n]" and "Program <id> success" lines.
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next(), words.next()) {
+            (Some("Program"), Some(id), Some("invoke")) => invocations.push(id),
+            (Some("Program"), Some(_), Some("success" | "failed:")) => {
+                invocations.pop();
+            }
+            _ => {}
+        }
+    }
+
+    Ok(events)
+}
+
+/// Reads little-endian fields from Borsh-encoded event data.
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let (bytes, rest) = self
+            .0
+            .split_first_chunk::<N>()
+            .ok_or(anyhow!("event data too short"))?;
+        self.0 = rest;
+        Ok(*bytes)
+    }
+
+    fn pubkey(&mut self) -> Result<Pubkey> {
+        Ok(Pubkey::new_from_array(self.take()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take::<1>()?[0])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Swap of 100 tokens for 190 in the pool with key bytes 1..=32.
+    const SWAP_DATA: &str = "QMbN6CYIceIBAgMEBQYHCAkKCwwNDg8QERITFBUWFxgZGhscHR4fIOgDAAAAAAAA0AcAAAAAAABkAAAAAAAAAL4AAAAAAAAAAQAAAAAAAAACAAAAAAAAAAE=";
+    /// Withdrawal of 50 and 100 tokens from the same pool.
+    const LP_CHANGE_DATA: &str = "eaPNyTnadTwBAgMEBQYHCAkKCwwNDg8QERITFBUWFxgZGhscHR4fIPQBAAAAAAAA6AMAAAAAAADQBwAAAAAAADIAAAAAAAAAZAAAAAAAAAAAAAAAAAAAAAMAAAAAAAAAAQ==";
+    /// Swap event cut off after the pool key.
+    const TRUNCATED_DATA: &str = "QMbN6CYIceIBAgMEBQYHCAkKCwwNDg8QERITFBUWFxgZGhscHR4fIA==";
+    /// Event of another type with the pool key as its only field.
+    const UNKNOWN_DATA: &str = "oOKFJfo3600BAgMEBQYHCAkKCwwNDg8QERITFBUWFxgZGhscHR4fIA==";
+
+    const OTHER_PROGRAM: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+
+    fn pool_id() -> Pubkey {
+        Pubkey::new_from_array(std::array::from_fn(|i| i as u8 + 1))
+    }
+
+    fn swap_event() -> PoolEvent {
+        PoolEvent::Swap(SwapEvent {
+            pool_id: pool_id(),
+            input_vault_before: 1000,
+            output_vault_before: 2000,
+            input_amount: 100,
+            output_amount: 190,
+            input_transfer_fee: 1,
+            output_transfer_fee: 2,
+            base_input: true,
+        })
+    }
+
+    fn lp_change_event() -> PoolEvent {
+        PoolEvent::LpChange(LpChangeEvent {
+            pool_id: pool_id(),
+            lp_amount_before: 500,
+            token_0_vault_before: 1000,
+            token_1_vault_before: 2000,
+            token_0_amount: 50,
+            token_1_amount: 100,
+            token_0_transfer_fee: 0,
+            token_1_transfer_fee: 3,
+            change_type: LpChangeType::Withdraw,
+        })
+    }
+
+    fn invoke(program: &str, depth: usize) -> String {
+        format!("Program {program} invoke [{depth}]")
+    }
+
+    fn success(program: &str) -> String {
+        format!("Program {program} success")
+    }
+
+    fn data(data: &str) -> String {
+        format!("{PROGRAM_DATA_PREFIX}{data}")
+    }
+
+    #[test]
+    fn decodes_events_of_the_program() {
+        let program = raydium_cp_swap::id().to_string();
+        let logs = vec![
+            invoke(&program, 1),
+            "Program log: Instruction: SwapBaseInput".to_string(),
+            data(SWAP_DATA),
+            format!("Program {program} consumed 41000 of 200000 compute units"),
+            success(&program),
+            invoke(&program, 1),
+            "Program log: Instruction: Withdraw".to_string(),
+            data(LP_CHANGE_DATA),
+            success(&program),
+        ];
+
+        let events = parse_events(&raydium_cp_swap::id(), &logs).unwrap();
+        assert_eq!(events, vec![swap_event(), lp_change_event()]);
+    }
+
+    #[test]
+    fn follows_nested_invocations() {
+        let program = raydium_cp_swap::id().to_string();
+        let logs = vec![
+            // The program invoked through CPI by a router still emits its own events.
+            invoke(OTHER_PROGRAM, 1),
+            invoke(&program, 2),
+            // Data logged by a program the pool invokes is not the pool's.
+            invoke(OTHER_PROGRAM, 3),
+            data(LP_CHANGE_DATA),
+            success(OTHER_PROGRAM),
+            data(SWAP_DATA),
+            success(&program),
+            // Back in the router after the pool returned.
+            data(SWAP_DATA),
+            success(OTHER_PROGRAM),
+        ];
+
+        let events = parse_events(&raydium_cp_swap::id(), &logs).unwrap();
+        assert_eq!(events, vec![swap_event()]);
+    }
+
+    #[test]
+    fn ignores_data_of_other_programs() {
+        let logs = vec![
+            invoke(OTHER_PROGRAM, 1),
+            data(SWAP_DATA),
+            format!("Program {OTHER_PROGRAM} failed: custom program error: 0x1"),
+        ];
+
+        let events = parse_events(&raydium_cp_swap::id(), &logs).unwrap();
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn skips_unknown_discriminators() {
+        let program = raydium_cp_swap::id().to_string();
+        let logs = vec![
+            invoke(&program, 1),
+            data(UNKNOWN_DATA),
+            data(SWAP_DATA),
+            success(&program),
+        ];
+
+        let events = parse_events(&raydium_cp_swap::id(), &logs).unwrap();
+        assert_eq!(events, vec![swap_event()]);
+        assert_eq!(PoolEvent::decode(&[1, 2, 3]).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_truncated_or_invalid_data() {
+        let program = raydium_cp_swap::id().to_string();
+        let truncated = vec![invoke(&program, 1), data(TRUNCATED_DATA), success(&program)];
+        assert!(parse_events(&raydium_cp_swap::id(), &truncated).is_err());
+
+        let invalid = vec![invoke(&program, 1), data("not base64!"), success(&program)];
+        assert!(parse_events(&raydium_cp_swap::id(), &invalid).is_err());
+    }
+}
@@ -0,0 +1,544 @@
+use std::str::FromStr;
+
+use anchor_client::{
+    anchor_lang::{AnchorDeserialize, Discriminator},
+    solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config,
+    solana_sdk::{bs58, pubkey::Pubkey, signature::Signature},
+};
+use anyhow::{anyhow, Context, Result};
+use raydium_cp_swap::{
+    curve::Fees,
+    instruction,
+    states::{AmmConfig, PoolState},
+};
+use serde::Serialize;
+use solana_transaction_status::{
+    EncodedConfirmedTransactionWithStatusMeta, UiInnerInstructions, UiInstruction,
+    UiLoadedAddresses,
+};
+
+use crate::{
+    events::{LpChangeEvent, LpChangeType, PoolEvent, SwapEvent},
+    store::{OperationKind, PoolActivity, QueuedSignature},
+    RaydiumIntegration,
+};
+
+/// Number of signatures requested per `getSignaturesForAddress` page (the RPC maximum).
+const SIGNATURES_PAGE_SIZE: usize = 1000;
+
+/// Account index of the pool state in initialize and swap instructions.
+const SWAP_POOL_STATE_INDEX: usize = 3;
+/// Account index of the pool state in deposit and withdraw instructions.
+const LP_POOL_STATE_INDEX: usize = 2;
+/// Account index of the input vault in swap instructions.
+const SWAP_INPUT_VAULT_INDEX: usize = 6;
+
+/// Outcome of an indexing run.
+#[derive(Debug, Default)]
+pub struct IndexSummary {
+    /// Number of transactions processed, including failed ones that were skipped.
+    pub transactions: usize,
+    /// Number of activity records written to the store.
+    pub activities: usize,
+    /// Number of transactions that could not be decoded, whose activity is missing from the
+    /// store.
+    pub skipped: usize,
+    /// Last transaction processed, from which the next run resumes.
+    pub last_signature: Option<Signature>,
+    /// Whether transactions newer than the last one processed remain to be indexed.
+    pub has_more: bool,
+}
+
+/// Pool volume, fees and reserves over one interval of the timeline.
+#[derive(Debug, Clone, Serialize)]
+pub struct TimelinePoint {
+    /// Unix timestamp at which the interval starts.
+    pub start: i64,
+    /// Number of swaps in the interval.
+    pub swaps: usize,
+    /// Amount of token 0 swapped into or out of the pool.
+    pub token_0_volume: u64,
+    /// Amount of token 1 swapped into or out of the pool.
+    pub token_1_volume: u64,
+    /// Trade fees charged in token 0.
+    pub token_0_fees: u64,
+    /// Trade fees charged in token 1.
+    pub token_1_fees: u64,
+    /// Token 0 vault balance at the end of the interval.
+    pub token_0_reserve: u64,
+    /// Token 1 vault balance at the end of the interval.
+    pub token_1_reserve: u64,
+}
+
+/// Program instruction with its accounts resolved.
+struct ProgramInstruction {
+    accounts: Vec<Pubkey>,
+    data: Vec<u8>,
+}
+
+/// Pool accounts and fee rate needed to normalize the pool's instructions.
+struct IndexedPool {
+    pool_state: Pubkey,
+    token_0_vault: Pubkey,
+    trade_fee_rate: u64,
+}
+
+impl RaydiumIntegration {
+    /// Backfills a pool's history into the attached store, oldest transaction first.
+    ///
+    /// Resumes after the last transaction indexed for the pool. At most `limit` transactions
+    /// are processed if given, so long histories can be indexed in several runs.
+    pub fn index_pool_history(
+        &self,
+        pool_state: Pubkey,
+        limit: Option<usize>,
+    ) -> Result<IndexSummary> {
+        let store = self
+            .store
+            .as_ref()
+            .ok_or(anyhow!("indexing requires an operation store"))?;
+
+        let pool_data = self
+            .program
+            .account::<PoolState>(pool_state)
+            .context("failed to fetch pool state")?;
+        let amm_config = self
+            .program
+            .account::<AmmConfig>(pool_data.amm_config)
+            .context("failed to fetch amm config")?;
+        let pool = IndexedPool {
+            pool_state,
+            token_0_vault: pool_data.token_0_vault,
+            trade_fee_rate: amm_config.trade_fee_rate,
+        };
+
+        // Signatures are returned newest first, so the ones newer than any already known are
+        // queued once and then processed oldest first, over as many runs as `limit` requires.
+        let until = store.get_newest_known_signature(pool_state)?;
+        let mut before = None;
+        let mut done = false;
+        store.queue_signatures(pool_state, || {
+            if done {
+                return Ok(None);
+            }
+            let page = self
+                .client_rpc
+                .get_signatures_for_address_with_config(
+                    &pool_state,
+                    GetConfirmedSignaturesForAddress2Config {
+                        before,
+                        until,
+                        limit: Some(SIGNATURES_PAGE_SIZE),
+                        commitment: Some(self.client_rpc.commitment()),
+                    },
+                )
+                .context("failed to get pool signatures")?;
+            done = page.len() < SIGNATURES_PAGE_SIZE;
+
+            let page = page
+                .iter()
+                .map(|status| {
+                    Ok(QueuedSignature {
+                        signature: Signature::from_str(&status.signature)?,
+                        slot: status.slot,
+                        failed: status.err.is_some(),
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            match page.last() {
+                Some(last) => before = Some(last.signature),
+                None => return Ok(None),
+            }
+            Ok(Some(page))
+        })?;
+
+        let mut summary = IndexSummary::default();
+        for queued in store.list_queued_signatures(pool_state, limit)? {
+            let signature = queued.signature;
+
+            // Failed transactions did not change the pool.
+            let activity = if queued.failed {
+                Vec::new()
+            } else {
+                // Decoding fails the same way on every run, so the transaction is skipped
+                // rather than blocking the cursor.
+                let transaction = self.get_confirmed_transaction(signature)?;
+                match self.decode_pool_activity(&pool, signature, &transaction) {
+                    Ok(activity) => activity,
+                    Err(e) => {
+                        log::warn!("Skipping transaction {signature}: {e:#}");
+                        summary.skipped += 1;
+                        Vec::new()
+                    }
+                }
+            };
+
+            store.insert_indexed_transaction(pool_state, signature, queued.slot, &activity)?;
+            summary.transactions += 1;
+            summary.activities += activity.len();
+            summary.last_signature = Some(signature);
+        }
+        summary.has_more = store.count_queued_signatures(pool_state)? > 0;
+
+        Ok(summary)
+    }
+
+    /// Aggregates the indexed activity of a pool into intervals of `interval` seconds.
+    ///
+    /// Intervals without activity are omitted; their reserves are those of the previous point.
+    pub fn get_pool_timeline(
+        &self,
+        pool_state: Pubkey,
+        interval: u64,
+        since: Option<i64>,
+    ) -> Result<Vec<TimelinePoint>> {
+        let store = self
+            .store
+            .as_ref()
+            .ok_or(anyhow!("the timeline requires an operation store"))?;
+        let interval = i64::try_from(interval)
+            .ok()
+            .filter(|interval| *interval > 0)
+            .ok_or(anyhow!("timeline interval must be positive"))?;
+
+        let mut timeline = Vec::new();
+        let mut current: Option<TimelinePoint> = None;
+        for item in store.list_pool_activity(pool_state, since)? {
+            let Some(block_time) = item.block_time else {
+                continue;
+            };
+            let start = block_time - block_time.rem_euclid(interval);
+
+            if current.as_ref().is_some_and(|point| point.start != start) {
+                timeline.extend(current.take());
+            }
+            let point = current.get_or_insert_with(|| TimelinePoint {
+                start,
+                swaps: 0,
+                token_0_volume: 0,
+                token_1_volume: 0,
+                token_0_fees: 0,
+                token_1_fees: 0,
+                token_0_reserve: 0,
+                token_1_reserve: 0,
+            });
+
+            if item.kind == OperationKind::Swap {
+                point.swaps += 1;
+            }
+            let sum = |total: u64, amount: u64| {
+                total
+                    .checked_add(amount)
+                    .ok_or(anyhow!("timeline totals overflow u64 in interval {start}"))
+            };
+            point.token_0_volume = sum(point.token_0_volume, item.token_0_volume)?;
+            point.token_1_volume = sum(point.token_1_volume, item.token_1_volume)?;
+            point.token_0_fees = sum(point.token_0_fees, item.token_0_fee)?;
+            point.token_1_fees = sum(point.token_1_fees, item.token_1_fee)?;
+            point.token_0_reserve = item.token_0_reserve;
+            point.token_1_reserve = item.token_1_reserve;
+        }
+        timeline.extend(current);
+
+        Ok(timeline)
+    }
+
+    /// Normalizes the pool's instructions in a confirmed transaction using the events they emitted.
+    fn decode_pool_activity(
+        &self,
+        pool: &IndexedPool,
+        signature: Signature,
+        transaction: &EncodedConfirmedTransactionWithStatusMeta,
+    ) -> Result<Vec<PoolActivity>> {
+        let events = self.get_transaction_events(transaction)?;
+        let mut swap_events = events.iter().filter_map(|event| match event {
+            PoolEvent::Swap(swap) if swap.pool_id == pool.pool_state => Some(swap),
+            _ => None,
+        });
+        let mut lp_events = events.iter().filter_map(|event| match event {
+            PoolEvent::LpChange(change) if change.pool_id == pool.pool_state => Some(change),
+            _ => None,
+        });
+
+        // An instruction that cannot be normalized is skipped rather than failing the backfill,
+        // so the cursor still moves past its transaction.
+        let mut activity = Vec::new();
+        for (position, ix) in program_instructions(&self.program.id(), transaction)?
+            .iter()
+            .enumerate()
+        {
+            match decode_instruction(pool, ix, &mut swap_events, &mut lp_events) {
+                Ok(Some(item)) => activity.push(item),
+                Ok(None) => {}
+                Err(e) => log::warn!(
+                    "Skipping program instruction {position} of transaction {signature}: {e:#}"
+                ),
+            }
+        }
+
+        for (index, item) in activity.iter_mut().enumerate() {
+            item.pool_state = pool.pool_state.to_string();
+            item.signature = signature.to_string();
+            item.instruction_index = u32::try_from(index)?;
+            item.slot = transaction.slot;
+            item.block_time = transaction.block_time;
+        }
+
+        Ok(activity)
+    }
+}
+
+/// Normalizes one program instruction on the pool using the next event of its kind, returning
+/// `None` for instructions on other pools or of other kinds.
+fn decode_instruction<'a>(
+    pool: &IndexedPool,
+    ix: &ProgramInstruction,
+    swap_events: &mut impl Iterator<Item = &'a SwapEvent>,
+    lp_events: &mut impl Iterator<Item = &'a LpChangeEvent>,
+) -> Result<Option<PoolActivity>> {
+    let Some((discriminator, mut args)) = ix.data.split_first_chunk::<8>() else {
+        return Ok(None);
+    };
+    let account = |index: usize| ix.accounts.get(index).copied();
+
+    let item = if *discriminator == instruction::Initialize::DISCRIMINATOR {
+        if account(SWAP_POOL_STATE_INDEX) != Some(pool.pool_state) {
+            return Ok(None);
+        }
+        initialize_activity(&instruction::Initialize::deserialize(&mut args)?)
+    } else if *discriminator == instruction::SwapBaseInput::DISCRIMINATOR
+        || *discriminator == instruction::SwapBaseOutput::DISCRIMINATOR
+    {
+        if account(SWAP_POOL_STATE_INDEX) != Some(pool.pool_state) {
+            return Ok(None);
+        }
+        let event = swap_events
+            .next()
+            .ok_or(anyhow!("swap instruction without a swap event"))?;
+        let zero_for_one = account(SWAP_INPUT_VAULT_INDEX) == Some(pool.token_0_vault);
+        swap_activity(pool, event, zero_for_one)?
+    } else if *discriminator == instruction::Deposit::DISCRIMINATOR {
+        if account(LP_POOL_STATE_INDEX) != Some(pool.pool_state) {
+            return Ok(None);
+        }
+        let args = instruction::Deposit::deserialize(&mut args)?;
+        let event = lp_events
+            .next()
+            .ok_or(anyhow!("deposit instruction without an LP change event"))?;
+        lp_change_activity(event, args.lp_token_amount)?
+    } else if *discriminator == instruction::Withdraw::DISCRIMINATOR {
+        if account(LP_POOL_STATE_INDEX) != Some(pool.pool_state) {
+            return Ok(None);
+        }
+        let args = instruction::Withdraw::deserialize(&mut args)?;
+        let event = lp_events
+            .next()
+            .ok_or(anyhow!("withdraw instruction without an LP change event"))?;
+        lp_change_activity(event, args.lp_token_amount)?
+    } else {
+        return Ok(None);
+    };
+
+    Ok(Some(item))
+}
+
+/// Returns the instructions of a program in a transaction in execution order, including CPIs.
+fn program_instructions(
+    program_id: &Pubkey,
+    transaction: &EncodedConfirmedTransactionWithStatusMeta,
+) -> Result<Vec<ProgramInstruction>> {
+    let versioned = transaction
+        .transaction
+        .transaction
+        .decode()
+        .ok_or(anyhow!("failed to decode transaction"))?;
+    let meta = transaction
+        .transaction
+        .meta
+        .as_ref()
+        .ok_or(anyhow!("transaction has no status meta"))?;
+
+    // Accounts loaded from lookup tables follow the static keys, writable ones first.
+    let mut account_keys = versioned.message.static_account_keys().to_vec();
+    let loaded: Option<UiLoadedAddresses> = meta.loaded_addresses.clone().into();
+    if let Some(loaded) = loaded {
+        for key in loaded.writable.iter().chain(&loaded.readonly) {
+            account_keys.push(Pubkey::from_str(key)?);
+        }
+    }
+    let inner: Option<Vec<UiInnerInstructions>> = meta.inner_instructions.clone().into();
+    let inner = inner.unwrap_or_default();
+
+    let resolve = |program_id_index: u8, accounts: &[u8]| -> Result<(Pubkey, Vec<Pubkey>)> {
+        let key = |index: u8| {
+            account_keys
+                .get(usize::from(index))
+                .copied()
+                .ok_or(anyhow!("account index {index} out of range"))
+        };
+        Ok((
+            key(program_id_index)?,
+            accounts
+                .iter()
+                .map(|&index| key(index))
+                .collect::<Result<_>>()?,
+        ))
+    };
+
+    let mut instructions = Vec::new();
+    for (index, ix) in versioned.message.instructions().iter().enumerate() {
+        let (id, accounts) = resolve(ix.program_id_index, &ix.accounts)?;
+        if id == *program_id {
+            instructions.push(ProgramInstruction {
+                accounts,
+                data: ix.data.clone(),
+            });
+        }
+
+        let invoked = inner
+            .iter()
+            .filter(|inner| usize::from(inner.index) == index)
+            .flat_map(|inner| &inner.instructions);
+        for inner_ix in invoked {
+            // Binary encodings always return compiled inner instructions.
+            let UiInstruction::Compiled(inner_ix) = inner_ix else {
+                continue;
+            };
+            let (id, accounts) = resolve(inner_ix.program_id_index, &inner_ix.accounts)?;
+            if id == *program_id {
+                instructions.push(ProgramInstruction {
+                    accounts,
+                    data: bs58::decode(&inner_ix.data).into_vec()?,
+                });
+            }
+        }
+    }
+
+    Ok(instructions)
+}
+
+/// Creates the activity of a pool initialization from the initial amounts.
+fn initialize_activity(args: &instruction::Initialize) -> PoolActivity {
+    // The initial LP supply is the geometric mean of the initial amounts.
+    let lp_supply = (u128::from(args.init_amount_0) * u128::from(args.init_amount_1)).isqrt();
+
+    PoolActivity {
+        token_0_change: to_i64(u128::from(args.init_amount_0)),
+        token_1_change: to_i64(u128::from(args.init_amount_1)),
+        lp_change: to_i64(lp_supply),
+        token_0_reserve: args.init_amount_0,
+        token_1_reserve: args.init_amount_1,
+        ..empty_activity(OperationKind::Initialize)
+    }
+}
+
+/// Creates the activity of a swap from its event.
+fn swap_activity(
+    pool: &IndexedPool,
+    event: &SwapEvent,
+    zero_for_one: bool,
+) -> Result<PoolActivity> {
+    let trade_fee = Fees::trading_fee(u128::from(event.input_amount), pool.trade_fee_rate)
+        .ok_or(anyhow!("failed to calculate trade fee"))?;
+    let trade_fee = u64::try_from(trade_fee).map_err(|_| anyhow!("trade fee too large for u64"))?;
+
+    let input_reserve = event
+        .input_vault_before
+        .checked_add(event.input_amount)
+        .ok_or(anyhow!("swap input overflows the vault balance"))?;
+    let output_reserve = event
+        .output_vault_before
+        .checked_sub(event.output_amount)
+        .ok_or(anyhow!("swap output exceeds the vault balance"))?;
+    let input_change = to_i64(u128::from(event.input_amount));
+    let output_change = -to_i64(u128::from(event.output_amount));
+
+    let activity = empty_activity(OperationKind::Swap);
+    Ok(if zero_for_one {
+        PoolActivity {
+            token_0_change: input_change,
+            token_1_change: output_change,
+            token_0_volume: event.input_amount,
+            token_1_volume: event.output_amount,
+            token_0_fee: trade_fee,
+            token_0_reserve: input_reserve,
+            token_1_reserve: output_reserve,
+            ..activity
+        }
+    } else {
+        PoolActivity {
+            token_0_change: output_change,
+            token_1_change: input_change,
+            token_0_volume: event.output_amount,
+            token_1_volume: event.input_amount,
+            token_1_fee: trade_fee,
+            token_0_reserve: output_reserve,
+            token_1_reserve: input_reserve,
+            ..activity
+        }
+    })
+}
+
+/// Creates the activity of a deposit or withdrawal from its event and the LP amount requested.
+fn lp_change_activity(event: &LpChangeEvent, lp_token_amount: u64) -> Result<PoolActivity> {
+    let token_0_amount = to_i64(u128::from(event.token_0_amount));
+    let token_1_amount = to_i64(u128::from(event.token_1_amount));
+    let lp_token_amount = to_i64(u128::from(lp_token_amount));
+
+    Ok(match event.change_type {
+        LpChangeType::Deposit => PoolActivity {
+            token_0_change: token_0_amount,
+            token_1_change: token_1_amount,
+            lp_change: lp_token_amount,
+            token_0_reserve: event
+                .token_0_vault_before
+                .checked_add(event.token_0_amount)
+                .ok_or(anyhow!("deposit overflows the vault balance"))?,
+            token_1_reserve: event
+                .token_1_vault_before
+                .checked_add(event.token_1_amount)
+                .ok_or(anyhow!("deposit overflows the vault balance"))?,
+            ..empty_activity(OperationKind::Deposit)
+        },
+        LpChangeType::Withdraw => PoolActivity {
+            token_0_change: -token_0_amount,
+            token_1_change: -token_1_amount,
+            lp_change: -lp_token_amount,
+            token_0_reserve: event
+                .token_0_vault_before
+                .checked_sub(event.token_0_amount)
+                .ok_or(anyhow!("withdrawal exceeds the vault balance"))?,
+            token_1_reserve: event
+                .token_1_vault_before
+                .checked_sub(event.token_1_amount)
+                .ok_or(anyhow!("withdrawal exceeds the vault balance"))?,
+            ..empty_activity(OperationKind::Withdraw)
+        },
+    })
+}
+
+/// Creates an activity record without any amounts, filled in by the caller.
+fn empty_activity(kind: OperationKind) -> PoolActivity {
+    PoolActivity {
+        pool_state: String::new(),
+        signature: String::new(),
+        instruction_index: 0,
+        slot: 0,
+        block_time: None,
+        kind,
+        token_0_change: 0,
+        token_1_change: 0,
+        lp_change: 0,
+        token_0_volume: 0,
+        token_1_volume: 0,
+        token_0_fee: 0,
+        token_1_fee: 0,
+        token_0_reserve: 0,
+        token_1_reserve: 0,
+    }
+}
+
+/// Converts an amount to a signed change, saturating at the largest change SQLite can store.
+fn to_i64(amount: u128) -> i64 {
+    i64::try_from(amount).unwrap_or(i64::MAX)
+}
@@ -0,0 +1,127 @@
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anyhow::{anyhow, Context, Result};
+use raydium_cp_swap::states::AmmConfig;
+
+use crate::{order_tokens, PoolInitialization, RaydiumIntegration};
+
+/// Initial amount of one side of a new pool in base units.
+#[derive(Debug, Clone, Copy)]
+pub enum InitialAmount {
+    /// Amount of token A, token B is computed from the price.
+    TokenA(u64),
+    /// Amount of token B, token A is computed from the price.
+    TokenB(u64),
+}
+
+#[derive(Debug)]
+pub struct PoolInitPlan {
+    /// AMM config the pool is created under.
+    pub amm_config: Pubkey,
+    /// Pool's state account.
+    pub pool_state: Pubkey,
+    /// Whether the pool already exists, in which case nothing is initialized.
+    pub pool_exists: bool,
+    /// Mint of the pool's token 0.
+    pub token_0_mint: Pubkey,
+    /// Mint of the pool's token 1.
+    pub token_1_mint: Pubkey,
+    /// Initial amount of token 0 in base units.
+    pub token_0_amount: u64,
+    /// Initial amount of token 1 in base units.
+    pub token_1_amount: u64,
+    /// Initial price of token 0 denominated in token 1 (adjusted by mint decimals).
+    pub token_0_price: f64,
+    /// Unix timestamp from which the pool accepts swaps.
+    pub open_time: u64,
+    /// Fee in lamports charged by the AMM config for creating the pool.
+    pub create_pool_fee: u64,
+    /// Lamports needed for the creation fee and rent of the new pool accounts.
+    pub creation_cost: u64,
+}
+
+impl RaydiumIntegration {
+    /// Plans a pool initialization from mints in any order, the price of token A in token B
+    /// (adjusted by mint decimals) and the amount of one side.
+    pub fn plan_pool_initialization(
+        &self,
+        amm_config_key: Pubkey,
+        token_a: Pubkey,
+        token_b: Pubkey,
+        price: f64,
+        amount: InitialAmount,
+        open_time: u64,
+    ) -> Result<PoolInitPlan> {
+        if token_a == token_b {
+            return Err(anyhow!("pool tokens must be different"));
+        }
+        if !price.is_finite() || price <= 0.0 {
+            return Err(anyhow!("initial price must be positive"));
+        }
+
+        let decimals_a = self.get_mint_decimals(token_a)?;
+        let decimals_b = self.get_mint_decimals(token_b)?;
+
+        // Price of one base unit of token A in base units of token B.
+        let raw_price = price * 10f64.powi(i32::from(decimals_b) - i32::from(decimals_a));
+        let (amount_a, amount_b) = match amount {
+            InitialAmount::TokenA(amount_a) => (amount_a, to_amount(amount_a as f64 * raw_price)?),
+            InitialAmount::TokenB(amount_b) => (to_amount(amount_b as f64 / raw_price)?, amount_b),
+        };
+
+        let (token_0_mint, token_1_mint) = order_tokens(token_a, token_b);
+        let (token_0_amount, token_1_amount, token_0_price) = if token_0_mint == token_a {
+            (amount_a, amount_b, price)
+        } else {
+            (amount_b, amount_a, 1.0 / price)
+        };
+
+        let amm_config = self
+            .program
+            .account::<AmmConfig>(amm_config_key)
+            .context("failed to fetch amm config")?;
+
+        let pool_state = self.get_pool_address(amm_config_key, token_a, token_b);
+        let pool_exists = self
+            .client_rpc
+            .get_account_with_commitment(&pool_state, self.client_rpc.commitment())?
+            .value
+            .is_some();
+
+        Ok(PoolInitPlan {
+            amm_config: amm_config_key,
+            pool_state,
+            pool_exists,
+            token_0_mint,
+            token_1_mint,
+            token_0_amount,
+            token_1_amount,
+            token_0_price,
+            open_time,
+            create_pool_fee: amm_config.create_pool_fee,
+            creation_cost: self.get_pool_creation_cost(&amm_config, token_0_mint, token_1_mint)?,
+        })
+    }
+
+    /// Initializes the pool described by a plan, handling an existing pool according to the policy.
+    pub fn initialize_pool_with_plan(&self, plan: &PoolInitPlan) -> Result<PoolInitialization> {
+        self.initialize_pool(
+            plan.amm_config,
+            plan.token_0_mint,
+            plan.token_1_mint,
+            plan.token_0_amount,
+            plan.token_1_amount,
+            plan.open_time,
+        )
+    }
+}
+
+/// Rounds a computed amount to base units, rejecting amounts that round to zero or overflow.
+fn to_amount(value: f64) -> Result<u64> {
+    let amount = value.round();
+    if amount < 1.0 || amount > u64::MAX as f64 {
+        return Err(anyhow!(
+            "computed initial amount {value} is out of range, adjust the price or amount"
+        ));
+    }
+    Ok(amount as u64)
+}
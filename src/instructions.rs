@@ -0,0 +1,464 @@
+use anchor_client::{
+    anchor_lang::{InstructionData, ToAccountMetas},
+    solana_sdk::{pubkey::Pubkey, system_program, sysvar},
+};
+use anyhow::Result;
+use raydium_cp_swap::{accounts, curve::TradeDirection, instruction, states::PoolState};
+use solana_program::instruction::Instruction;
+use spl_associated_token_account::{
+    get_associated_token_address, get_associated_token_address_with_program_id,
+    instruction::create_associated_token_account_idempotent,
+};
+
+use crate::{
+    pda::{self, PoolPdas},
+    wsol::{unwrap_sol_instructions, wrap_sol_instructions},
+    InitializationKeys,
+};
+
+/// Accounts of a Raydium CP-AMM pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolKeys {
+    /// Pool's state account.
+    pub pool_state: Pubkey,
+    /// AMM config the pool belongs to.
+    pub amm_config: Pubkey,
+    /// Authority account shared by all pools of the program.
+    pub authority: Pubkey,
+    /// Mint account for the pool's LP tokens.
+    pub lp_mint: Pubkey,
+    /// Mint of token 0.
+    pub token_0_mint: Pubkey,
+    /// Mint of token 1.
+    pub token_1_mint: Pubkey,
+    /// Pool's vault account for token 0.
+    pub token_0_vault: Pubkey,
+    /// Pool's vault account for token 1.
+    pub token_1_vault: Pubkey,
+    /// Token program of token 0 (SPL Token or Token-2022).
+    pub token_0_program: Pubkey,
+    /// Token program of token 1 (SPL Token or Token-2022).
+    pub token_1_program: Pubkey,
+    /// Pool's price observation account.
+    pub observation_state: Pubkey,
+}
+
+impl PoolKeys {
+    /// Derives the accounts of the pool for two mints, ordered as by `order_tokens`, under an
+    /// AMM config.
+    pub fn derive(
+        program_id: &Pubkey,
+        amm_config: Pubkey,
+        token_0_mint: Pubkey,
+        token_1_mint: Pubkey,
+        token_0_program: Pubkey,
+        token_1_program: Pubkey,
+    ) -> Self {
+        let pdas = PoolPdas::derive(program_id, &amm_config, &token_0_mint, &token_1_mint);
+
+        Self {
+            pool_state: pdas.pool_state.address,
+            amm_config,
+            authority: pda::authority(program_id).address,
+            lp_mint: pdas.lp_mint.address,
+            token_0_mint,
+            token_1_mint,
+            token_0_vault: pdas.token_0_vault.address,
+            token_1_vault: pdas.token_1_vault.address,
+            token_0_program,
+            token_1_program,
+            observation_state: pdas.observation_state.address,
+        }
+    }
+
+    /// Reads the accounts from a pool state.
+    pub fn from_pool_state(program_id: &Pubkey, pool_state: Pubkey, pool_data: &PoolState) -> Self {
+        Self {
+            pool_state,
+            amm_config: pool_data.amm_config,
+            authority: pda::authority(program_id).address,
+            lp_mint: pool_data.lp_mint,
+            token_0_mint: pool_data.token_0_mint,
+            token_1_mint: pool_data.token_1_mint,
+            token_0_vault: pool_data.token_0_vault,
+            token_1_vault: pool_data.token_1_vault,
+            token_0_program: pool_data.token_0_program,
+            token_1_program: pool_data.token_1_program,
+            observation_state: pool_data.observation_key,
+        }
+    }
+
+    /// Returns the pool's accounts with the owner's associated token accounts.
+    pub fn owner_keys(&self, owner: &Pubkey) -> InitializationKeys {
+        InitializationKeys {
+            token_0_vault: self.token_0_vault,
+            token_1_vault: self.token_1_vault,
+            pool_state: self.pool_state,
+            pool_authority: self.authority,
+            lp_mint: self.lp_mint,
+            creator_token_0: get_associated_token_address_with_program_id(
+                owner,
+                &self.token_0_mint,
+                &self.token_0_program,
+            ),
+            creator_token_1: get_associated_token_address_with_program_id(
+                owner,
+                &self.token_1_mint,
+                &self.token_1_program,
+            ),
+            // LP mints are always SPL Token mints.
+            creator_lp_ata: get_associated_token_address(owner, &self.lp_mint),
+        }
+    }
+}
+
+/// Creates the instruction initializing a pool with its initial amounts.
+///
+/// The creator's token accounts are its associated token accounts, and its LP token account is
+/// created by the program.
+pub fn initialize_instruction(
+    program_id: &Pubkey,
+    creator: &Pubkey,
+    pool: &PoolKeys,
+    create_pool_fee: &Pubkey,
+    init_amount_0: u64,
+    init_amount_1: u64,
+    open_time: u64,
+) -> Instruction {
+    let keys = pool.owner_keys(creator);
+
+    Instruction {
+        program_id: *program_id,
+        accounts: accounts::Initialize {
+            creator: *creator,
+            amm_config: pool.amm_config,
+            authority: pool.authority,
+            pool_state: pool.pool_state,
+            token_0_mint: pool.token_0_mint,
+            token_1_mint: pool.token_1_mint,
+            lp_mint: pool.lp_mint,
+            creator_token_0: keys.creator_token_0,
+            creator_token_1: keys.creator_token_1,
+            creator_lp_token: keys.creator_lp_ata,
+            token_0_vault: pool.token_0_vault,
+            token_1_vault: pool.token_1_vault,
+            create_pool_fee: *create_pool_fee,
+            observation_state: pool.observation_state,
+            token_program: spl_token::id(),
+            token_0_program: pool.token_0_program,
+            token_1_program: pool.token_1_program,
+            associated_token_program: spl_associated_token_account::id(),
+            system_program: system_program::id(),
+            rent: sysvar::rent::id(),
+        }
+        .to_account_metas(None),
+        data: instruction::Initialize {
+            init_amount_0,
+            init_amount_1,
+            open_time,
+        }
+        .data(),
+    }
+}
+
+/// Creates the instruction depositing at most the maximum amounts for an exact amount of LP
+/// tokens.
+pub fn deposit_instruction(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    keys: &InitializationKeys,
+    token_0_mint: Pubkey,
+    token_1_mint: Pubkey,
+    lp_token_amount: u64,
+    maximum_token_0_amount: u64,
+    maximum_token_1_amount: u64,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: accounts::Deposit {
+            owner: *owner,
+            authority: keys.pool_authority,
+            pool_state: keys.pool_state,
+            owner_lp_token: keys.creator_lp_ata,
+            token_0_account: keys.creator_token_0,
+            token_1_account: keys.creator_token_1,
+            token_0_vault: keys.token_0_vault,
+            token_1_vault: keys.token_1_vault,
+            token_program: spl_token::id(),
+            token_program_2022: spl_token_2022::id(),
+            vault_0_mint: token_0_mint,
+            vault_1_mint: token_1_mint,
+            lp_mint: keys.lp_mint,
+        }
+        .to_account_metas(None),
+        data: instruction::Deposit {
+            lp_token_amount,
+            maximum_token_0_amount,
+            maximum_token_1_amount,
+        }
+        .data(),
+    }
+}
+
+/// Creates the instruction redeeming an exact amount of LP tokens for at least the minimum
+/// amounts.
+pub fn withdraw_instruction(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    keys: &InitializationKeys,
+    token_0_mint: Pubkey,
+    token_1_mint: Pubkey,
+    lp_token_amount: u64,
+    minimum_token_0_amount: u64,
+    minimum_token_1_amount: u64,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: accounts::Withdraw {
+            owner: *owner,
+            authority: keys.pool_authority,
+            pool_state: keys.pool_state,
+            owner_lp_token: keys.creator_lp_ata,
+            token_0_account: keys.creator_token_0,
+            token_1_account: keys.creator_token_1,
+            token_0_vault: keys.token_0_vault,
+            token_1_vault: keys.token_1_vault,
+            token_program: spl_token::id(),
+            token_program_2022: spl_token_2022::id(),
+            vault_0_mint: token_0_mint,
+            vault_1_mint: token_1_mint,
+            lp_mint: keys.lp_mint,
+            memo_program: spl_memo::id(),
+        }
+        .to_account_metas(None),
+        data: instruction::Withdraw {
+            lp_token_amount,
+            minimum_token_0_amount,
+            minimum_token_1_amount,
+        }
+        .data(),
+    }
+}
+
+/// Creates the instruction swapping an exact input amount for at least the minimum output.
+pub fn swap_base_input_instruction(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    pool: &PoolKeys,
+    direction: TradeDirection,
+    input_token_account: Pubkey,
+    output_token_account: Pubkey,
+    amount_in: u64,
+    minimum_amount_out: u64,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: swap_accounts(
+            payer,
+            pool,
+            direction,
+            input_token_account,
+            output_token_account,
+        )
+        .to_account_metas(None),
+        data: instruction::SwapBaseInput {
+            amount_in,
+            minimum_amount_out,
+        }
+        .data(),
+    }
+}
+
+/// Creates the instruction swapping at most the maximum input for an exact output amount.
+pub fn swap_base_output_instruction(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    pool: &PoolKeys,
+    direction: TradeDirection,
+    input_token_account: Pubkey,
+    output_token_account: Pubkey,
+    max_amount_in: u64,
+    amount_out: u64,
+) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: swap_accounts(
+            payer,
+            pool,
+            direction,
+            input_token_account,
+            output_token_account,
+        )
+        .to_account_metas(None),
+        data: instruction::SwapBaseOutput {
+            max_amount_in,
+            amount_out,
+        }
+        .data(),
+    }
+}
+
+/// Creates the instructions of a deposit transaction: funding the WSOL ATA up to the maximum
+/// amounts of native SOL, creating the LP token account, the deposit and returning the SOL left
+/// over unless the owner already held WSOL.
+pub(crate) fn deposit_instructions(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    keys: &InitializationKeys,
+    token_0_mint: Pubkey,
+    token_1_mint: Pubkey,
+    lp_token_amount: u64,
+    maximum_token_0_amount: u64,
+    maximum_token_1_amount: u64,
+    held_wsol: bool,
+) -> Result<Vec<Instruction>> {
+    let mut instructions = wrap_sol_instructions(
+        owner,
+        &[
+            (token_0_mint, maximum_token_0_amount),
+            (token_1_mint, maximum_token_1_amount),
+        ],
+    )?;
+    // LP mints are always SPL Token mints.
+    instructions.push(create_associated_token_account_idempotent(
+        owner,
+        owner,
+        &keys.lp_mint,
+        &spl_token::id(),
+    ));
+    instructions.push(deposit_instruction(
+        program_id,
+        owner,
+        keys,
+        token_0_mint,
+        token_1_mint,
+        lp_token_amount,
+        maximum_token_0_amount,
+        maximum_token_1_amount,
+    ));
+    instructions.extend(unwrap_sol_instructions(
+        owner,
+        &[token_0_mint, token_1_mint],
+        held_wsol,
+    )?);
+    Ok(instructions)
+}
+
+/// Creates the instructions of a withdrawal transaction: creating the token accounts with the
+/// mints' token programs, the withdrawal and unwrapping withdrawn WSOL unless the owner already
+/// held WSOL.
+pub(crate) fn withdrawal_instructions(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    keys: &InitializationKeys,
+    token_0_mint: Pubkey,
+    token_1_mint: Pubkey,
+    token_0_program: Pubkey,
+    token_1_program: Pubkey,
+    lp_token_amount: u64,
+    minimum_token_0_amount: u64,
+    minimum_token_1_amount: u64,
+    held_wsol: bool,
+) -> Result<Vec<Instruction>> {
+    let mut instructions = vec![
+        create_associated_token_account_idempotent(owner, owner, &token_0_mint, &token_0_program),
+        create_associated_token_account_idempotent(owner, owner, &token_1_mint, &token_1_program),
+        withdraw_instruction(
+            program_id,
+            owner,
+            keys,
+            token_0_mint,
+            token_1_mint,
+            lp_token_amount,
+            minimum_token_0_amount,
+            minimum_token_1_amount,
+        ),
+    ];
+    instructions.extend(unwrap_sol_instructions(
+        owner,
+        &[token_0_mint, token_1_mint],
+        held_wsol,
+    )?);
+    Ok(instructions)
+}
+
+/// Creates the instructions of a swap transaction between the owner's associated token
+/// accounts: funding the WSOL ATA if the input is native SOL, creating the output token account,
+/// the swap and unwrapping WSOL received or left over unless the owner already held WSOL.
+pub(crate) fn swap_instructions(
+    program_id: &Pubkey,
+    owner: &Pubkey,
+    pool: &PoolKeys,
+    direction: TradeDirection,
+    amount_in: u64,
+    minimum_amount_out: u64,
+    held_wsol: bool,
+) -> Result<Vec<Instruction>> {
+    let ((input_mint, input_program), (output_mint, output_program)) = match direction {
+        TradeDirection::ZeroForOne => (
+            (pool.token_0_mint, pool.token_0_program),
+            (pool.token_1_mint, pool.token_1_program),
+        ),
+        TradeDirection::OneForZero => (
+            (pool.token_1_mint, pool.token_1_program),
+            (pool.token_0_mint, pool.token_0_program),
+        ),
+    };
+
+    let mut instructions = wrap_sol_instructions(owner, &[(input_mint, amount_in)])?;
+    instructions.push(create_associated_token_account_idempotent(
+        owner,
+        owner,
+        &output_mint,
+        &output_program,
+    ));
+    instructions.push(swap_base_input_instruction(
+        program_id,
+        owner,
+        pool,
+        direction,
+        get_associated_token_address_with_program_id(owner, &input_mint, &input_program),
+        get_associated_token_address_with_program_id(owner, &output_mint, &output_program),
+        amount_in,
+        minimum_amount_out,
+    ));
+    instructions.extend(unwrap_sol_instructions(
+        owner,
+        &[input_mint, output_mint],
+        held_wsol,
+    )?);
+    Ok(instructions)
+}
+
+/// Returns the accounts shared by both swap instructions.
+fn swap_accounts(
+    payer: &Pubkey,
+    pool: &PoolKeys,
+    direction: TradeDirection,
+    input_token_account: Pubkey,
+    output_token_account: Pubkey,
+) -> accounts::Swap {
+    let token_0 = (pool.token_0_mint, pool.token_0_vault, pool.token_0_program);
+    let token_1 = (pool.token_1_mint, pool.token_1_vault, pool.token_1_program);
+    let ((input_mint, input_vault, input_program), (output_mint, output_vault, output_program)) =
+        match direction {
+            TradeDirection::ZeroForOne => (token_0, token_1),
+            TradeDirection::OneForZero => (token_1, token_0),
+        };
+
+    accounts::Swap {
+        payer: *payer,
+        authority: pool.authority,
+        amm_config: pool.amm_config,
+        pool_state: pool.pool_state,
+        input_token_account,
+        output_token_account,
+        input_vault,
+        output_vault,
+        input_token_program: input_program,
+        output_token_program: output_program,
+        input_token_mint: input_mint,
+        output_token_mint: output_mint,
+        observation_state: pool.observation_state,
+    }
+}
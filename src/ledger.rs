@@ -0,0 +1,533 @@
+use std::{
+    cell::RefCell,
+    fs,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anchor_client::solana_sdk::{pubkey::Pubkey, signature::Signature};
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    events::{LpChangeEvent, LpChangeType, PoolEvent, SentTransaction},
+    position::redeemable_amounts,
+    PoolLiquidity, RaydiumIntegration,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LedgerEntryKind {
+    Deposit,
+    Withdrawal,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    /// Unix timestamp at which the operation was confirmed.
+    pub timestamp: u64,
+    /// Signature of the transaction containing the operation.
+    #[serde(with = "serde_display")]
+    pub signature: Signature,
+    /// Pool's state account.
+    #[serde(with = "serde_display")]
+    pub pool_state: Pubkey,
+    /// Kind of liquidity operation.
+    pub kind: LedgerEntryKind,
+    /// Amount of LP tokens minted or burned.
+    pub lp_amount: u64,
+    /// Amount of token 0 deposited or withdrawn.
+    pub token_0_amount: u64,
+    /// Amount of token 1 deposited or withdrawn.
+    pub token_1_amount: u64,
+    /// Pool's token 0 reserve before the operation.
+    pub reserve_0: u64,
+    /// Pool's token 1 reserve before the operation.
+    pub reserve_1: u64,
+    /// Pool's LP supply before the operation.
+    pub lp_supply: u64,
+}
+
+impl LedgerEntry {
+    /// Price of token 0 denominated in token 1 before the operation (base units).
+    pub fn price(&self) -> f64 {
+        self.reserve_1 as f64 / self.reserve_0 as f64
+    }
+}
+
+#[derive(Debug)]
+pub struct PositionReport {
+    /// Pool's state account.
+    pub pool_state: Pubkey,
+    /// Amount of LP tokens held according to the ledger.
+    pub lp_amount: u64,
+    /// Amount of token 0 deposited for the LP tokens still held.
+    pub entry_token_0_amount: f64,
+    /// Amount of token 1 deposited for the LP tokens still held.
+    pub entry_token_1_amount: f64,
+    /// Amount of token 0 currently redeemable for the LP tokens held.
+    pub token_0_amount: u64,
+    /// Amount of token 1 currently redeemable for the LP tokens held.
+    pub token_1_amount: u64,
+    /// Value of the deposited tokens had they been held, in token 1 base units at the current price.
+    pub hold_value: f64,
+    /// Value of the redeemable tokens, in token 1 base units at the current price.
+    pub position_value: f64,
+    /// Loss of the position versus holding, excluding fees (negative values are losses).
+    pub impermanent_loss: f64,
+    /// Value of the trading fees earned by the position, in token 1 base units.
+    pub fees_earned: f64,
+    /// Profit of the position versus its entry value, in token 1 base units.
+    pub unrealized_pnl: f64,
+    /// Profit realized on withdrawals versus their entry value, in token 1 base units.
+    pub realized_pnl: f64,
+    /// Value received for withdrawn LP tokens with no recorded deposit, in token 1 base units,
+    /// which is excluded from the realized PnL.
+    pub untracked_value: f64,
+}
+
+/// Ledger of liquidity operations persisted as a JSON file.
+pub struct PositionLedger {
+    path: PathBuf,
+    entries: Vec<LedgerEntry>,
+}
+
+impl PositionLedger {
+    /// Opens the ledger at the given path, starting an empty ledger if the file does not exist.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+
+        let entries = if path.exists() {
+            let data = fs::read_to_string(&path).context("failed to read ledger file")?;
+            serde_json::from_str(&data).context("failed to parse ledger file")?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self { path, entries })
+    }
+
+    /// Appends entries to the ledger and writes it to disk.
+    pub fn record(&mut self, entries: impl IntoIterator<Item = LedgerEntry>) -> Result<()> {
+        self.entries.extend(entries);
+        let data = serde_json::to_string_pretty(&self.entries)?;
+        fs::write(&self.path, data).context("failed to write ledger file")
+    }
+
+    /// Reports impermanent loss, fees earned and PnL for a pool against its current liquidity.
+    pub fn report(&self, pool_state: Pubkey, liquidity: &PoolLiquidity) -> Result<PositionReport> {
+        if liquidity.token_0_amount == 0 || liquidity.token_1_amount == 0 {
+            return Err(anyhow!("pool has no liquidity"));
+        }
+
+        let mut lp_amount = 0u64;
+        let mut entry_token_0_amount = 0.0;
+        let mut entry_token_1_amount = 0.0;
+        let mut entry_value = 0.0;
+        // Sum of LP amounts weighted by the invariant per LP token at entry.
+        let mut entry_growth = 0.0;
+        let mut realized_pnl = 0.0;
+        let mut untracked_value = 0.0;
+
+        for entry in self.entries.iter().filter(|e| e.pool_state == pool_state) {
+            match entry.kind {
+                LedgerEntryKind::Deposit => {
+                    lp_amount += entry.lp_amount;
+                    entry_token_0_amount += entry.token_0_amount as f64;
+                    entry_token_1_amount += entry.token_1_amount as f64;
+                    entry_value +=
+                        entry.token_0_amount as f64 * entry.price() + entry.token_1_amount as f64;
+                    entry_growth += entry.lp_amount as f64
+                        * invariant_per_lp(entry.reserve_0, entry.reserve_1, entry.lp_supply);
+                }
+                LedgerEntryKind::Withdrawal => {
+                    let received =
+                        entry.token_0_amount as f64 * entry.price() + entry.token_1_amount as f64;
+                    if entry.lp_amount == 0 {
+                        untracked_value += received;
+                        continue;
+                    }
+
+                    // LP tokens acquired outside the ledger have no entry value to realize
+                    // against, so what they returned is booked separately.
+                    let withdrawn = entry.lp_amount.min(lp_amount);
+                    let tracked = withdrawn as f64 / entry.lp_amount as f64;
+                    untracked_value += received * (1.0 - tracked);
+                    if withdrawn == 0 {
+                        continue;
+                    }
+
+                    let fraction = withdrawn as f64 / lp_amount as f64;
+                    realized_pnl += received * tracked - entry_value * fraction;
+
+                    lp_amount -= withdrawn;
+                    entry_token_0_amount *= 1.0 - fraction;
+                    entry_token_1_amount *= 1.0 - fraction;
+                    entry_value *= 1.0 - fraction;
+                    entry_growth *= 1.0 - fraction;
+                }
+            }
+        }
+
+        let (token_0_amount, token_1_amount) = redeemable_amounts(lp_amount, liquidity)?;
+
+        let price = liquidity.token_1_amount as f64 / liquidity.token_0_amount as f64;
+        let position_value = token_0_amount as f64 * price + token_1_amount as f64;
+        let hold_value = entry_token_0_amount * price + entry_token_1_amount;
+
+        // Fees grow the invariant per LP token while price moves leave it unchanged.
+        let fee_growth = if lp_amount == 0 || entry_growth == 0.0 {
+            1.0
+        } else {
+            invariant_per_lp(
+                liquidity.token_0_amount,
+                liquidity.token_1_amount,
+                liquidity.lp_supply,
+            ) / (entry_growth / lp_amount as f64)
+        };
+        let value_without_fees = position_value / fee_growth;
+
+        let impermanent_loss = if hold_value == 0.0 {
+            0.0
+        } else {
+            value_without_fees / hold_value - 1.0
+        };
+
+        Ok(PositionReport {
+            pool_state,
+            lp_amount,
+            entry_token_0_amount,
+            entry_token_1_amount,
+            token_0_amount,
+            token_1_amount,
+            hold_value,
+            position_value,
+            impermanent_loss,
+            fees_earned: position_value - value_without_fees,
+            unrealized_pnl: position_value - entry_value,
+            realized_pnl,
+            untracked_value,
+        })
+    }
+}
+
+impl RaydiumIntegration {
+    /// Attaches a position ledger that records every liquidity operation.
+    pub fn with_ledger(mut self, ledger: PositionLedger) -> Self {
+        self.ledger = Some(RefCell::new(ledger));
+        self
+    }
+
+    /// Reports impermanent loss, fees earned and PnL for the ledger's position in a pool.
+    pub fn get_position_report(&self, pool_state: Pubkey) -> Result<PositionReport> {
+        let ledger = self
+            .ledger
+            .as_ref()
+            .ok_or(anyhow!("no position ledger attached"))?;
+
+        let liquidity = self.get_pool_liquidity(pool_state)?;
+        ledger.borrow().report(pool_state, &liquidity)
+    }
+
+    /// Records the liquidity operations of a confirmed transaction from the LP change events it
+    /// emitted.
+    ///
+    /// Failures are logged rather than returned since the transaction has already landed.
+    pub(crate) fn record_ledger_entries(
+        &self,
+        pool_state: Pubkey,
+        sent: &SentTransaction,
+        operations: &[(LedgerEntryKind, u64)],
+    ) {
+        let Some(ledger) = &self.ledger else {
+            return;
+        };
+        // Multisig proposals run later, if at all, so there is nothing to record.
+        if sent.proposal.is_some() {
+            return;
+        }
+
+        let signature = sent.signature;
+        if let Err(e) = ledger_entries(pool_state, sent, operations)
+            .and_then(|entries| ledger.borrow_mut().record(entries))
+        {
+            log::error!("Failed to record ledger entries for transaction {signature}: {e}");
+        }
+    }
+}
+
+/// Builds the ledger entries of a transaction's operations from its LP change events, in order.
+fn ledger_entries(
+    pool_state: Pubkey,
+    sent: &SentTransaction,
+    operations: &[(LedgerEntryKind, u64)],
+) -> Result<Vec<LedgerEntry>> {
+    let events: Vec<&LpChangeEvent> = sent
+        .events
+        .iter()
+        .filter_map(|event| match event {
+            PoolEvent::LpChange(event) if event.pool_id == pool_state => Some(event),
+            _ => None,
+        })
+        .collect();
+    if events.len() != operations.len() {
+        return Err(anyhow!(
+            "expected {} LP change events but the transaction emitted {}",
+            operations.len(),
+            events.len()
+        ));
+    }
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    operations
+        .iter()
+        .zip(events)
+        .map(|(&(kind, lp_amount), event)| {
+            let expected = match kind {
+                LedgerEntryKind::Deposit => LpChangeType::Deposit,
+                LedgerEntryKind::Withdrawal => LpChangeType::Withdraw,
+            };
+            if event.change_type != expected {
+                return Err(anyhow!(
+                    "LP change event does not match the {kind:?} operation"
+                ));
+            }
+
+            Ok(LedgerEntry {
+                timestamp,
+                signature: sent.signature,
+                pool_state,
+                kind,
+                lp_amount,
+                token_0_amount: event.token_0_amount,
+                token_1_amount: event.token_1_amount,
+                reserve_0: event.token_0_vault_before,
+                reserve_1: event.token_1_vault_before,
+                lp_supply: event.lp_amount_before,
+            })
+        })
+        .collect()
+}
+
+/// Square root of the constant product per LP token, which only grows through fees.
+fn invariant_per_lp(reserve_0: u64, reserve_1: u64, lp_supply: u64) -> f64 {
+    (reserve_0 as f64 * reserve_1 as f64).sqrt() / lp_supply as f64
+}
+
+/// Serializes keys and signatures as their base58 strings.
+pub(crate) mod serde_display {
+    use std::{fmt::Display, str::FromStr};
+
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<T: Display, S: Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(
+        pool_state: Pubkey,
+        kind: LedgerEntryKind,
+        lp_amount: u64,
+        (token_0_amount, token_1_amount): (u64, u64),
+        (reserve_0, reserve_1, lp_supply): (u64, u64, u64),
+    ) -> LedgerEntry {
+        LedgerEntry {
+            timestamp: 0,
+            signature: Signature::default(),
+            pool_state,
+            kind,
+            lp_amount,
+            token_0_amount,
+            token_1_amount,
+            reserve_0,
+            reserve_1,
+            lp_supply,
+        }
+    }
+
+    fn ledger(entries: Vec<LedgerEntry>) -> PositionLedger {
+        PositionLedger {
+            path: PathBuf::new(),
+            entries,
+        }
+    }
+
+    fn liquidity(token_0_amount: u64, token_1_amount: u64, lp_supply: u64) -> PoolLiquidity {
+        PoolLiquidity {
+            token_0_amount,
+            token_1_amount,
+            lp_supply,
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() <= 1e-6 * expected.abs().max(1.0),
+            "{actual} != {expected}"
+        );
+    }
+
+    /// Deposit of 200_000 LP tokens for 100_000 of token 0 and 400_000 of token 1, at a price of
+    /// 4 and an invariant of 1 per LP token, leaving the pool at (1_100_000, 4_400_000, 2_200_000).
+    fn deposit(pool_state: Pubkey) -> LedgerEntry {
+        entry(
+            pool_state,
+            LedgerEntryKind::Deposit,
+            200_000,
+            (100_000, 400_000),
+            (1_000_000, 4_000_000, 2_000_000),
+        )
+    }
+
+    #[test]
+    fn unchanged_pool_has_no_loss_or_fees() {
+        let pool_state = Pubkey::new_unique();
+        let report = ledger(vec![deposit(pool_state)])
+            .report(pool_state, &liquidity(1_100_000, 4_400_000, 2_200_000))
+            .unwrap();
+
+        assert_eq!(report.lp_amount, 200_000);
+        assert_eq!(
+            (report.token_0_amount, report.token_1_amount),
+            (100_000, 400_000)
+        );
+        assert_close(report.hold_value, 800_000.0);
+        assert_close(report.position_value, 800_000.0);
+        assert_close(report.impermanent_loss, 0.0);
+        assert_close(report.fees_earned, 0.0);
+        assert_close(report.unrealized_pnl, 0.0);
+    }
+
+    #[test]
+    fn price_move_without_fees_is_impermanent_loss() {
+        let pool_state = Pubkey::new_unique();
+        // The price of token 0 quadruples to 16 at the same invariant: the reserves become
+        // (1_100_000 / 2, 4_400_000 * 2), and the position (50_000, 800_000) is worth
+        // 1_600_000 against 100_000 * 16 + 400_000 = 2_000_000 held, a loss of
+        // 2 * sqrt(4) / (1 + 4) - 1 = -20%.
+        let report = ledger(vec![deposit(pool_state)])
+            .report(pool_state, &liquidity(550_000, 8_800_000, 2_200_000))
+            .unwrap();
+
+        assert_eq!(
+            (report.token_0_amount, report.token_1_amount),
+            (50_000, 800_000)
+        );
+        assert_close(report.hold_value, 2_000_000.0);
+        assert_close(report.position_value, 1_600_000.0);
+        assert_close(report.impermanent_loss, -0.2);
+        assert_close(report.fees_earned, 0.0);
+        assert_close(report.unrealized_pnl, 800_000.0);
+    }
+
+    #[test]
+    fn invariant_growth_is_fees() {
+        let pool_state = Pubkey::new_unique();
+        // Fees grow both reserves by 10% at the same price, so the invariant per LP token grows
+        // from 1 to sqrt(1_210_000 * 4_840_000) / 2_200_000 = 1.1. The position (110_000,
+        // 440_000) is worth 880_000, of which 880_000 - 880_000 / 1.1 = 80_000 are fees.
+        let report = ledger(vec![deposit(pool_state)])
+            .report(pool_state, &liquidity(1_210_000, 4_840_000, 2_200_000))
+            .unwrap();
+
+        assert_eq!(
+            (report.token_0_amount, report.token_1_amount),
+            (110_000, 440_000)
+        );
+        assert_close(report.position_value, 880_000.0);
+        assert_close(report.fees_earned, 80_000.0);
+        assert_close(report.impermanent_loss, 0.0);
+        assert_close(report.unrealized_pnl, 80_000.0);
+    }
+
+    #[test]
+    fn partial_withdrawal_realizes_its_share() {
+        let pool_state = Pubkey::new_unique();
+        // Half of the LP tokens are withdrawn after the fees above for (55_000, 220_000), worth
+        // 440_000 against half the entry value, 400_000.
+        let withdrawal = entry(
+            pool_state,
+            LedgerEntryKind::Withdrawal,
+            100_000,
+            (55_000, 220_000),
+            (1_210_000, 4_840_000, 2_200_000),
+        );
+        let report = ledger(vec![deposit(pool_state), withdrawal])
+            .report(pool_state, &liquidity(1_155_000, 4_620_000, 2_100_000))
+            .unwrap();
+
+        assert_eq!(report.lp_amount, 100_000);
+        assert_close(report.entry_token_0_amount, 50_000.0);
+        assert_close(report.entry_token_1_amount, 200_000.0);
+        assert_close(report.realized_pnl, 40_000.0);
+        assert_eq!(
+            (report.token_0_amount, report.token_1_amount),
+            (55_000, 220_000)
+        );
+        assert_close(report.hold_value, 400_000.0);
+        assert_close(report.position_value, 440_000.0);
+        assert_close(report.fees_earned, 40_000.0);
+        assert_close(report.unrealized_pnl, 40_000.0);
+        assert_close(report.untracked_value, 0.0);
+    }
+
+    #[test]
+    fn withdrawals_beyond_the_ledger_are_untracked() {
+        let pool_state = Pubkey::new_unique();
+        // 300_000 LP tokens are withdrawn for 1_200_000 at a price of 4, but only 200_000 were
+        // recorded: two thirds realize against the entry value of 800_000 and one third is
+        // untracked.
+        let withdrawal = entry(
+            pool_state,
+            LedgerEntryKind::Withdrawal,
+            300_000,
+            (150_000, 600_000),
+            (1_000_000, 4_000_000, 2_000_000),
+        );
+        let unknown = entry(
+            pool_state,
+            LedgerEntryKind::Withdrawal,
+            0,
+            (10_000, 40_000),
+            (1_000_000, 4_000_000, 2_000_000),
+        );
+        let other_pool = deposit(Pubkey::new_unique());
+        let report = ledger(vec![deposit(pool_state), withdrawal, unknown, other_pool])
+            .report(pool_state, &liquidity(1_000_000, 4_000_000, 2_000_000))
+            .unwrap();
+
+        assert_eq!(report.lp_amount, 0);
+        assert_close(report.realized_pnl, 0.0);
+        assert_close(report.untracked_value, 400_000.0 + 80_000.0);
+        assert_close(report.position_value, 0.0);
+        assert_close(report.impermanent_loss, 0.0);
+        assert_close(report.fees_earned, 0.0);
+    }
+
+    #[test]
+    fn empty_pool_cannot_be_reported() {
+        let pool_state = Pubkey::new_unique();
+        assert!(ledger(vec![deposit(pool_state)])
+            .report(pool_state, &liquidity(0, 4_400_000, 2_200_000))
+            .is_err());
+    }
+}
@@ -0,0 +1,1166 @@
+pub mod amount;
+pub mod arbitrage;
+pub mod cache;
+pub mod compound;
+pub mod config;
+pub mod events;
+pub mod indexer;
+pub mod init;
+pub mod instructions;
+pub mod ledger;
+pub mod market;
+pub mod multisig;
+pub mod nonblocking;
+pub mod oracle;
+pub mod pda;
+pub mod position;
+mod preflight;
+pub mod route;
+pub mod signer;
+pub mod store;
+pub mod strategy;
+pub mod subscription;
+#[cfg(test)]
+mod testing;
+pub mod wsol;
+
+use std::{cell::RefCell, rc::Rc};
+
+use anchor_client::{
+    solana_client::{rpc_client::RpcClient, rpc_config::RpcTransactionConfig},
+    solana_sdk::{
+        commitment_config::CommitmentConfig,
+        pubkey::Pubkey,
+        signature::{Signature, Signer},
+        transaction::Transaction,
+    },
+    Client, Cluster, Program,
+};
+use anyhow::{anyhow, Context, Result};
+use raydium_cp_swap::{
+    curve::{CurveCalculator, RoundDirection, TradeDirection},
+    states::{AmmConfig, PoolState},
+};
+use solana_program::instruction::Instruction;
+use solana_transaction_status::{EncodedConfirmedTransactionWithStatusMeta, UiTransactionEncoding};
+use spl_associated_token_account::{
+    get_associated_token_address, get_associated_token_address_with_program_id,
+};
+
+use cache::{vault_liquidity, AccountCache};
+use config::{Config, PriorityFee, ProgramIds};
+use events::{PoolEvent, SentTransaction};
+use instructions::{
+    deposit_instructions, initialize_instruction, swap_instructions, withdrawal_instructions,
+    PoolKeys,
+};
+use ledger::{LedgerEntryKind, PositionLedger};
+use market::Market;
+use multisig::Multisig;
+use preflight::TokenRequirement;
+use signer::{PayerSigner, SharedSigner};
+use store::{Operation, OperationKind, OperationStore};
+
+/// Slippage applied to deposits, withdrawals and swaps unless configured otherwise (1%).
+pub const DEFAULT_SLIPPAGE_BPS: u64 = 100;
+
+#[derive(Debug)]
+pub struct InitializationKeys {
+    /// Pool's vault account for token 0.
+    pub token_0_vault: Pubkey,
+    /// Pool's vault account for token 1.
+    pub token_1_vault: Pubkey,
+    /// Pool's state account.
+    pub pool_state: Pubkey,
+    /// Pool's authority account.
+    pub pool_authority: Pubkey,
+    /// Mint account for the pool's LP tokens.
+    pub lp_mint: Pubkey,
+    /// Creator ATA for token 0.
+    pub creator_token_0: Pubkey,
+    /// Creator ATA for token 1.
+    pub creator_token_1: Pubkey,
+    /// Creator ATA for LP tokens.
+    pub creator_lp_ata: Pubkey,
+}
+
+/// Outcome of a pool initialization.
+#[derive(Debug)]
+pub enum PoolInitialization {
+    /// The pool was created with the requested amounts.
+    Created {
+        signature: Signature,
+        keys: InitializationKeys,
+    },
+    /// The pool already existed and was left unchanged.
+    AlreadyExists {
+        keys: InitializationKeys,
+        liquidity: PoolLiquidity,
+    },
+    /// The pool already existed and the requested amounts were deposited at its ratio.
+    Deposited {
+        signature: Signature,
+        keys: InitializationKeys,
+        liquidity: PoolLiquidity,
+        lp_amount: u64,
+        events: Vec<PoolEvent>,
+    },
+}
+
+impl PoolInitialization {
+    /// Returns the pool's accounts and the payer's token accounts.
+    pub fn keys(&self) -> &InitializationKeys {
+        match self {
+            Self::Created { keys, .. }
+            | Self::AlreadyExists { keys, .. }
+            | Self::Deposited { keys, .. } => keys,
+        }
+    }
+
+    /// Returns the signature of the sent transaction, if any.
+    pub fn signature(&self) -> Option<Signature> {
+        match self {
+            Self::Created { signature, .. } | Self::Deposited { signature, .. } => Some(*signature),
+            Self::AlreadyExists { .. } => None,
+        }
+    }
+}
+
+/// Behaviour of `initialize_pool` when the pool already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExistingPoolPolicy {
+    /// Return the existing pool's accounts and reserves.
+    #[default]
+    Return,
+    /// Fail with an error describing the existing pool.
+    Fail,
+    /// Deposit as much of the requested amounts as the existing ratio allows.
+    Deposit,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PoolLiquidity {
+    /// Amount of token 0 in the pool.
+    pub token_0_amount: u64,
+    /// Amount of token 1 in the pool.
+    pub token_1_amount: u64,
+    /// Total supply of LP tokens.
+    pub lp_supply: u64,
+}
+
+pub struct RaydiumIntegration {
+    client_rpc: RpcClient,
+    cluster: Cluster,
+    program: Program<Rc<SharedSigner>>,
+    payer: PayerSigner,
+    slippage_bps: u64,
+    existing_pool_policy: ExistingPoolPolicy,
+    priority_fee: PriorityFee,
+    program_ids: ProgramIds,
+    ledger: Option<RefCell<PositionLedger>>,
+    store: Option<OperationStore>,
+    multisig: Option<Multisig>,
+    cache: AccountCache,
+    compound_sol_reserve: u64,
+}
+
+impl RaydiumIntegration {
+    /// Creates a new Raydium integration with initialized clients and payer wallet.
+    pub fn new(payer: PayerSigner, cluster: Cluster) -> Result<Self> {
+        Self::from_config(
+            payer,
+            &Config {
+                cluster,
+                ..Config::default()
+            },
+        )
+    }
+
+    /// Creates a new Raydium integration from resolved configuration settings.
+    pub fn from_config(payer: PayerSigner, config: &Config) -> Result<Self> {
+        let cluster = config.resolved_cluster();
+        let client_rpc =
+            RpcClient::new_with_commitment(cluster.url().to_string(), config.commitment);
+        let client_anchor = Client::new_with_options(
+            cluster.clone(),
+            Rc::new(SharedSigner(payer.clone())),
+            config.commitment,
+        );
+        let program = client_anchor.program(config.program_ids.raydium_cp_swap)?;
+
+        Ok(Self {
+            client_rpc,
+            cluster,
+            program,
+            payer,
+            slippage_bps: config.slippage_bps,
+            existing_pool_policy: ExistingPoolPolicy::default(),
+            priority_fee: config.priority_fee,
+            program_ids: config.program_ids,
+            ledger: None,
+            store: None,
+            multisig: None,
+            cache: AccountCache::default(),
+            compound_sol_reserve: compound::DEFAULT_COMPOUND_SOL_RESERVE,
+        })
+    }
+
+    /// Sets the slippage applied to deposits, withdrawals and swaps in basis points.
+    pub fn with_slippage_bps(mut self, slippage_bps: u64) -> Self {
+        self.slippage_bps = slippage_bps;
+        self
+    }
+
+    /// Sets how `initialize_pool` handles a pool that already exists.
+    pub fn with_existing_pool_policy(mut self, policy: ExistingPoolPolicy) -> Self {
+        self.existing_pool_policy = policy;
+        self
+    }
+
+    /// Initializes a new Raydium CP-AMM pool, handling an existing pool according to the policy.
+    pub fn initialize_pool(
+        &self,
+        amm_config_key: Pubkey,
+        token_0_mint: Pubkey,
+        token_1_mint: Pubkey,
+        token_0_amount: u64,
+        token_1_amount: u64,
+        open_time: u64,
+    ) -> Result<PoolInitialization> {
+        if self.multisig.is_some() {
+            return Err(anyhow!(
+                "pool initialization is not supported for multisig vaults"
+            ));
+        }
+        if token_0_amount == 0 || token_1_amount == 0 {
+            return Err(anyhow!("initial amounts cannot be zero"));
+        }
+
+        // Get the pool accounts and check if the pool already exists.
+        // If it exists return the data from the pool state account instead of initializing the pool.
+        let token_0_program = self
+            .client_rpc
+            .get_account(&token_0_mint)
+            .context("failed to get token_0_mint owner")?
+            .owner;
+
+        let token_1_program = self
+            .client_rpc
+            .get_account(&token_1_mint)
+            .context("failed to get token_1_mint owner")?
+            .owner;
+
+        let pool = PoolKeys::derive(
+            &self.program.id(),
+            amm_config_key,
+            token_0_mint,
+            token_1_mint,
+            token_0_program,
+            token_1_program,
+        );
+        let pool_state = pool.pool_state;
+        let pool_authority = pool.authority;
+        let InitializationKeys {
+            creator_token_0,
+            creator_token_1,
+            ..
+        } = pool.owner_keys(&self.payer.pubkey());
+
+        if let Ok(pool_data) = self.program.account::<PoolState>(pool_state) {
+            log::info!(
+                "Pool already exists for tokens {} and {}",
+                token_0_mint,
+                token_1_mint
+            );
+
+            let token_0_vault = pool_data.token_0_vault;
+            let token_1_vault = pool_data.token_1_vault;
+            let lp_mint = pool_data.lp_mint;
+            let creator_lp_ata = get_associated_token_address(&self.payer.pubkey(), &lp_mint);
+
+            let keys = InitializationKeys {
+                token_0_vault,
+                token_1_vault,
+                pool_state,
+                pool_authority,
+                lp_mint,
+                creator_token_0,
+                creator_token_1,
+                creator_lp_ata,
+            };
+            let liquidity = self.get_vault_liquidity(&pool_data)?;
+
+            return match self.existing_pool_policy {
+                ExistingPoolPolicy::Return => {
+                    Ok(PoolInitialization::AlreadyExists { keys, liquidity })
+                }
+                ExistingPoolPolicy::Fail => Err(anyhow!(
+                    "pool {} already exists with reserves token_0_amount={}, token_1_amount={}",
+                    pool_state,
+                    liquidity.token_0_amount,
+                    liquidity.token_1_amount
+                )),
+                ExistingPoolPolicy::Deposit => {
+                    let lp_amount =
+                        self.calculate_lp_amount(token_0_amount, token_1_amount, &liquidity)?;
+
+                    let deposit = self.add_liquidity(
+                        pool_state,
+                        pool_authority,
+                        lp_mint,
+                        token_0_mint,
+                        token_1_mint,
+                        token_0_vault,
+                        token_1_vault,
+                        creator_token_0,
+                        creator_token_1,
+                        creator_lp_ata,
+                        lp_amount,
+                    )?;
+
+                    Ok(PoolInitialization::Deposited {
+                        signature: deposit.signature,
+                        keys,
+                        liquidity,
+                        lp_amount,
+                        events: deposit.events,
+                    })
+                }
+            };
+        }
+
+        log::info!(
+            "Initializing pool with tokens {} ({}) and {} ({})",
+            token_0_mint,
+            token_0_amount,
+            token_1_mint,
+            token_1_amount
+        );
+
+        let lp_mint = pool.lp_mint;
+
+        // Fund the WSOL ATA with the initial amount if either token is native SOL.
+        let mut initialization_instructions = self.create_wrap_sol_instructions(&[
+            (token_0_mint, token_0_amount),
+            (token_1_mint, token_1_amount),
+        ])?;
+
+        initialization_instructions.push(initialize_instruction(
+            &self.program.id(),
+            &self.payer.pubkey(),
+            &pool,
+            &self.program_ids.create_pool_fee_receiver,
+            token_0_amount,
+            token_1_amount,
+            open_time,
+        ));
+        initialization_instructions
+            .extend(self.create_unwrap_sol_instructions(&[token_0_mint, token_1_mint])?);
+
+        let operation = Operation {
+            token_0_amount: Some(token_0_amount),
+            token_1_amount: Some(token_1_amount),
+            ..Operation::new(
+                OperationKind::Initialize,
+                pool_state,
+                token_0_mint,
+                token_1_mint,
+                lp_mint,
+            )
+        };
+
+        let amm_config = self
+            .program
+            .account::<AmmConfig>(amm_config_key)
+            .context("failed to fetch amm config")?;
+
+        self.check_balances(
+            &initialization_instructions,
+            &[
+                TokenRequirement {
+                    mint: token_0_mint,
+                    account: creator_token_0,
+                    amount: token_0_amount,
+                },
+                TokenRequirement {
+                    mint: token_1_mint,
+                    account: creator_token_1,
+                    amount: token_1_amount,
+                },
+            ],
+            self.get_pool_creation_cost(&amm_config, token_0_mint, token_1_mint)?,
+        )?;
+
+        let SentTransaction { signature, .. } = self
+            .send_transaction(&initialization_instructions, &[operation])
+            .context("failed to send initialization transaction")?;
+
+        Ok(PoolInitialization::Created {
+            signature,
+            keys: pool.owner_keys(&self.payer.pubkey()),
+        })
+    }
+
+    /// Adds liquidity to a Raydium CP-AMM pool.
+    pub fn add_liquidity(
+        &self,
+        pool_state: Pubkey,
+        pool_authority: Pubkey,
+        lp_mint: Pubkey,
+        token_0_mint: Pubkey,
+        token_1_mint: Pubkey,
+        token_0_vault: Pubkey,
+        token_1_vault: Pubkey,
+        owner_token_0: Pubkey,
+        owner_token_1: Pubkey,
+        owner_lp: Pubkey,
+        lp_token_amount: u64,
+    ) -> Result<SentTransaction> {
+        let pool = self.get_pool_snapshot(pool_state)?;
+
+        let (max_token_0, max_token_1) =
+            self.calculate_token_amounts(&pool.liquidity, lp_token_amount, true)?;
+
+        let tx_instructions = self.create_deposit_instructions(
+            pool_state,
+            pool_authority,
+            lp_mint,
+            token_0_mint,
+            token_1_mint,
+            token_0_vault,
+            token_1_vault,
+            owner_token_0,
+            owner_token_1,
+            owner_lp,
+            lp_token_amount,
+            max_token_0,
+            max_token_1,
+        )?;
+
+        let operation = Operation {
+            lp_amount: Some(lp_token_amount),
+            token_0_bound: Some(max_token_0),
+            token_1_bound: Some(max_token_1),
+            ..Operation::new(
+                OperationKind::Deposit,
+                pool_state,
+                token_0_mint,
+                token_1_mint,
+                lp_mint,
+            )
+        };
+
+        self.check_balances(
+            &tx_instructions,
+            &[
+                TokenRequirement {
+                    mint: token_0_mint,
+                    account: owner_token_0,
+                    amount: max_token_0,
+                },
+                TokenRequirement {
+                    mint: token_1_mint,
+                    account: owner_token_1,
+                    amount: max_token_1,
+                },
+            ],
+            0,
+        )?;
+
+        let sent = self
+            .send_transaction(&tx_instructions, &[operation])
+            .context("failed to send add_liquidity transaction")?;
+
+        self.record_ledger_entries(
+            pool_state,
+            &sent,
+            &[(LedgerEntryKind::Deposit, lp_token_amount)],
+        );
+
+        Ok(sent)
+    }
+
+    /// Creates instructions for depositing liquidity to a Raydium CP-AMM pool.
+    fn create_deposit_instructions(
+        &self,
+        pool_state: Pubkey,
+        pool_authority: Pubkey,
+        lp_mint: Pubkey,
+        token_0_mint: Pubkey,
+        token_1_mint: Pubkey,
+        token_0_vault: Pubkey,
+        token_1_vault: Pubkey,
+        owner_token_0: Pubkey,
+        owner_token_1: Pubkey,
+        owner_lp: Pubkey,
+        lp_token_amount: u64,
+        max_token_0: u64,
+        max_token_1: u64,
+    ) -> Result<Vec<Instruction>> {
+        deposit_instructions(
+            &self.program.id(),
+            &self.owner(),
+            &InitializationKeys {
+                token_0_vault,
+                token_1_vault,
+                pool_state,
+                pool_authority,
+                lp_mint,
+                creator_token_0: owner_token_0,
+                creator_token_1: owner_token_1,
+                creator_lp_ata: owner_lp,
+            },
+            token_0_mint,
+            token_1_mint,
+            lp_token_amount,
+            max_token_0,
+            max_token_1,
+            self.held_wsol(&[token_0_mint, token_1_mint])?,
+        )
+    }
+
+    /// Removes liquidity from a Raydium CP-AMM pool.
+    pub fn remove_liquidity(
+        &self,
+        pool_state: Pubkey,
+        pool_authority: Pubkey,
+        lp_mint: Pubkey,
+        token_0_mint: Pubkey,
+        token_1_mint: Pubkey,
+        token_0_vault: Pubkey,
+        token_1_vault: Pubkey,
+        owner_token_0: Pubkey,
+        owner_token_1: Pubkey,
+        owner_lp: Pubkey,
+        lp_token_amount: u64,
+    ) -> Result<SentTransaction> {
+        let pool = self.get_pool_snapshot(pool_state)?;
+
+        let (min_token_0, min_token_1) =
+            self.calculate_token_amounts(&pool.liquidity, lp_token_amount, false)?;
+
+        let tx_instructions = self.create_withdrawal_instructions(
+            pool_state,
+            pool_authority,
+            lp_mint,
+            token_0_mint,
+            token_1_mint,
+            pool.pool_data.token_0_program,
+            pool.pool_data.token_1_program,
+            token_0_vault,
+            token_1_vault,
+            owner_token_0,
+            owner_token_1,
+            owner_lp,
+            lp_token_amount,
+            min_token_0,
+            min_token_1,
+        )?;
+
+        let operation = Operation {
+            lp_amount: Some(lp_token_amount),
+            token_0_bound: Some(min_token_0),
+            token_1_bound: Some(min_token_1),
+            ..Operation::new(
+                OperationKind::Withdraw,
+                pool_state,
+                token_0_mint,
+                token_1_mint,
+                lp_mint,
+            )
+        };
+
+        self.check_balances(
+            &tx_instructions,
+            &[TokenRequirement {
+                mint: lp_mint,
+                account: owner_lp,
+                amount: lp_token_amount,
+            }],
+            0,
+        )?;
+
+        let sent = self
+            .send_transaction(&tx_instructions, &[operation])
+            .context("failed to send remove_liquidity transaction")?;
+
+        self.record_ledger_entries(
+            pool_state,
+            &sent,
+            &[(LedgerEntryKind::Withdrawal, lp_token_amount)],
+        );
+
+        Ok(sent)
+    }
+
+    /// Creates instructions for withdrawing liquidity from a Raydium CP-AMM pool, creating the
+    /// owner's token accounts with the mints' token programs.
+    fn create_withdrawal_instructions(
+        &self,
+        pool_state: Pubkey,
+        pool_authority: Pubkey,
+        lp_mint: Pubkey,
+        token_0_mint: Pubkey,
+        token_1_mint: Pubkey,
+        token_0_program: Pubkey,
+        token_1_program: Pubkey,
+        token_0_vault: Pubkey,
+        token_1_vault: Pubkey,
+        owner_token_0: Pubkey,
+        owner_token_1: Pubkey,
+        owner_lp: Pubkey,
+        lp_token_amount: u64,
+        min_token_0: u64,
+        min_token_1: u64,
+    ) -> Result<Vec<Instruction>> {
+        withdrawal_instructions(
+            &self.program.id(),
+            &self.owner(),
+            &InitializationKeys {
+                token_0_vault,
+                token_1_vault,
+                pool_state,
+                pool_authority,
+                lp_mint,
+                creator_token_0: owner_token_0,
+                creator_token_1: owner_token_1,
+                creator_lp_ata: owner_lp,
+            },
+            token_0_mint,
+            token_1_mint,
+            token_0_program,
+            token_1_program,
+            lp_token_amount,
+            min_token_0,
+            min_token_1,
+            self.held_wsol(&[token_0_mint, token_1_mint])?,
+        )
+    }
+
+    /// Adds and removes liquidity from a Raydium CP-AMM pool in a single transaction.
+    pub fn add_and_remove_liquidity(
+        &self,
+        pool_state: Pubkey,
+        pool_authority: Pubkey,
+        lp_mint: Pubkey,
+        token_0_mint: Pubkey,
+        token_1_mint: Pubkey,
+        token_0_vault: Pubkey,
+        token_1_vault: Pubkey,
+        owner_token_0: Pubkey,
+        owner_token_1: Pubkey,
+        owner_lp: Pubkey,
+        lp_token_amount: u64,
+    ) -> Result<SentTransaction> {
+        // Both halves of the transaction are bounded from the same snapshot of the pool.
+        let pool = self.get_pool_snapshot(pool_state)?;
+
+        let (max_token_0, max_token_1) =
+            self.calculate_token_amounts(&pool.liquidity, lp_token_amount, true)?;
+        let (min_token_0, min_token_1) =
+            self.calculate_token_amounts(&pool.liquidity, lp_token_amount, false)?;
+
+        let mut tx_instructions = self.create_deposit_instructions(
+            pool_state,
+            pool_authority,
+            lp_mint,
+            token_0_mint,
+            token_1_mint,
+            token_0_vault,
+            token_1_vault,
+            owner_token_0,
+            owner_token_1,
+            owner_lp,
+            lp_token_amount,
+            max_token_0,
+            max_token_1,
+        )?;
+
+        tx_instructions.extend(self.create_withdrawal_instructions(
+            pool_state,
+            pool_authority,
+            lp_mint,
+            token_0_mint,
+            token_1_mint,
+            pool.pool_data.token_0_program,
+            pool.pool_data.token_1_program,
+            token_0_vault,
+            token_1_vault,
+            owner_token_0,
+            owner_token_1,
+            owner_lp,
+            lp_token_amount,
+            min_token_0,
+            min_token_1,
+        )?);
+
+        let deposit = Operation {
+            lp_amount: Some(lp_token_amount),
+            token_0_bound: Some(max_token_0),
+            token_1_bound: Some(max_token_1),
+            ..Operation::new(
+                OperationKind::Deposit,
+                pool_state,
+                token_0_mint,
+                token_1_mint,
+                lp_mint,
+            )
+        };
+
+        let withdrawal = Operation {
+            kind: OperationKind::Withdraw,
+            token_0_bound: Some(min_token_0),
+            token_1_bound: Some(min_token_1),
+            ..deposit.clone()
+        };
+
+        // The withdrawn LP tokens are minted by the deposit in the same transaction.
+        self.check_balances(
+            &tx_instructions,
+            &[
+                TokenRequirement {
+                    mint: token_0_mint,
+                    account: owner_token_0,
+                    amount: max_token_0,
+                },
+                TokenRequirement {
+                    mint: token_1_mint,
+                    account: owner_token_1,
+                    amount: max_token_1,
+                },
+            ],
+            0,
+        )?;
+
+        let sent = self
+            .send_transaction(&tx_instructions, &[deposit, withdrawal])
+            .context("failed to send add_and_remove_liquidity transaction")?;
+
+        self.record_ledger_entries(
+            pool_state,
+            &sent,
+            &[
+                (LedgerEntryKind::Deposit, lp_token_amount),
+                (LedgerEntryKind::Withdrawal, lp_token_amount),
+            ],
+        );
+
+        Ok(sent)
+    }
+
+    /// Swaps an exact amount of input tokens through a Raydium CP-AMM pool.
+    pub fn swap(
+        &self,
+        pool_state: Pubkey,
+        input_mint: Pubkey,
+        amount_in: u64,
+    ) -> Result<SentTransaction> {
+        let pool = self.get_pool_snapshot(pool_state)?;
+        let pool_data = &pool.pool_data;
+
+        let direction = swap_direction(pool_state, pool_data, input_mint)?;
+        let output_mint = match direction {
+            TradeDirection::ZeroForOne => pool_data.token_1_mint,
+            TradeDirection::OneForZero => pool_data.token_0_mint,
+        };
+        // The program checks the minimum against the output received after its transfer fee.
+        let transfer_fees =
+            self.get_transfer_fees(&[pool_data.token_0_mint, pool_data.token_1_mint])?;
+        let quote = transfer_fees.quote(
+            &Market::from(&pool),
+            direction,
+            &input_mint,
+            &output_mint,
+            amount_in,
+        )?;
+        let minimum_amount_out = minimum_amount_out(quote.amount_out, self.slippage_bps);
+
+        let tx_instructions = self.create_swap_instructions(
+            pool_state,
+            pool_data,
+            direction,
+            amount_in,
+            minimum_amount_out,
+        )?;
+
+        let operation = Operation::new(
+            OperationKind::Swap,
+            pool_state,
+            pool_data.token_0_mint,
+            pool_data.token_1_mint,
+            pool_data.lp_mint,
+        );
+        let operation = match direction {
+            TradeDirection::ZeroForOne => Operation {
+                token_0_amount: Some(amount_in),
+                token_1_bound: Some(minimum_amount_out),
+                ..operation
+            },
+            TradeDirection::OneForZero => Operation {
+                token_1_amount: Some(amount_in),
+                token_0_bound: Some(minimum_amount_out),
+                ..operation
+            },
+        };
+
+        let input_program = match direction {
+            TradeDirection::ZeroForOne => pool_data.token_0_program,
+            TradeDirection::OneForZero => pool_data.token_1_program,
+        };
+
+        self.check_balances(
+            &tx_instructions,
+            &[TokenRequirement {
+                mint: input_mint,
+                account: get_associated_token_address_with_program_id(
+                    &self.owner(),
+                    &input_mint,
+                    &input_program,
+                ),
+                amount: amount_in,
+            }],
+            0,
+        )?;
+
+        self.send_transaction(&tx_instructions, &[operation])
+            .context("failed to send swap transaction")
+    }
+
+    /// Creates instructions for swapping an exact input amount through a Raydium CP-AMM pool.
+    fn create_swap_instructions(
+        &self,
+        pool_state: Pubkey,
+        pool_data: &PoolState,
+        direction: TradeDirection,
+        amount_in: u64,
+        minimum_amount_out: u64,
+    ) -> Result<Vec<Instruction>> {
+        swap_instructions(
+            &self.program.id(),
+            &self.owner(),
+            &PoolKeys::from_pool_state(&self.program.id(), pool_state, pool_data),
+            direction,
+            amount_in,
+            minimum_amount_out,
+            self.held_wsol(&[pool_data.token_0_mint, pool_data.token_1_mint])?,
+        )
+    }
+
+    /// Returns the address of the pool for two mints under an AMM configuration.
+    pub fn get_pool_address(
+        &self,
+        amm_config_key: Pubkey,
+        token_a: Pubkey,
+        token_b: Pubkey,
+    ) -> Pubkey {
+        let (token_0_mint, token_1_mint) = order_tokens(token_a, token_b);
+
+        pda::pool_state(
+            &self.program.id(),
+            &amm_config_key,
+            &token_0_mint,
+            &token_1_mint,
+        )
+        .address
+    }
+
+    /// Returns the pool's accounts and the owner's token accounts for an existing pool.
+    pub fn get_pool_keys(&self, pool_state: Pubkey) -> Result<InitializationKeys> {
+        let pool_data = self.get_pool_snapshot(pool_state)?.pool_data;
+
+        Ok(
+            PoolKeys::from_pool_state(&self.program.id(), pool_state, &pool_data)
+                .owner_keys(&self.owner()),
+        )
+    }
+
+    /// Returns the owner of the token accounts used by deposits, withdrawals and swaps: the
+    /// multisig vault if one is attached, otherwise the payer.
+    pub fn owner(&self) -> Pubkey {
+        match &self.multisig {
+            Some(multisig) => multisig.vault(),
+            None => self.payer.pubkey(),
+        }
+    }
+
+    /// Signs and sends a transaction, recording its operations to the store if one is attached
+    /// and decoding the events it emitted.
+    ///
+    /// With a multisig attached the instructions are proposed to its vault instead.
+    fn send_transaction(
+        &self,
+        instructions: &[Instruction],
+        operations: &[Operation],
+    ) -> Result<SentTransaction> {
+        if let Some(multisig) = &self.multisig {
+            return self.send_proposal(multisig, instructions, operations);
+        }
+
+        let mut tx_instructions = self.priority_fee_instructions(instructions)?;
+        tx_instructions.extend_from_slice(instructions);
+
+        let recent_blockhash = self
+            .client_rpc
+            .get_latest_blockhash()
+            .context("failed to get recent blockhash")?;
+
+        let mut transaction =
+            Transaction::new_with_payer(&tx_instructions, Some(&self.payer.pubkey()));
+        transaction
+            .try_sign(&[self.payer.as_ref() as &dyn Signer], recent_blockhash)
+            .context("failed to sign transaction")?;
+
+        let result = self
+            .client_rpc
+            .send_and_confirm_transaction_with_spinner(&transaction);
+        // Even a failed transaction may have landed, so refetch the accounts it writes to.
+        self.invalidate_written_accounts(instructions);
+
+        let signature = transaction.signatures[0];
+
+        // The confirmed transaction is fetched once for both the store and the events.
+        let confirmed = match &result {
+            Ok(_) => match self.get_confirmed_transaction(signature) {
+                Ok(confirmed) => Some(confirmed),
+                Err(e) => {
+                    log::warn!("Failed to fetch transaction {signature}: {e}");
+                    None
+                }
+            },
+            Err(_) => None,
+        };
+
+        // The transaction has landed, so a decoding failure must not make it look failed.
+        let events = match &confirmed {
+            Some(confirmed) => self.get_transaction_events(confirmed).unwrap_or_else(|e| {
+                log::warn!("Failed to decode events of transaction {signature}: {e}");
+                Vec::new()
+            }),
+            None => Vec::new(),
+        };
+
+        self.record_operations(
+            signature,
+            operations,
+            confirmed.as_ref(),
+            &events,
+            result.as_ref().err(),
+        );
+        result?;
+
+        Ok(SentTransaction {
+            signature,
+            events,
+            proposal: None,
+        })
+    }
+
+    /// Creates a vault transaction and proposal for the instructions, signed by the payer.
+    ///
+    /// Nothing is recorded to the store since the operations only run once the members approve
+    /// and execute the proposal.
+    fn send_proposal(
+        &self,
+        multisig: &Multisig,
+        instructions: &[Instruction],
+        operations: &[Operation],
+    ) -> Result<SentTransaction> {
+        let transaction_index = self.get_next_transaction_index(multisig)?;
+        let memo = operations
+            .iter()
+            .map(|operation| operation.kind.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let (proposal_instructions, proposal) = multisig.proposal_instructions(
+            &self.payer.pubkey(),
+            transaction_index,
+            instructions,
+            Some(format!("Raydium CP-AMM {memo}")),
+        )?;
+
+        let mut tx_instructions = self.priority_fee_instructions(&proposal_instructions)?;
+        tx_instructions.extend(proposal_instructions);
+
+        let recent_blockhash = self
+            .client_rpc
+            .get_latest_blockhash()
+            .context("failed to get recent blockhash")?;
+
+        let mut transaction =
+            Transaction::new_with_payer(&tx_instructions, Some(&self.payer.pubkey()));
+        transaction
+            .try_sign(&[self.payer.as_ref() as &dyn Signer], recent_blockhash)
+            .context("failed to sign transaction")?;
+
+        let signature = self
+            .client_rpc
+            .send_and_confirm_transaction_with_spinner(&transaction)
+            .context("failed to send multisig proposal")?;
+
+        Ok(SentTransaction {
+            signature,
+            events: Vec::new(),
+            proposal: Some(proposal),
+        })
+    }
+
+    /// Fetches a confirmed transaction with its status meta.
+    fn get_confirmed_transaction(
+        &self,
+        signature: Signature,
+    ) -> Result<EncodedConfirmedTransactionWithStatusMeta> {
+        Ok(self.client_rpc.get_transaction_with_config(
+            &signature,
+            RpcTransactionConfig {
+                encoding: Some(UiTransactionEncoding::Base64),
+                commitment: Some(CommitmentConfig::confirmed()),
+                max_supported_transaction_version: Some(0),
+            },
+        )?)
+    }
+
+    /// Calculates the largest LP amount whose deposit, including slippage, stays within the token amounts.
+    fn calculate_lp_amount(
+        &self,
+        token_0_amount: u64,
+        token_1_amount: u64,
+        liquidity: &PoolLiquidity,
+    ) -> Result<u64> {
+        if liquidity.token_0_amount == 0 || liquidity.token_1_amount == 0 {
+            return Err(anyhow!("pool has no liquidity"));
+        }
+
+        let lp_supply = u128::from(liquidity.lp_supply);
+        let lp_amount = (u128::from(token_0_amount) * lp_supply
+            / u128::from(liquidity.token_0_amount))
+        .min(u128::from(token_1_amount) * lp_supply / u128::from(liquidity.token_1_amount));
+
+        // Deposits are bounded by the amounts plus slippage, so leave room for it.
+        let lp_amount = lp_amount * 10_000 / (10_000 + u128::from(self.slippage_bps));
+        let lp_amount =
+            u64::try_from(lp_amount).map_err(|_| anyhow!("lp amount too large for u64"))?;
+
+        if lp_amount == 0 {
+            return Err(anyhow!(
+                "amounts are too small to deposit into the existing pool"
+            ));
+        }
+        Ok(lp_amount)
+    }
+
+    /// Dynamically calculate token amounts needed for deposit or expected from withdrawal.
+    fn calculate_token_amounts(
+        &self,
+        pool_liquidity: &PoolLiquidity,
+        lp_token_amount: u64,
+        deposit: bool,
+    ) -> Result<(u64, u64)> {
+        token_amounts_with_slippage(pool_liquidity, lp_token_amount, self.slippage_bps, deposit)
+    }
+
+    /// Slippage as a fraction.
+    fn slippage(&self) -> f64 {
+        self.slippage_bps as f64 / 10_000.0
+    }
+
+    /// Lists available AMM configurations ordered by index.
+    pub fn list_amm_configs(&self) -> Result<Vec<(Pubkey, AmmConfig)>> {
+        let mut configs: Vec<(Pubkey, AmmConfig)> = self.program.accounts(vec![])?;
+        configs.sort_by_key(|(_, config)| config.index);
+        Ok(configs)
+    }
+
+    /// Returns an AMM configuration for the specified index if it exists.
+    pub fn get_amm_config_by_index(&self, index: u16) -> Result<(Pubkey, AmmConfig)> {
+        let amm_config_key = pda::amm_config(&self.program.id(), index).address;
+
+        let config = self.program.account::<AmmConfig>(amm_config_key)?;
+        Ok((amm_config_key, config))
+    }
+
+    /// Fetches the current liquidity amounts from a Raydium CP-AMM pool.
+    pub fn get_pool_liquidity(&self, pool_state: Pubkey) -> Result<PoolLiquidity> {
+        Ok(self.get_pool_snapshot(pool_state)?.liquidity)
+    }
+
+    /// Reads the current vault balances for an already fetched pool state.
+    fn get_vault_liquidity(&self, pool_data: &PoolState) -> Result<PoolLiquidity> {
+        let vault_accounts = self
+            .client_rpc
+            .get_multiple_accounts(&[pool_data.token_0_vault, pool_data.token_1_vault])?;
+        vault_liquidity(pool_data, &vault_accounts)
+    }
+}
+
+/// Calculates the token amounts for an LP amount, bounded by slippage.
+///
+/// Deposits return the maximum amounts to pay and withdrawals the minimum amounts to receive.
+pub(crate) fn token_amounts_with_slippage(
+    pool_liquidity: &PoolLiquidity,
+    lp_token_amount: u64,
+    slippage_bps: u64,
+    deposit: bool,
+) -> Result<(u64, u64)> {
+    // Calculate base amounts using Raydium's CurveCalculator.
+    let results = CurveCalculator::lp_tokens_to_trading_tokens(
+        u128::from(lp_token_amount),
+        u128::from(pool_liquidity.lp_supply),
+        u128::from(pool_liquidity.token_0_amount),
+        u128::from(pool_liquidity.token_1_amount),
+        RoundDirection::Ceiling,
+    )
+    .ok_or(anyhow!("failed to calculate amounts"))?;
+
+    if results.token_0_amount > u64::MAX as u128 || results.token_1_amount > u64::MAX as u128 {
+        return Err(anyhow!("token amount too large for u64"));
+    }
+    let token_0_amount = results.token_0_amount as u64;
+    let token_1_amount = results.token_1_amount as u64;
+
+    let slippage = slippage_bps as f64 / 10_000.0;
+
+    if deposit {
+        // For deposits, add slippage to get maximum amounts.
+        let max_amount_0 = (token_0_amount as f64 * (1.0 + slippage)).ceil() as u64;
+        let max_amount_1 = (token_1_amount as f64 * (1.0 + slippage)).ceil() as u64;
+        Ok((max_amount_0, max_amount_1))
+    } else {
+        // For withdrawals, subtract slippage to get minimum amounts.
+        let min_amount_0 = (token_0_amount as f64 * (1.0 - slippage)).floor() as u64;
+        let min_amount_1 = (token_1_amount as f64 * (1.0 - slippage)).floor() as u64;
+        Ok((min_amount_0, min_amount_1))
+    }
+    // NOTE: Token-2022 transfer fee calculations are skipped for simplicity.
+}
+
+/// Returns the direction of a swap selling `input_mint` through the pool.
+pub(crate) fn swap_direction(
+    pool_state: Pubkey,
+    pool_data: &PoolState,
+    input_mint: Pubkey,
+) -> Result<TradeDirection> {
+    if input_mint == pool_data.token_0_mint {
+        Ok(TradeDirection::ZeroForOne)
+    } else if input_mint == pool_data.token_1_mint {
+        Ok(TradeDirection::OneForZero)
+    } else {
+        Err(anyhow!(
+            "mint {input_mint} is not part of pool {pool_state}"
+        ))
+    }
+}
+
+/// Calculates the minimum output of a swap quoted at `amount_out`, bounded by slippage.
+pub(crate) fn minimum_amount_out(amount_out: u64, slippage_bps: u64) -> u64 {
+    let slippage = slippage_bps as f64 / 10_000.0;
+    (amount_out as f64 * (1.0 - slippage)).floor() as u64
+}
+
+/// Helper function used to order tokens when creating the CP-AMM pool.
+pub fn order_tokens(token_a: Pubkey, token_b: Pubkey) -> (Pubkey, Pubkey) {
+    if token_a < token_b {
+        (token_a, token_b)
+    } else {
+        (token_b, token_a)
+    }
+}
@@ -1,817 +1,1505 @@
-use std::{rc::Rc, str::FromStr};
-
-use anchor_client::{
-    solana_client::rpc_client::RpcClient,
-    solana_sdk::{
-        commitment_config::CommitmentConfig,
-        program_pack::Pack,
-        pubkey::Pubkey,
-        signature::{read_keypair_file, Keypair, Signature, Signer},
-        system_program, sysvar,
-        transaction::Transaction,
-    },
-    Client, Cluster, Program,
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+    thread,
+    time::Duration,
 };
-use anyhow::{anyhow, Context, Result};
-use raydium_cp_swap::{
-    accounts,
-    curve::{CurveCalculator, RoundDirection},
-    instruction,
-    states::{
-        pool::{POOL_LP_MINT_SEED, POOL_SEED, POOL_VAULT_SEED},
-        AmmConfig, PoolState, AMM_CONFIG_SEED, OBSERVATION_SEED,
-    },
-    AUTH_SEED,
+
+use anchor_client::solana_sdk::{
+    commitment_config::CommitmentLevel,
+    pubkey::Pubkey,
+    signature::{read_keypair_file, Signer},
 };
-use solana_program::instruction::Instruction;
-use spl_associated_token_account::{
-    get_associated_token_address, get_associated_token_address_with_program_id,
-    instruction::create_associated_token_account_idempotent,
+use anyhow::{anyhow, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use raydium_cp_swap::curve::TradeDirection;
+use serde_json::{json, Value};
+use solana_raydium_integration::{
+    amount::{format_ui_amount, parse_ui_amount, scale_ui_value},
+    compound::{CompoundFailure, CompoundPlan, CompoundSummary, Compounding},
+    config::{Config, PriorityFee, Profile},
+    events::{LpChangeType, PoolEvent, SentTransaction},
+    init::InitialAmount,
+    ledger::PositionLedger,
+    multisig::Multisig,
+    order_tokens,
+    position::LpPosition,
+    signer::{write_keystore, SignerSource},
+    store::OperationStore,
+    strategy::{ActionJournal, StrategyAction, StrategyConfig, StrategyRunner},
+    ExistingPoolPolicy, InitializationKeys, PoolInitialization, RaydiumIntegration,
 };
-use spl_token::state::Account;
-
-const RPC_URL: &str = "https://api.devnet.solana.com";
-const WALLET_FILE: &str = "./devnet.json";
-const TOKEN_A: &str = "69iigTreHjCuinTmPvbaVdKtvwVwkWp5nd8ERNqg49ho";
-const TOKEN_B: &str = "2vEyg5rDJZmSjsTKKGKVtETu2DSGyKwcs8h3kycLyziU";
-
-#[derive(Debug)]
-pub struct InitializationKeys {
-    /// Pool's vault account for token 0.
-    pub token_0_vault: Pubkey,
-    /// Pool's vault account for token 1.
-    pub token_1_vault: Pubkey,
-    /// Pool's state account.
-    pub pool_state: Pubkey,
-    /// Pool's authority account.
-    pub pool_authority: Pubkey,
-    /// Mint account for the pool's LP tokens.
-    pub lp_mint: Pubkey,
-    /// Creator ATA for token 0.
-    pub creator_token_0: Pubkey,
-    /// Creator ATA for token 1.
-    pub creator_token_1: Pubkey,
-    /// Creator ATA for LP tokens.
-    pub creator_lp_ata: Pubkey,
-}
-
-#[derive(Debug)]
-pub struct PoolLiquidity {
-    /// Amount of token 0 in the pool.
-    pub token_0_amount: u64,
-    /// Amount of token 1 in the pool.
-    pub token_1_amount: u64,
-    /// Total supply of LP tokens.
-    pub lp_supply: u64,
-}
-
-struct RaydiumIntegration {
-    client_rpc: RpcClient,
-    program: Program<Rc<Keypair>>,
-    payer: Rc<Keypair>,
-}
-
-impl RaydiumIntegration {
-    /// Creates a new Raydium integration with initialized clients and payer wallet.
-    pub fn new(payer: Rc<Keypair>, cluster: Cluster) -> Result<Self> {
-        let client_rpc = RpcClient::new(RPC_URL);
-        let client_anchor =
-            Client::new_with_options(cluster, payer.clone(), CommitmentConfig::confirmed());
-        let program = client_anchor.program(raydium_cp_swap::id())?;
+use spl_token::native_mint;
+
+const LEDGER_FILE: &str = "./ledger.json";
+const STORE_FILE: &str = "./operations.db";
+const JOURNAL_FILE: &str = "./strategy.jsonl";
+
+/// Period over which `pool info` averages the pool price.
+const TWAP_WINDOW: u64 = 3600; // One hour
+
+/// Manage liquidity and trade on Raydium CP-AMM pools.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// Path to the TOML configuration file [default: ./raydium.toml if present]
+    #[arg(long, short = 'c', global = true, env = "RAYDIUM_CONFIG")]
+    config: Option<PathBuf>,
+    /// Configuration profile to use.
+    #[arg(long, short = 'p', global = true)]
+    profile: Option<String>,
+    /// Cluster name (devnet, mainnet, localnet) or RPC URL.
+    #[arg(long, short = 'u', global = true)]
+    url: Option<String>,
+    /// Websocket URL, derived from the RPC URL if not set.
+    #[arg(long, global = true)]
+    ws_url: Option<String>,
+    /// Commitment used for queries and confirmations (processed, confirmed, finalized).
+    #[arg(long, global = true)]
+    commitment: Option<CommitmentLevel>,
+    /// Payer signer: keypair file path, env:VAR, keystore:PATH or signing service URL.
+    #[arg(long, short = 'k', global = true)]
+    keypair: Option<SignerSource>,
+    /// Slippage applied to deposits, withdrawals and swaps in basis points.
+    #[arg(long, global = true)]
+    slippage_bps: Option<u64>,
+    /// Fixed compute unit price in micro-lamports.
+    #[arg(long, global = true)]
+    priority_fee: Option<u64>,
+    /// Squads multisig whose vault owns the positions; deposits, withdrawals and swaps are
+    /// proposed to it instead of being sent.
+    #[arg(long, global = true)]
+    multisig: Option<Pubkey>,
+    /// Index of the multisig vault.
+    #[arg(long, global = true, default_value_t = 0, requires = "multisig")]
+    vault_index: u8,
+    /// Print output as JSON.
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
 
-        Ok(Self {
-            client_rpc,
-            program,
-            payer,
-        })
+impl Cli {
+    /// Flags override the configuration file profile and environment variables.
+    fn profile(&self) -> Profile {
+        Profile {
+            url: self.url.clone(),
+            ws_url: self.ws_url.clone(),
+            commitment: self.commitment,
+            keypair: self.keypair.clone(),
+            slippage_bps: self.slippage_bps,
+            priority_fee: self
+                .priority_fee
+                .map(|micro_lamports| PriorityFee::Fixed { micro_lamports }),
+            programs: None,
+        }
     }
+}
 
-    /// Initializes a new Raydium CP-AMM pool or returns data from an existing pool.
-    pub fn initialize_pool(
-        &self,
-        amm_config_key: Pubkey,
-        token_0_mint: Pubkey,
-        token_1_mint: Pubkey,
-        token_0_amount: u64,
-        token_1_amount: u64,
-        open_time: u64,
-    ) -> Result<(Option<Signature>, InitializationKeys)> {
-        if token_0_amount == 0 || token_1_amount == 0 {
-            return Err(anyhow!("initial amounts cannot be zero"));
-        }
+#[derive(Subcommand)]
+enum Command {
+    /// List available AMM configurations.
+    Configs,
+    /// Initialize or inspect a pool.
+    #[command(subcommand)]
+    Pool(PoolCommand),
+    /// Add liquidity to a pool for the requested amount of LP tokens.
+    Deposit(LiquidityArgs),
+    /// Remove liquidity from a pool by redeeming LP tokens.
+    Withdraw(LiquidityArgs),
+    /// Swap an exact amount of input tokens.
+    Swap(SwapArgs),
+    /// Quote a swap without sending it.
+    Quote(SwapArgs),
+    /// Show the payer's LP positions.
+    Positions(PositionsArgs),
+    /// Deposit idle balances of the pool tokens into one pool or every pool with an LP position.
+    Compound(CompoundArgs),
+    /// Find round trips between pools of a pair and through a third token, optionally executing
+    /// the most profitable one.
+    Arbitrage(ArbitrageArgs),
+    /// Keep LP positions within the value bands of a strategy file.
+    Strategy(StrategyArgs),
+    /// Manage encrypted keystore files.
+    #[command(subcommand)]
+    Keystore(KeystoreCommand),
+}
 
-        // Get the pool accounts and check if the pool already exists.
-        // If it exists return the data from the pool state account instead of initializing the pool.
-        let token_0_program = self
-            .client_rpc
-            .get_account(&token_0_mint)
-            .context("failed to get token_0_mint owner")?
-            .owner;
-
-        let token_1_program = self
-            .client_rpc
-            .get_account(&token_1_mint)
-            .context("failed to get token_1_mint owner")?
-            .owner;
-
-        let (pool_state, _bump) = Pubkey::find_program_address(
-            &[
-                POOL_SEED.as_bytes(),
-                amm_config_key.to_bytes().as_ref(),
-                token_0_mint.to_bytes().as_ref(),
-                token_1_mint.to_bytes().as_ref(),
-            ],
-            &self.program.id(),
-        );
+#[derive(Subcommand)]
+enum PoolCommand {
+    /// Initialize a pool, or show the existing pool for the mints.
+    Init(PoolInitArgs),
+    /// Show pool accounts, liquidity and prices.
+    Info(PoolArgs),
+    /// Backfill the pool's transaction history into the operation store.
+    Index(PoolIndexArgs),
+    /// Show the pool's indexed volume, fees and reserves over time.
+    History(PoolHistoryArgs),
+    /// Stream the pool's liquidity as it changes.
+    Watch(PoolArgs),
+}
 
-        let (pool_authority, _bump) =
-            Pubkey::find_program_address(&[AUTH_SEED.as_bytes()], &self.program.id());
+#[derive(Subcommand)]
+enum KeystoreCommand {
+    /// Encrypt a plaintext keypair file with a passphrase.
+    Encrypt(KeystoreEncryptArgs),
+}
 
-        let creator_token_0 = get_associated_token_address_with_program_id(
-            &self.payer.pubkey(),
-            &token_0_mint,
-            &token_0_program,
-        );
+#[derive(Args)]
+struct KeystoreEncryptArgs {
+    /// Plaintext keypair file to encrypt.
+    #[arg(long)]
+    input: PathBuf,
+    /// Keystore file to write.
+    #[arg(long)]
+    output: PathBuf,
+}
 
-        let creator_token_1 = get_associated_token_address_with_program_id(
-            &self.payer.pubkey(),
-            &token_1_mint,
-            &token_1_program,
-        );
+#[derive(Args)]
+struct PoolArgs {
+    /// Mint of the first pool token.
+    #[arg(long)]
+    mint_a: Pubkey,
+    /// Mint of the second pool token.
+    #[arg(long)]
+    mint_b: Pubkey,
+    /// Index of the AMM config the pool belongs to.
+    #[arg(long, default_value_t = 0)]
+    config_index: u16,
+}
 
-        if let Ok(pool_data) = self.program.account::<PoolState>(pool_state) {
-            log::info!(
-                "Pool already exists for tokens {} and {}",
-                token_0_mint,
-                token_1_mint
-            );
+#[derive(Args)]
+struct PoolIndexArgs {
+    #[command(flatten)]
+    pool: PoolArgs,
+    /// Maximum number of transactions to index in this run.
+    #[arg(long)]
+    limit: Option<usize>,
+}
 
-            let token_0_vault = pool_data.token_0_vault;
-            let token_1_vault = pool_data.token_1_vault;
-            let lp_mint = pool_data.lp_mint;
-            let creator_lp_ata = get_associated_token_address(&self.payer.pubkey(), &lp_mint);
-
-            return Ok((
-                None,
-                InitializationKeys {
-                    token_0_vault,
-                    token_1_vault,
-                    pool_state,
-                    pool_authority,
-                    lp_mint,
-                    creator_token_0,
-                    creator_token_1,
-                    creator_lp_ata,
-                },
-            ));
+#[derive(Args)]
+struct PoolHistoryArgs {
+    #[command(flatten)]
+    pool: PoolArgs,
+    /// Length of each timeline interval in seconds.
+    #[arg(long, default_value_t = 86400)]
+    interval: u64,
+}
+
+#[derive(Args)]
+struct PoolInitArgs {
+    #[command(flatten)]
+    pool: PoolArgs,
+    /// Initial amount of the first token in display units (e.g. 1000.5).
+    #[arg(long, required_unless_present = "amount_b")]
+    amount_a: Option<String>,
+    /// Initial amount of the second token in display units (e.g. 1000.5).
+    #[arg(long)]
+    amount_b: Option<String>,
+    /// Initial price of the first token in the second token, used with one of the amounts.
+    #[arg(long)]
+    price: Option<f64>,
+    /// Unix timestamp from which the pool accepts swaps (immediately by default).
+    #[arg(long, default_value_t = 0)]
+    open_time: u64,
+    /// What to do if the pool already exists.
+    #[arg(long, value_enum, default_value_t = IfExists::Return)]
+    if_exists: IfExists,
+    /// Initialize without asking for confirmation.
+    #[arg(long, short = 'y')]
+    yes: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum IfExists {
+    /// Show the existing pool.
+    Return,
+    /// Fail with an error.
+    Fail,
+    /// Deposit the amounts at the existing pool's ratio.
+    Deposit,
+}
+
+impl From<IfExists> for ExistingPoolPolicy {
+    fn from(value: IfExists) -> Self {
+        match value {
+            IfExists::Return => ExistingPoolPolicy::Return,
+            IfExists::Fail => ExistingPoolPolicy::Fail,
+            IfExists::Deposit => ExistingPoolPolicy::Deposit,
         }
+    }
+}
 
-        log::info!(
-            "Initializing pool with tokens {} ({}) and {} ({})",
-            token_0_mint,
-            token_0_amount,
-            token_1_mint,
-            token_1_amount
-        );
+#[derive(Args)]
+struct LiquidityArgs {
+    #[command(flatten)]
+    pool: PoolArgs,
+    /// Amount of LP tokens to mint or redeem in display units.
+    #[arg(long)]
+    lp_amount: String,
+}
 
-        // Get other accounts related to the program.
-        let (token_0_vault, _bump) = Pubkey::find_program_address(
-            &[
-                POOL_VAULT_SEED.as_bytes(),
-                pool_state.to_bytes().as_ref(),
-                token_0_mint.to_bytes().as_ref(),
-            ],
-            &self.program.id(),
-        );
+#[derive(Args)]
+struct SwapArgs {
+    /// Mint of the token sold.
+    #[arg(long)]
+    input_mint: Pubkey,
+    /// Mint of the token bought.
+    #[arg(long)]
+    output_mint: Pubkey,
+    /// Amount of input tokens to sell in display units.
+    #[arg(long)]
+    amount_in: String,
+    /// Index of the AMM config the pool belongs to.
+    #[arg(long, default_value_t = 0)]
+    config_index: u16,
+    /// Route through the best one or two pools of any AMM config instead of a single pool.
+    #[arg(long, conflicts_with = "config_index")]
+    route: bool,
+}
 
-        let (token_1_vault, _bump) = Pubkey::find_program_address(
-            &[
-                POOL_VAULT_SEED.as_bytes(),
-                pool_state.to_bytes().as_ref(),
-                token_1_mint.to_bytes().as_ref(),
-            ],
-            &self.program.id(),
-        );
+#[derive(Args)]
+struct PositionsArgs {
+    /// Mint of the first pool token (all pools if omitted).
+    #[arg(long, requires = "mint_b")]
+    mint_a: Option<Pubkey>,
+    /// Mint of the second pool token (all pools if omitted).
+    #[arg(long, requires = "mint_a")]
+    mint_b: Option<Pubkey>,
+    /// Index of the AMM config the pool belongs to.
+    #[arg(long, default_value_t = 0)]
+    config_index: u16,
+}
 
-        let (lp_mint, _bump) = Pubkey::find_program_address(
-            &[POOL_LP_MINT_SEED.as_bytes(), pool_state.to_bytes().as_ref()],
-            &self.program.id(),
-        );
+#[derive(Args)]
+struct CompoundArgs {
+    /// Mint of the first pool token (all pools with an LP position if omitted).
+    #[arg(long, requires = "mint_b")]
+    mint_a: Option<Pubkey>,
+    /// Mint of the second pool token (all pools with an LP position if omitted).
+    #[arg(long, requires = "mint_a")]
+    mint_b: Option<Pubkey>,
+    /// Index of the AMM config the pool belongs to.
+    #[arg(long, default_value_t = 0)]
+    config_index: u16,
+    /// Swap the side in excess of the pool's ratio before depositing.
+    #[arg(long)]
+    balance: bool,
+    /// SOL kept back from compounding in pools of native SOL, for fees and rent.
+    #[arg(long, default_value = "0.05")]
+    sol_reserve: String,
+    /// Show the planned deposits without sending them.
+    #[arg(long)]
+    dry_run: bool,
+}
 
-        let (observation_state, _bump) = Pubkey::find_program_address(
-            &[OBSERVATION_SEED.as_bytes(), pool_state.to_bytes().as_ref()],
-            &self.program.id(),
-        );
+#[derive(Args)]
+struct ArbitrageArgs {
+    /// Mint the round trips start and end in, in which profits are measured.
+    #[arg(long)]
+    mint_a: Pubkey,
+    /// Mint of the other pair token.
+    #[arg(long)]
+    mint_b: Pubkey,
+    /// Execute the most profitable round trip found.
+    #[arg(long)]
+    execute: bool,
+    /// Scan again every this many seconds until interrupted.
+    #[arg(long)]
+    interval: Option<u64>,
+}
 
-        let creator_lp_ata = get_associated_token_address(&self.payer.pubkey(), &lp_mint);
-
-        let initialization_accounts = accounts::Initialize {
-            creator: self.payer.pubkey(),
-            amm_config: amm_config_key,
-            authority: pool_authority,
-            pool_state,
-            token_0_mint,
-            token_1_mint,
-            lp_mint,
-            creator_token_0,
-            creator_token_1,
-            creator_lp_token: creator_lp_ata,
-            token_0_vault,
-            token_1_vault,
-            create_pool_fee: raydium_cp_swap::create_pool_fee_reveiver::id(),
-            observation_state,
-            token_program: spl_token::id(),
-            token_0_program,
-            token_1_program,
-            associated_token_program: spl_associated_token_account::id(),
-            system_program: system_program::id(),
-            rent: sysvar::rent::id(),
-        };
+#[derive(Args)]
+struct StrategyArgs {
+    /// Path to the TOML strategy file.
+    #[arg(long)]
+    file: PathBuf,
+    /// Record the actions to the journal without sending them.
+    #[arg(long)]
+    dry_run: bool,
+    /// Evaluate the pools again every this many seconds until interrupted.
+    #[arg(long, conflicts_with = "on_update")]
+    interval: Option<u64>,
+    /// Evaluate each pool whenever its liquidity changes until interrupted.
+    #[arg(long)]
+    on_update: bool,
+}
 
-        let initialization_args = instruction::Initialize {
-            init_amount_0: token_0_amount,
-            init_amount_1: token_1_amount,
-            open_time,
-        };
+/// Result of a command in human-readable and JSON form.
+struct Output {
+    lines: Vec<String>,
+    json: Value,
+}
 
-        let initialization_instructions = self
-            .program
-            .request()
-            .accounts(initialization_accounts)
-            .args(initialization_args)
-            .instructions()
-            .context("failed to build initialization instructions")?;
-
-        let recent_blockhash = self
-            .client_rpc
-            .get_latest_blockhash()
-            .context("failed to get recent blockhash")?;
-
-        let transaction = Transaction::new_signed_with_payer(
-            &initialization_instructions,
-            Some(&self.payer.pubkey()),
-            &[&self.payer],
-            recent_blockhash,
-        );
+fn main() -> Result<()> {
+    env_logger::init();
+    let cli = Cli::parse();
+
+    let mut config = Config::load(cli.config.as_deref(), cli.profile.as_deref())?;
+    config.apply(&cli.profile())?;
+
+    // The ledger and the store are only opened by commands that send transactions, so
+    // read-only commands leave no files behind.
+    let connect = |recording: bool| -> Result<RaydiumIntegration> {
+        let mut raydium = RaydiumIntegration::from_config(config.keypair.load()?, &config)?;
+        if recording {
+            raydium = raydium
+                .with_ledger(PositionLedger::open(LEDGER_FILE)?)
+                .with_store(OperationStore::open(STORE_FILE)?);
+        }
+        if let Some(multisig) = cli.multisig {
+            raydium = raydium.with_multisig(Multisig::new(multisig, cli.vault_index));
+        }
+        Ok(raydium)
+    };
+
+    let output = match &cli.command {
+        // Keystores are managed without a payer or a connection to the cluster.
+        Command::Keystore(KeystoreCommand::Encrypt(args)) => keystore_encrypt(args)?,
+        Command::Configs => configs(&connect(false)?)?,
+        Command::Pool(PoolCommand::Init(args)) => pool_init(
+            &connect(true)?.with_existing_pool_policy(args.if_exists.into()),
+            args,
+        )?,
+        Command::Pool(PoolCommand::Info(args)) => pool_info(&connect(false)?, args)?,
+        Command::Pool(PoolCommand::Index(args)) => pool_index(
+            &connect(false)?.with_store(OperationStore::open(STORE_FILE)?),
+            args,
+        )?,
+        Command::Pool(PoolCommand::History(args)) => pool_history(
+            &connect(false)?.with_store(OperationStore::open(STORE_FILE)?),
+            args,
+        )?,
+        // Watching streams its output until interrupted.
+        Command::Pool(PoolCommand::Watch(args)) => {
+            return pool_watch(&connect(false)?, args, cli.json)
+        }
+        Command::Deposit(args) => deposit(&connect(true)?, args)?,
+        Command::Withdraw(args) => withdraw(&connect(true)?, args)?,
+        Command::Swap(args) => swap(&connect(true)?, args)?,
+        Command::Quote(args) => quote(&connect(false)?, args)?,
+        Command::Positions(args) => positions(
+            &connect(false)?.with_ledger(PositionLedger::open(LEDGER_FILE)?),
+            args,
+        )?,
+        Command::Compound(args) => compound(
+            &connect(!args.dry_run)?.with_compound_sol_reserve(parse_ui_amount(
+                &args.sol_reserve,
+                native_mint::DECIMALS,
+            )?),
+            args,
+        )?,
+        // Watching with an interval streams its output until interrupted.
+        Command::Arbitrage(args) if args.interval.is_some() => {
+            return arbitrage_watch(&connect(args.execute)?, args, cli.json)
+        }
+        Command::Arbitrage(args) => arbitrage(&connect(args.execute)?, args)?,
+        // Running on a schedule or on updates streams its output until interrupted.
+        Command::Strategy(args) if args.interval.is_some() || args.on_update => {
+            return strategy_watch(&connect(!args.dry_run)?, args, cli.json)
+        }
+        Command::Strategy(args) => strategy(&connect(!args.dry_run)?, args)?,
+    };
 
-        let signature = self
-            .client_rpc
-            .send_and_confirm_transaction_with_spinner(&transaction)
-            .context("failed to send initialization transaction")?;
-
-        Ok((
-            Some(signature),
-            InitializationKeys {
-                token_0_vault,
-                token_1_vault,
-                pool_state,
-                pool_authority,
-                lp_mint,
-                creator_token_0,
-                creator_token_1,
-                creator_lp_ata,
-            },
-        ))
+    if cli.json {
+        println!("{}", serde_json::to_string_pretty(&output.json)?);
+    } else {
+        for line in output.lines {
+            println!("{line}");
+        }
     }
 
-    /// Adds liquidity to a Raydium CP-AMM pool.
-    pub fn add_liquidity(
-        &self,
-        pool_state: Pubkey,
-        pool_authority: Pubkey,
-        lp_mint: Pubkey,
-        token_0_mint: Pubkey,
-        token_1_mint: Pubkey,
-        token_0_vault: Pubkey,
-        token_1_vault: Pubkey,
-        owner_token_0: Pubkey,
-        owner_token_1: Pubkey,
-        owner_lp: Pubkey,
-        lp_token_amount: u64,
-    ) -> Result<Signature> {
-        let tx_instructions = self.create_deposit_instructions(
-            pool_state,
-            pool_authority,
-            lp_mint,
-            token_0_mint,
-            token_1_mint,
-            token_0_vault,
-            token_1_vault,
-            owner_token_0,
-            owner_token_1,
-            owner_lp,
-            lp_token_amount,
-        )?;
-
-        let recent_blockhash = self
-            .client_rpc
-            .get_latest_blockhash()
-            .context("failed to get recent blockhash")?;
-
-        let transaction = Transaction::new_signed_with_payer(
-            &tx_instructions,
-            Some(&self.payer.pubkey()),
-            &[&self.payer],
-            recent_blockhash,
-        );
+    Ok(())
+}
 
-        let signature = self
-            .client_rpc
-            .send_and_confirm_transaction_with_spinner(&transaction)
-            .context("failed to send add_liquidity transaction")?;
+fn configs(raydium: &RaydiumIntegration) -> Result<Output> {
+    let configs = raydium.list_amm_configs()?;
+
+    Ok(Output {
+        lines: configs
+            .iter()
+            .map(|(address, config)| {
+                format!(
+                    "AMM config {}: index={}, trade_fee_rate={}, protocol_fee_rate={}, fund_fee_rate={}, create_pool_fee={}",
+                    address,
+                    config.index,
+                    config.trade_fee_rate,
+                    config.protocol_fee_rate,
+                    config.fund_fee_rate,
+                    config.create_pool_fee
+                )
+            })
+            .collect(),
+        json: configs
+            .iter()
+            .map(|(address, config)| {
+                json!({
+                    "address": address.to_string(),
+                    "index": config.index,
+                    "trade_fee_rate": config.trade_fee_rate,
+                    "protocol_fee_rate": config.protocol_fee_rate,
+                    "fund_fee_rate": config.fund_fee_rate,
+                    "create_pool_fee": config.create_pool_fee,
+                })
+            })
+            .collect(),
+    })
+}
 
-        Ok(signature)
+fn pool_init(raydium: &RaydiumIntegration, args: &PoolInitArgs) -> Result<Output> {
+    let (amm_config_key, _) = raydium.get_amm_config_by_index(args.pool.config_index)?;
+    let (mint_a, mint_b) = (args.pool.mint_a, args.pool.mint_b);
+
+    let amount_a = args
+        .amount_a
+        .as_deref()
+        .map(|amount| raydium.parse_amount(mint_a, amount))
+        .transpose()?;
+    let amount_b = args
+        .amount_b
+        .as_deref()
+        .map(|amount| raydium.parse_amount(mint_b, amount))
+        .transpose()?;
+
+    let plan = match (amount_a, amount_b, args.price) {
+        (Some(amount_a), None, Some(price)) => raydium.plan_pool_initialization(
+            amm_config_key,
+            mint_a,
+            mint_b,
+            price,
+            InitialAmount::TokenA(amount_a),
+            args.open_time,
+        )?,
+        (None, Some(amount_b), Some(price)) => raydium.plan_pool_initialization(
+            amm_config_key,
+            mint_a,
+            mint_b,
+            price,
+            InitialAmount::TokenB(amount_b),
+            args.open_time,
+        )?,
+        (Some(amount_a), Some(amount_b), None) => {
+            let decimals_a = raydium.get_mint_decimals(mint_a)?;
+            let decimals_b = raydium.get_mint_decimals(mint_b)?;
+            let price = scale_ui_value(amount_b as f64, decimals_b)
+                / scale_ui_value(amount_a as f64, decimals_a);
+
+            // Keep the exact amounts rather than the ones recomputed from the price.
+            let mut plan = raydium.plan_pool_initialization(
+                amm_config_key,
+                mint_a,
+                mint_b,
+                price,
+                InitialAmount::TokenA(amount_a),
+                args.open_time,
+            )?;
+            (plan.token_0_amount, plan.token_1_amount) = if plan.token_0_mint == mint_a {
+                (amount_a, amount_b)
+            } else {
+                (amount_b, amount_a)
+            };
+            plan
+        }
+        _ => {
+            return Err(anyhow!(
+                "give both amounts, or the price with the amount of one token"
+            ))
+        }
+    };
+
+    let decimals_0 = raydium.get_mint_decimals(plan.token_0_mint)?;
+    let decimals_1 = raydium.get_mint_decimals(plan.token_1_mint)?;
+    let mut lines = vec![
+        format!("Pool state: {}", plan.pool_state),
+        format!(
+            "Initial liquidity: token_0_amount={} ({}), token_1_amount={} ({})",
+            format_ui_amount(plan.token_0_amount, decimals_0),
+            plan.token_0_mint,
+            format_ui_amount(plan.token_1_amount, decimals_1),
+            plan.token_1_mint
+        ),
+        format!("Initial price: token_0_price={}", plan.token_0_price),
+        format!(
+            "Pool creation fee: {} SOL (total cost including rent: {} SOL)",
+            format_ui_amount(plan.create_pool_fee, native_mint::DECIMALS),
+            format_ui_amount(plan.creation_cost, native_mint::DECIMALS)
+        ),
+    ];
+
+    let sends_transaction = !plan.pool_exists || args.if_exists == IfExists::Deposit;
+    if sends_transaction && !args.yes {
+        for line in &lines {
+            eprintln!("{line}");
+        }
+        if !confirm("Initialize pool?")? {
+            return Err(anyhow!("pool initialization cancelled"));
+        }
     }
 
-    /// Creates instructions for depositing liquidity to a Raydium CP-AMM pool.
-    fn create_deposit_instructions(
-        &self,
-        pool_state: Pubkey,
-        pool_authority: Pubkey,
-        lp_mint: Pubkey,
-        token_0_mint: Pubkey,
-        token_1_mint: Pubkey,
-        token_0_vault: Pubkey,
-        token_1_vault: Pubkey,
-        owner_token_0: Pubkey,
-        owner_token_1: Pubkey,
-        owner_lp: Pubkey,
-        lp_token_amount: u64,
-    ) -> Result<Vec<Instruction>> {
-        let mut tx_instructions = Vec::new();
-
-        // Create LP token ATA if it doesn't exist.
-        let create_ata_instructions = create_associated_token_account_idempotent(
-            &self.payer.pubkey(),
-            &self.payer.pubkey(),
-            &lp_mint,
-            &spl_token::id(),
-        );
-        tx_instructions.push(create_ata_instructions);
-
-        let deposit_accounts = accounts::Deposit {
-            owner: self.payer.pubkey(),
-            authority: pool_authority,
-            pool_state,
-            owner_lp_token: owner_lp,
-            token_0_account: owner_token_0,
-            token_1_account: owner_token_1,
-            token_0_vault,
-            token_1_vault,
-            token_program: spl_token::id(),
-            token_program_2022: spl_token_2022::id(),
-            vault_0_mint: token_0_mint,
-            vault_1_mint: token_1_mint,
-            lp_mint,
-        };
+    let initialization = raydium.initialize_pool_with_plan(&plan)?;
 
-        let (max_token_0, max_token_1) =
-            self.calculate_token_amounts(pool_state, lp_token_amount, true)?;
+    let existing_liquidity = match &initialization {
+        PoolInitialization::Created { signature, .. } => {
+            lines.push(format!("Initialized pool: {signature}"));
+            None
+        }
+        PoolInitialization::AlreadyExists { liquidity, .. } => {
+            lines.push("Pool already exists, nothing was deposited".to_string());
+            Some(liquidity)
+        }
+        PoolInitialization::Deposited {
+            signature,
+            liquidity,
+            lp_amount,
+            events,
+            ..
+        } => {
+            lines.push(format!(
+                "Pool already exists, deposited for {} LP tokens at its ratio: {signature}",
+                raydium.format_amount(initialization.keys().lp_mint, *lp_amount)?
+            ));
+            lines.extend(
+                events
+                    .iter()
+                    .map(|event| event_line(event, decimals_0, decimals_1)),
+            );
+            Some(liquidity)
+        }
+    };
+    if let Some(liquidity) = existing_liquidity {
+        lines.push(format!(
+            "Existing reserves: token_0_amount={}, token_1_amount={}",
+            format_ui_amount(liquidity.token_0_amount, decimals_0),
+            format_ui_amount(liquidity.token_1_amount, decimals_1)
+        ));
+    }
+    let keys = initialization.keys();
+    lines.extend(keys_lines(keys));
+
+    Ok(Output {
+        lines,
+        json: json!({
+            "signature": initialization.signature().map(|s| s.to_string()),
+            "pool_exists": existing_liquidity.is_some(),
+            "existing_reserves": existing_liquidity.map(|liquidity| json!({
+                "token_0_amount": liquidity.token_0_amount,
+                "token_1_amount": liquidity.token_1_amount,
+            })),
+            "plan": {
+                "pool_state": plan.pool_state.to_string(),
+                "token_0_mint": plan.token_0_mint.to_string(),
+                "token_1_mint": plan.token_1_mint.to_string(),
+                "token_0_amount": plan.token_0_amount,
+                "token_1_amount": plan.token_1_amount,
+                "token_0_price": plan.token_0_price,
+                "create_pool_fee": plan.create_pool_fee,
+                "creation_cost": plan.creation_cost,
+            },
+            "keys": keys_json(keys),
+        }),
+    })
+}
 
-        let deposit_args = instruction::Deposit {
-            lp_token_amount,
-            maximum_token_0_amount: max_token_0,
-            maximum_token_1_amount: max_token_1,
-        };
+fn pool_info(raydium: &RaydiumIntegration, args: &PoolArgs) -> Result<Output> {
+    let keys = resolve_pool(raydium, args)?;
+    let (token_0_mint, token_1_mint) = order_tokens(args.mint_a, args.mint_b);
+    let decimals = PoolDecimals::fetch(raydium, token_0_mint, token_1_mint, keys.lp_mint)?;
+    let liquidity = raydium.get_pool_liquidity(keys.pool_state)?;
+    let spot_price = raydium.get_spot_price(keys.pool_state)?;
+
+    let mut lines = keys_lines(&keys);
+    lines.push(format!(
+        "Liquidity: token_0_amount={}, token_1_amount={}, lp_supply={}",
+        format_ui_amount(liquidity.token_0_amount, decimals.token_0),
+        format_ui_amount(liquidity.token_1_amount, decimals.token_1),
+        format_ui_amount(liquidity.lp_supply, decimals.lp)
+    ));
+    lines.push(format!(
+        "Spot price: token_0_price={}, token_1_price={}",
+        spot_price.token_0_price, spot_price.token_1_price
+    ));
+
+    // A new pool has no observations yet, which should not fail the whole command.
+    let twap = match raydium.get_pool_twap(keys.pool_state, TWAP_WINDOW) {
+        Ok(twap) => {
+            lines.push(format!(
+                "TWAP over {}s: token_0_price={}, token_1_price={}",
+                twap.window(),
+                twap.token_0_price,
+                twap.token_1_price
+            ));
+            json!({
+                "window": twap.window(),
+                "token_0_price": twap.token_0_price,
+                "token_1_price": twap.token_1_price,
+            })
+        }
+        Err(e) => {
+            lines.push(format!("TWAP unavailable: {e}"));
+            Value::Null
+        }
+    };
+
+    Ok(Output {
+        lines,
+        json: json!({
+            "keys": keys_json(&keys),
+            "liquidity": {
+                "token_0_amount": liquidity.token_0_amount,
+                "token_1_amount": liquidity.token_1_amount,
+                "lp_supply": liquidity.lp_supply,
+            },
+            "spot_price": {
+                "token_0_price": spot_price.token_0_price,
+                "token_1_price": spot_price.token_1_price,
+            },
+            "twap": twap,
+        }),
+    })
+}
+
+fn pool_index(raydium: &RaydiumIntegration, args: &PoolIndexArgs) -> Result<Output> {
+    let keys = resolve_pool(raydium, &args.pool)?;
+    let summary = raydium.index_pool_history(keys.pool_state, args.limit)?;
+
+    let mut lines = vec![format!(
+        "Indexed {} transactions with {} pool instructions",
+        summary.transactions, summary.activities
+    )];
+    if summary.skipped > 0 {
+        lines.push(format!(
+            "Skipped {} transactions that could not be decoded, their volume and fees are missing",
+            summary.skipped
+        ));
+    }
+    if let Some(signature) = summary.last_signature {
+        lines.push(format!("Last indexed transaction: {signature}"));
+    }
+    if summary.has_more {
+        lines.push("More transactions remain, run again to continue".to_string());
+    }
+
+    Ok(Output {
+        lines,
+        json: json!({
+            "transactions": summary.transactions,
+            "activities": summary.activities,
+            "skipped": summary.skipped,
+            "last_signature": summary.last_signature.map(|s| s.to_string()),
+            "has_more": summary.has_more,
+        }),
+    })
+}
+
+fn pool_history(raydium: &RaydiumIntegration, args: &PoolHistoryArgs) -> Result<Output> {
+    let keys = resolve_pool(raydium, &args.pool)?;
+    let (token_0_mint, token_1_mint) = order_tokens(args.pool.mint_a, args.pool.mint_b);
+    let decimals = PoolDecimals::fetch(raydium, token_0_mint, token_1_mint, keys.lp_mint)?;
+    let timeline = raydium.get_pool_timeline(keys.pool_state, args.interval, None)?;
+
+    let lines = timeline
+        .iter()
+        .map(|point| {
+            format!(
+                "{}: swaps={}, token_0_volume={}, token_1_volume={}, token_0_fees={}, token_1_fees={}, token_0_reserve={}, token_1_reserve={}",
+                point.start,
+                point.swaps,
+                format_ui_amount(point.token_0_volume, decimals.token_0),
+                format_ui_amount(point.token_1_volume, decimals.token_1),
+                format_ui_amount(point.token_0_fees, decimals.token_0),
+                format_ui_amount(point.token_1_fees, decimals.token_1),
+                format_ui_amount(point.token_0_reserve, decimals.token_0),
+                format_ui_amount(point.token_1_reserve, decimals.token_1)
+            )
+        })
+        .collect();
 
-        let deposit_instructions = self
-            .program
-            .request()
-            .accounts(deposit_accounts)
-            .args(deposit_args)
-            .instructions()
-            .context("failed to build deposit instructions")?;
+    Ok(Output {
+        lines,
+        json: serde_json::to_value(&timeline)?,
+    })
+}
 
-        tx_instructions.extend(deposit_instructions);
-        Ok(tx_instructions)
+fn pool_watch(raydium: &RaydiumIntegration, args: &PoolArgs, json: bool) -> Result<()> {
+    let keys = resolve_pool(raydium, args)?;
+    let (token_0_mint, token_1_mint) = order_tokens(args.mint_a, args.mint_b);
+    let decimals = PoolDecimals::fetch(raydium, token_0_mint, token_1_mint, keys.lp_mint)?;
+
+    for snapshot in raydium.subscribe_pool_liquidity(keys.pool_state)? {
+        let snapshot = snapshot?;
+        let liquidity = snapshot.liquidity;
+        if json {
+            println!(
+                "{}",
+                json!({
+                    "slot": snapshot.slot,
+                    "token_0_amount": liquidity.token_0_amount,
+                    "token_1_amount": liquidity.token_1_amount,
+                    "lp_supply": liquidity.lp_supply,
+                })
+            );
+        } else {
+            println!(
+                "Slot {}: token_0_amount={}, token_1_amount={}, lp_supply={}",
+                snapshot.slot,
+                format_ui_amount(liquidity.token_0_amount, decimals.token_0),
+                format_ui_amount(liquidity.token_1_amount, decimals.token_1),
+                format_ui_amount(liquidity.lp_supply, decimals.lp)
+            );
+        }
     }
 
-    /// Removes liquidity from a Raydium CP-AMM pool.
-    pub fn remove_liquidity(
-        &self,
-        pool_state: Pubkey,
-        pool_authority: Pubkey,
-        lp_mint: Pubkey,
-        token_0_mint: Pubkey,
-        token_1_mint: Pubkey,
-        token_0_vault: Pubkey,
-        token_1_vault: Pubkey,
-        owner_token_0: Pubkey,
-        owner_token_1: Pubkey,
-        owner_lp: Pubkey,
-        lp_token_amount: u64,
-    ) -> Result<Signature> {
-        let tx_instructions = self.create_withdrawal_instructions(
-            pool_state,
-            pool_authority,
-            lp_mint,
-            token_0_mint,
-            token_1_mint,
-            token_0_vault,
-            token_1_vault,
-            owner_token_0,
-            owner_token_1,
-            owner_lp,
-            lp_token_amount,
-        )?;
-
-        let recent_blockhash = self
-            .client_rpc
-            .get_latest_blockhash()
-            .context("failed to get recent blockhash")?;
-
-        let transaction = Transaction::new_signed_with_payer(
-            &tx_instructions,
-            Some(&self.payer.pubkey()),
-            &[&self.payer],
-            recent_blockhash,
-        );
+    Ok(())
+}
+
+fn deposit(raydium: &RaydiumIntegration, args: &LiquidityArgs) -> Result<Output> {
+    let keys = resolve_pool(raydium, &args.pool)?;
+    let (token_0_mint, token_1_mint) = order_tokens(args.pool.mint_a, args.pool.mint_b);
+
+    let sent = raydium.add_liquidity(
+        keys.pool_state,
+        keys.pool_authority,
+        keys.lp_mint,
+        token_0_mint,
+        token_1_mint,
+        keys.token_0_vault,
+        keys.token_1_vault,
+        keys.creator_token_0,
+        keys.creator_token_1,
+        keys.creator_lp_ata,
+        raydium.parse_amount(keys.lp_mint, &args.lp_amount)?,
+    )?;
+
+    let decimals_0 = raydium.get_mint_decimals(token_0_mint)?;
+    let decimals_1 = raydium.get_mint_decimals(token_1_mint)?;
+    Ok(sent_output(
+        "Added liquidity",
+        &sent,
+        decimals_0,
+        decimals_1,
+    ))
+}
+
+fn withdraw(raydium: &RaydiumIntegration, args: &LiquidityArgs) -> Result<Output> {
+    let keys = resolve_pool(raydium, &args.pool)?;
+    let (token_0_mint, token_1_mint) = order_tokens(args.pool.mint_a, args.pool.mint_b);
+
+    let sent = raydium.remove_liquidity(
+        keys.pool_state,
+        keys.pool_authority,
+        keys.lp_mint,
+        token_0_mint,
+        token_1_mint,
+        keys.token_0_vault,
+        keys.token_1_vault,
+        keys.creator_token_0,
+        keys.creator_token_1,
+        keys.creator_lp_ata,
+        raydium.parse_amount(keys.lp_mint, &args.lp_amount)?,
+    )?;
 
-        let signature = self
-            .client_rpc
-            .send_and_confirm_transaction_with_spinner(&transaction)
-            .context("failed to send remove_liquidity transaction")?;
+    let decimals_0 = raydium.get_mint_decimals(token_0_mint)?;
+    let decimals_1 = raydium.get_mint_decimals(token_1_mint)?;
+    Ok(sent_output(
+        "Removed liquidity",
+        &sent,
+        decimals_0,
+        decimals_1,
+    ))
+}
 
-        Ok(signature)
+fn swap(raydium: &RaydiumIntegration, args: &SwapArgs) -> Result<Output> {
+    let amount_in = raydium.parse_amount(args.input_mint, &args.amount_in)?;
+
+    if args.route {
+        let route = raydium.get_best_route(args.input_mint, args.output_mint, amount_in)?;
+        let sent = raydium.swap_route(&route)?;
+
+        // Each hop emits a swap event in the hop's own input and output mints.
+        let hop_decimals = route
+            .hops
+            .iter()
+            .map(|hop| {
+                Ok((
+                    raydium.get_mint_decimals(hop.input_mint)?,
+                    raydium.get_mint_decimals(hop.output_mint)?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        return Ok(events_output("Swapped", &sent, &hop_decimals));
     }
 
-    /// Creates instructions for withdrawing liquidity from a Raydium CP-AMM pool.
-    fn create_withdrawal_instructions(
-        &self,
-        pool_state: Pubkey,
-        pool_authority: Pubkey,
-        lp_mint: Pubkey,
-        token_0_mint: Pubkey,
-        token_1_mint: Pubkey,
-        token_0_vault: Pubkey,
-        token_1_vault: Pubkey,
-        owner_token_0: Pubkey,
-        owner_token_1: Pubkey,
-        owner_lp: Pubkey,
-        lp_token_amount: u64,
-    ) -> Result<Vec<Instruction>> {
-        let mut tx_instructions = Vec::new();
-
-        // Create token ATAs if they don't exist.
-        let create_token_0_ata = create_associated_token_account_idempotent(
-            &self.payer.pubkey(),
-            &self.payer.pubkey(),
-            &token_0_mint,
-            &spl_token::id(),
-        );
-        tx_instructions.push(create_token_0_ata);
+    let (amm_config_key, _) = raydium.get_amm_config_by_index(args.config_index)?;
+    let pool_state = raydium.get_pool_address(amm_config_key, args.input_mint, args.output_mint);
+    let sent = raydium.swap(pool_state, args.input_mint, amount_in)?;
 
-        let create_token_1_ata = create_associated_token_account_idempotent(
-            &self.payer.pubkey(),
-            &self.payer.pubkey(),
-            &token_1_mint,
-            &spl_token::id(),
-        );
-        tx_instructions.push(create_token_1_ata);
-
-        let withdrawal_accounts = accounts::Withdraw {
-            owner: self.payer.pubkey(),
-            authority: pool_authority,
-            pool_state,
-            owner_lp_token: owner_lp,
-            token_0_account: owner_token_0,
-            token_1_account: owner_token_1,
-            token_0_vault,
-            token_1_vault,
-            token_program: spl_token::id(),
-            token_program_2022: spl_token_2022::id(),
-            vault_0_mint: token_0_mint,
-            vault_1_mint: token_1_mint,
-            lp_mint,
-            memo_program: spl_memo::id(),
-        };
+    let decimals_in = raydium.get_mint_decimals(args.input_mint)?;
+    let decimals_out = raydium.get_mint_decimals(args.output_mint)?;
+    Ok(sent_output("Swapped", &sent, decimals_in, decimals_out))
+}
 
-        let (min_token_0, min_token_1) =
-            self.calculate_token_amounts(pool_state, lp_token_amount, false)?;
+fn quote(raydium: &RaydiumIntegration, args: &SwapArgs) -> Result<Output> {
+    if args.route {
+        return quote_route(raydium, args);
+    }
 
-        let withdrawal_args = instruction::Withdraw {
-            lp_token_amount,
-            minimum_token_0_amount: min_token_0,
-            minimum_token_1_amount: min_token_1,
-        };
+    let (amm_config_key, _) = raydium.get_amm_config_by_index(args.config_index)?;
+    let pool_state = raydium.get_pool_address(amm_config_key, args.input_mint, args.output_mint);
 
-        let withdrawal_instructions = self
-            .program
-            .request()
-            .accounts(withdrawal_accounts)
-            .args(withdrawal_args)
-            .instructions()
-            .context("failed to build withdraw instructions")?;
+    let (token_0_mint, _) = order_tokens(args.input_mint, args.output_mint);
+    let direction = if args.input_mint == token_0_mint {
+        TradeDirection::ZeroForOne
+    } else {
+        TradeDirection::OneForZero
+    };
+
+    let decimals_in = raydium.get_mint_decimals(args.input_mint)?;
+    let decimals_out = raydium.get_mint_decimals(args.output_mint)?;
+    let amount_in = parse_ui_amount(&args.amount_in, decimals_in)?;
+
+    let quote = raydium.get_price_impact(pool_state, direction, amount_in)?;
+
+    Ok(Output {
+        lines: vec![format!(
+            "Quote: amount_in={}, amount_out={}, trade_fee={}, spot_price={}, execution_price={}, price_impact={:.4}%",
+            format_ui_amount(quote.amount_in, decimals_in),
+            format_ui_amount(quote.amount_out, decimals_out),
+            format_ui_amount(quote.trade_fee, decimals_in),
+            quote.spot_price,
+            quote.execution_price,
+            quote.price_impact * 100.0
+        )],
+        json: json!({
+            "pool_state": pool_state.to_string(),
+            "amount_in": quote.amount_in,
+            "amount_out": quote.amount_out,
+            "trade_fee": quote.trade_fee,
+            "spot_price": quote.spot_price,
+            "execution_price": quote.execution_price,
+            "price_impact": quote.price_impact,
+        }),
+    })
+}
 
-        tx_instructions.extend(withdrawal_instructions);
-        Ok(tx_instructions)
+fn quote_route(raydium: &RaydiumIntegration, args: &SwapArgs) -> Result<Output> {
+    let decimals_in = raydium.get_mint_decimals(args.input_mint)?;
+    let decimals_out = raydium.get_mint_decimals(args.output_mint)?;
+    let amount_in = parse_ui_amount(&args.amount_in, decimals_in)?;
+
+    let route = raydium.get_best_route(args.input_mint, args.output_mint, amount_in)?;
+
+    let mut lines = vec![format!(
+        "Route: amount_in={}, amount_out={}, minimum_amount_out={}, hops={}",
+        format_ui_amount(route.amount_in, decimals_in),
+        format_ui_amount(route.amount_out, decimals_out),
+        format_ui_amount(route.minimum_amount_out, decimals_out),
+        route.hops.len()
+    )];
+    for hop in &route.hops {
+        let hop_decimals_in = raydium.get_mint_decimals(hop.input_mint)?;
+        let hop_decimals_out = raydium.get_mint_decimals(hop.output_mint)?;
+        lines.push(format!(
+            "Hop: pool_state={}, input_mint={}, output_mint={}, amount_in={}, amount_out={}, trade_fee={}",
+            hop.pool.pool_state,
+            hop.input_mint,
+            hop.output_mint,
+            format_ui_amount(hop.amount_in, hop_decimals_in),
+            format_ui_amount(hop.amount_out, hop_decimals_out),
+            format_ui_amount(hop.trade_fee, hop_decimals_in)
+        ));
     }
 
-    /// Adds and removes liquidity from a Raydium CP-AMM pool in a single transaction.
-    pub fn add_and_remove_liquidity(
-        &self,
-        pool_state: Pubkey,
-        pool_authority: Pubkey,
-        lp_mint: Pubkey,
-        token_0_mint: Pubkey,
-        token_1_mint: Pubkey,
-        token_0_vault: Pubkey,
-        token_1_vault: Pubkey,
-        owner_token_0: Pubkey,
-        owner_token_1: Pubkey,
-        owner_lp: Pubkey,
-        lp_token_amount: u64,
-    ) -> Result<Signature> {
-        let mut tx_instructions = self.create_deposit_instructions(
-            pool_state,
-            pool_authority,
-            lp_mint,
-            token_0_mint,
-            token_1_mint,
-            token_0_vault,
-            token_1_vault,
-            owner_token_0,
-            owner_token_1,
-            owner_lp,
-            lp_token_amount,
-        )?;
-
-        tx_instructions.extend(self.create_withdrawal_instructions(
-            pool_state,
-            pool_authority,
-            lp_mint,
-            token_0_mint,
-            token_1_mint,
-            token_0_vault,
-            token_1_vault,
-            owner_token_0,
-            owner_token_1,
-            owner_lp,
-            lp_token_amount,
-        )?);
-
-        let recent_blockhash = self
-            .client_rpc
-            .get_latest_blockhash()
-            .context("failed to get recent blockhash")?;
-
-        let transaction = Transaction::new_signed_with_payer(
-            &tx_instructions,
-            Some(&self.payer.pubkey()),
-            &[&self.payer],
-            recent_blockhash,
-        );
+    Ok(Output {
+        lines,
+        json: json!({
+            "amount_in": route.amount_in,
+            "amount_out": route.amount_out,
+            "minimum_amount_out": route.minimum_amount_out,
+            "hops": route
+                .hops
+                .iter()
+                .map(|hop| json!({
+                    "pool_state": hop.pool.pool_state.to_string(),
+                    "input_mint": hop.input_mint.to_string(),
+                    "output_mint": hop.output_mint.to_string(),
+                    "amount_in": hop.amount_in,
+                    "amount_out": hop.amount_out,
+                    "minimum_amount_out": hop.minimum_amount_out,
+                    "trade_fee": hop.trade_fee,
+                }))
+                .collect::<Vec<_>>(),
+        }),
+    })
+}
 
-        let signature = self
-            .client_rpc
-            .send_and_confirm_transaction_with_spinner(&transaction)
-            .context("failed to send add_and_remove_liquidity transaction")?;
+fn arbitrage(raydium: &RaydiumIntegration, args: &ArbitrageArgs) -> Result<Output> {
+    let decimals = raydium.get_mint_decimals(args.mint_a)?;
+    let opportunities = raydium.find_arbitrage(args.mint_a, args.mint_b)?;
+
+    let mut lines: Vec<String> = opportunities
+        .iter()
+        .map(|opportunity| {
+            format!(
+                "Arbitrage: pools={}, amount_in={}, amount_out={}, gross_profit={}, network_fee={} SOL, net_profit={}",
+                opportunity
+                    .route
+                    .hops
+                    .iter()
+                    .map(|hop| hop.pool.pool_state.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" -> "),
+                format_ui_amount(opportunity.route.amount_in, decimals),
+                format_ui_amount(opportunity.route.amount_out, decimals),
+                format_ui_amount(opportunity.gross_profit, decimals),
+                format_ui_amount(opportunity.network_fee, native_mint::DECIMALS),
+                format_ui_amount(opportunity.net_profit, decimals)
+            )
+        })
+        .collect();
+    if lines.is_empty() {
+        lines.push("No profitable round trip found".to_string());
+    }
 
-        Ok(signature)
+    let mut json = json!({
+        "opportunities": opportunities
+            .iter()
+            .map(|opportunity| json!({
+                "pools": opportunity
+                    .route
+                    .hops
+                    .iter()
+                    .map(|hop| hop.pool.pool_state.to_string())
+                    .collect::<Vec<_>>(),
+                "amount_in": opportunity.route.amount_in,
+                "amount_out": opportunity.route.amount_out,
+                "minimum_amount_out": opportunity.route.minimum_amount_out,
+                "gross_profit": opportunity.gross_profit,
+                "network_fee": opportunity.network_fee,
+                "network_fee_in_mint": opportunity.network_fee_in_mint,
+                "net_profit": opportunity.net_profit,
+            }))
+            .collect::<Vec<_>>(),
+    });
+
+    if let (true, Some(best)) = (args.execute, opportunities.first()) {
+        let sent = raydium.execute_arbitrage(best)?;
+        lines.push(format!("Executed arbitrage: signature={}", sent.signature));
+        json["signature"] = json!(sent.signature.to_string());
     }
 
-    /// Dynamically calculate token amounts needed for deposit or expected from withdrawal.
-    fn calculate_token_amounts(
-        &self,
-        pool_state: Pubkey,
-        lp_token_amount: u64,
-        deposit: bool,
-    ) -> Result<(u64, u64)> {
-        let pool_liquidity = self.get_pool_liquidity(pool_state)?;
-
-        // Calculate base amounts using Raydium's CurveCalculator.
-        let results = CurveCalculator::lp_tokens_to_trading_tokens(
-            u128::from(lp_token_amount),
-            u128::from(pool_liquidity.lp_supply),
-            u128::from(pool_liquidity.token_0_amount),
-            u128::from(pool_liquidity.token_1_amount),
-            RoundDirection::Ceiling,
-        )
-        .ok_or(anyhow!("failed to calculate amounts"))?;
-
-        if results.token_0_amount > u64::MAX as u128 || results.token_1_amount > u64::MAX as u128 {
-            return Err(anyhow!("token amount too large for u64"));
-        }
-        let token_0_amount = results.token_0_amount as u64;
-        let token_1_amount = results.token_1_amount as u64;
+    Ok(Output { lines, json })
+}
 
-        const SLIPPAGE: f64 = 0.01; // Hardcoded for simplicity.
+fn arbitrage_watch(raydium: &RaydiumIntegration, args: &ArbitrageArgs, json: bool) -> Result<()> {
+    let interval = Duration::from_secs(args.interval.unwrap_or_default());
+
+    loop {
+        // A failed scan or execution is reported and retried on the next interval.
+        match arbitrage(raydium, args) {
+            Ok(output) if json => println!("{}", output.json),
+            Ok(output) => {
+                for line in output.lines {
+                    println!("{line}");
+                }
+            }
+            Err(e) => eprintln!("Arbitrage scan failed: {e:#}"),
+        }
+        thread::sleep(interval);
+    }
+}
 
-        if deposit {
-            // For deposits, add slippage to get maximum amounts.
-            let max_amount_0 = (token_0_amount as f64 * (1.0 + SLIPPAGE)).ceil() as u64;
-            let max_amount_1 = (token_1_amount as f64 * (1.0 + SLIPPAGE)).ceil() as u64;
-            Ok((max_amount_0, max_amount_1))
-        } else {
-            // For withdrawals, subtract slippage to get minimum amounts.
-            let min_amount_0 = (token_0_amount as f64 * (1.0 - SLIPPAGE)).floor() as u64;
-            let min_amount_1 = (token_1_amount as f64 * (1.0 - SLIPPAGE)).floor() as u64;
-            Ok((min_amount_0, min_amount_1))
+fn compound(raydium: &RaydiumIntegration, args: &CompoundArgs) -> Result<Output> {
+    let (Some(mint_a), Some(mint_b)) = (args.mint_a, args.mint_b) else {
+        return compound_all(raydium, args);
+    };
+    let (amm_config_key, _) = raydium.get_amm_config_by_index(args.config_index)?;
+    let pool_state = raydium.get_pool_address(amm_config_key, mint_a, mint_b);
+
+    let mut lines = Vec::new();
+    let mut entries = Vec::new();
+    if args.dry_run {
+        let plan = raydium.plan_compound(pool_state, args.balance)?;
+        lines.push(compound_plan_line(&plan));
+        entries.push(compound_plan_json(&plan));
+    } else {
+        match raydium.compound(pool_state, args.balance)? {
+            Some(compounding) => compounding_output(&compounding, &mut lines, &mut entries),
+            None => lines.push(format!("Compound: pool={pool_state}, nothing to deposit")),
         }
-        // NOTE: Token-2022 transfer fee calculations are skipped for simplicity.
     }
 
-    /// Lists available AMM configurations.
-    pub fn list_amm_configs(&self) -> Result<()> {
-        let configs: Vec<(Pubkey, AmmConfig)> =
-            self.program.accounts(vec![])?.into_iter().collect();
-
-        for (address, config) in configs {
-            log::info!(
-                "AMM config {}: index={}, trade_fee_rate={}, protocol_fee_rate={}",
-                address,
-                config.index,
-                config.trade_fee_rate,
-                config.protocol_fee_rate
-            );
+    Ok(Output {
+        lines,
+        json: json!({ "pools": entries }),
+    })
+}
+
+fn compound_all(raydium: &RaydiumIntegration, args: &CompoundArgs) -> Result<Output> {
+    let mut lines = Vec::new();
+    let mut entries = Vec::new();
+
+    let summary = if args.dry_run {
+        let mut summary = CompoundSummary::default();
+        for position in raydium.get_lp_positions()? {
+            match raydium.plan_compound(position.pool_state, args.balance) {
+                Ok(plan) => {
+                    lines.push(compound_plan_line(&plan));
+                    entries.push(compound_plan_json(&plan));
+                }
+                Err(error) => summary
+                    .failed
+                    .push(CompoundFailure::new(position.pool_state, error)),
+            }
+        }
+        summary
+    } else {
+        raydium.compound_all(args.balance)?
+    };
+
+    for compounding in &summary.compounded {
+        compounding_output(compounding, &mut lines, &mut entries);
+    }
+    let mut failed = Vec::new();
+    for failure in &summary.failed {
+        let swap_signature = failure.swap.as_ref().map(|swap| swap.signature.to_string());
+        if let Some(signature) = &swap_signature {
+            lines.push(format!("Sent balancing swap: signature={signature}"));
         }
-        Ok(())
+        lines.push(format!(
+            "Failed: pool={}, error={:#}",
+            failure.pool_state, failure.error
+        ));
+        failed.push(json!({
+            "pool_state": failure.pool_state.to_string(),
+            "swap_signature": swap_signature,
+            "error": format!("{:#}", failure.error),
+        }));
     }
 
-    /// Returns an AMM configuration for the specified index if it exists.
-    pub fn get_amm_config_by_index(&self, index: u16) -> Result<(Pubkey, AmmConfig)> {
-        let (amm_config_key, _) = Pubkey::find_program_address(
-            &[AMM_CONFIG_SEED.as_bytes(), &index.to_be_bytes()],
-            &self.program.id(),
-        );
+    Ok(Output {
+        lines,
+        json: json!({
+            "pools": entries,
+            "failed": failed,
+        }),
+    })
+}
 
-        let config = self.program.account::<AmmConfig>(amm_config_key)?;
-        Ok((amm_config_key, config))
+fn compounding_output(
+    compounding: &Compounding,
+    lines: &mut Vec<String>,
+    entries: &mut Vec<Value>,
+) {
+    let mut entry = compound_plan_json(&compounding.plan);
+    entry["lp_amount"] = json!(compounding.lp_amount);
+    entry["deposit_signature"] = json!(compounding.deposit.signature.to_string());
+    if let Some(swap) = &compounding.swap {
+        lines.push(format!("Sent balancing swap: signature={}", swap.signature));
+        entry["swap_signature"] = json!(swap.signature.to_string());
     }
+    lines.push(format!(
+        "Compounded: pool={}, lp_amount={}, signature={}",
+        compounding.plan.pool_state,
+        format_ui_amount(compounding.lp_amount, compounding.plan.lp_decimals),
+        compounding.deposit.signature
+    ));
+    entries.push(entry);
+}
 
-    /// Fetches the current liquidity amounts from a Raydium CP-AMM pool.
-    pub fn get_pool_liquidity(&self, pool_state: Pubkey) -> Result<PoolLiquidity> {
-        let pool_data = self
-            .program
-            .account::<PoolState>(pool_state)
-            .context("failed to fetch pool state")?;
+fn compound_plan_line(plan: &CompoundPlan) -> String {
+    let swap = match &plan.swap {
+        Some(swap) => {
+            let (decimals_in, decimals_out) = if swap.input_mint == plan.token_0_mint {
+                (plan.token_0_decimals, plan.token_1_decimals)
+            } else {
+                (plan.token_1_decimals, plan.token_0_decimals)
+            };
+            format!(
+                ", swap_input_mint={}, swap_amount_in={}, swap_amount_out={}",
+                swap.input_mint,
+                format_ui_amount(swap.amount_in, decimals_in),
+                format_ui_amount(swap.amount_out, decimals_out)
+            )
+        }
+        None => String::new(),
+    };
+    format!(
+        "Compound plan: pool={}, token_0_amount={}, token_1_amount={}{swap}, lp_amount={}",
+        plan.pool_state,
+        format_ui_amount(plan.token_0_amount, plan.token_0_decimals),
+        format_ui_amount(plan.token_1_amount, plan.token_1_decimals),
+        format_ui_amount(plan.lp_amount, plan.lp_decimals)
+    )
+}
 
-        let vault_accounts = self
-            .client_rpc
-            .get_multiple_accounts(&[pool_data.token_0_vault, pool_data.token_1_vault])?;
+fn compound_plan_json(plan: &CompoundPlan) -> Value {
+    json!({
+        "pool_state": plan.pool_state.to_string(),
+        "token_0_amount": plan.token_0_amount,
+        "token_1_amount": plan.token_1_amount,
+        "swap": plan.swap.map(|swap| json!({
+            "input_mint": swap.input_mint.to_string(),
+            "amount_in": swap.amount_in,
+            "amount_out": swap.amount_out,
+        })),
+        "lp_amount": plan.lp_amount,
+    })
+}
 
-        let [token_0_vault_account, token_1_vault_account] = match vault_accounts.as_slice() {
-            [Some(a), Some(b)] => [a, b],
-            _ => return Err(anyhow!("failed to fetch vault accounts")),
-        };
+fn strategy_runner<'a>(
+    raydium: &'a RaydiumIntegration,
+    args: &StrategyArgs,
+) -> Result<StrategyRunner<'a>> {
+    Ok(StrategyRunner::new(
+        raydium,
+        StrategyConfig::open(&args.file)?,
+        ActionJournal::open(JOURNAL_FILE)?,
+    )?
+    .with_dry_run(args.dry_run))
+}
 
-        let token_0_vault_info = Account::unpack(&token_0_vault_account.data)?;
-        let token_1_vault_info = Account::unpack(&token_1_vault_account.data)?;
+fn strategy(raydium: &RaydiumIntegration, args: &StrategyArgs) -> Result<Output> {
+    let actions = strategy_runner(raydium, args)?.run_once()?;
 
-        let (total_token_0_amount, total_token_1_amount) = pool_data
-            .vault_amount_without_fee(token_0_vault_info.amount, token_1_vault_info.amount);
+    let mut lines: Vec<String> = actions.iter().map(strategy_action_line).collect();
+    if lines.is_empty() {
+        lines.push("All positions are within their bands".to_string());
+    }
 
-        Ok(PoolLiquidity {
-            token_0_amount: total_token_0_amount,
-            token_1_amount: total_token_1_amount,
-            lp_supply: pool_data.lp_supply,
-        })
+    Ok(Output {
+        lines,
+        json: json!({ "actions": actions }),
+    })
+}
+
+fn strategy_watch(raydium: &RaydiumIntegration, args: &StrategyArgs, json: bool) -> Result<()> {
+    let print_actions = |actions: &[StrategyAction]| {
+        for action in actions {
+            if json {
+                println!("{}", json!(action));
+            } else {
+                println!("{}", strategy_action_line(action));
+            }
+        }
+    };
+
+    let mut runner = strategy_runner(raydium, args)?;
+    match args.interval {
+        Some(interval) => runner.run_on_schedule(Duration::from_secs(interval), print_actions),
+        None => runner.run_on_updates(print_actions),
     }
 }
 
-fn main() -> Result<()> {
-    env_logger::init();
-    let payer = Rc::new(
-        read_keypair_file(WALLET_FILE)
-            .map_err(|e| anyhow!("failed to read keypair file: {}", e))?,
-    );
-    let raydium = RaydiumIntegration::new(payer.clone(), Cluster::Devnet)?;
-
-    raydium.list_amm_configs()?;
-    const AMM_CONFIG_INDEX: u16 = 0;
-    log::info!("Using AMM config with index: {AMM_CONFIG_INDEX}");
-    let (amm_config_key, _) = raydium.get_amm_config_by_index(AMM_CONFIG_INDEX)?;
-
-    // STEP 1: Initialize the CP-AMM pool with 1000 of each token (6 decimals).
-
-    let token_a = Pubkey::from_str(TOKEN_A)?;
-    let token_b = Pubkey::from_str(TOKEN_B)?;
-    let (token_0_mint, token_1_mint) = order_tokens(token_a, token_b);
-    const TOKEN_0_AMOUNT: u64 = 1_000_000_000;
-    const TOKEN_1_AMOUNT: u64 = 1_000_000_000;
-    const OPEN_TIME: u64 = 0; // Immediatelly
-
-    let (signature, init_keys) = raydium.initialize_pool(
-        amm_config_key,
-        token_0_mint,
-        token_1_mint,
-        TOKEN_0_AMOUNT,
-        TOKEN_1_AMOUNT,
-        OPEN_TIME,
-    )?;
+fn strategy_action_line(action: &StrategyAction) -> String {
+    let (Some(kind), Some(position_value)) = (action.kind, action.position_value) else {
+        return format!(
+            "Strategy action: pool={}, target_value={}, error={}",
+            action.pool_state,
+            format_ui_amount(action.target_value, action.value_decimals),
+            action.error.as_deref().unwrap_or_default()
+        );
+    };
+
+    let outcome = match (&action.signature, &action.error) {
+        (Some(signature), _) => format!("signature={signature}"),
+        (None, Some(error)) => format!("error={error}"),
+        (None, None) => "dry_run=true".to_string(),
+    };
+    format!(
+        "Strategy action: pool={}, kind={:?}, lp_amount={}, position_value={}, target_value={}, {outcome}",
+        action.pool_state,
+        kind,
+        format_ui_amount(action.lp_amount, action.lp_decimals),
+        format_ui_amount(position_value, action.value_decimals),
+        format_ui_amount(action.target_value, action.value_decimals)
+    )
+}
 
-    log::info!(
-		"Initialization complete: {signature:?}, token_0_vault={}, token_1_vault={}, pool_state={}, pool_authority={}, lp_mint={}, creator_token_0={}, creator_token_1={}, creator_lp_ata={}", 
-		init_keys.token_0_vault,
-		init_keys.token_1_vault,
-		init_keys.pool_state,
-		init_keys.pool_authority,
-		init_keys.lp_mint,
-		init_keys.creator_token_0,
-		init_keys.creator_token_1,
-		init_keys.creator_lp_ata
-	);
-
-    let pool_liquidity = raydium.get_pool_liquidity(init_keys.pool_state)?;
-
-    log::info!(
-        "Current pool liquidity: token_0_amount={}, token_1_amount={}, lp_supply={}",
-        pool_liquidity.token_0_amount,
-        pool_liquidity.token_1_amount,
-        pool_liquidity.lp_supply
-    );
-
-    // STEP 2: Add liquidity to the CP-AMM pool based on how many LP tokens we want to receive.
-
-    const LP_TOKEN_AMOUNT: u64 = 10_000_000;
-
-    let signature = raydium.add_liquidity(
-        init_keys.pool_state,
-        init_keys.pool_authority,
-        init_keys.lp_mint,
-        token_0_mint,
-        token_1_mint,
-        init_keys.token_0_vault,
-        init_keys.token_1_vault,
-        init_keys.creator_token_0,
-        init_keys.creator_token_1,
-        init_keys.creator_lp_ata,
-        LP_TOKEN_AMOUNT,
+fn positions(raydium: &RaydiumIntegration, args: &PositionsArgs) -> Result<Output> {
+    let (Some(mint_a), Some(mint_b)) = (args.mint_a, args.mint_b) else {
+        let positions = raydium.get_lp_positions()?;
+        return Ok(Output {
+            lines: positions
+                .iter()
+                .map(|position| position_line(raydium, position))
+                .collect::<Result<_>>()?,
+            json: positions.iter().map(position_json).collect(),
+        });
+    };
+
+    let (amm_config_key, _) = raydium.get_amm_config_by_index(args.config_index)?;
+    let pool_state = raydium.get_pool_address(amm_config_key, mint_a, mint_b);
+    let position = raydium.get_lp_position(pool_state)?;
+    let report = raydium.get_position_report(pool_state)?;
+    let decimals = PoolDecimals::fetch(
+        raydium,
+        position.token_0_mint,
+        position.token_1_mint,
+        position.lp_mint,
     )?;
 
-    log::info!("Added liquidity: {signature}");
+    // Report values are denominated in token 1.
+    Ok(Output {
+        lines: vec![
+            position_line(raydium, &position)?,
+            format!(
+                "Position report: lp_amount={}, hold_value={}, position_value={}, impermanent_loss={:.4}%, fees_earned={}, unrealized_pnl={}, realized_pnl={}, untracked_value={}",
+                format_ui_amount(report.lp_amount, decimals.lp),
+                scale_ui_value(report.hold_value, decimals.token_1),
+                scale_ui_value(report.position_value, decimals.token_1),
+                report.impermanent_loss * 100.0,
+                scale_ui_value(report.fees_earned, decimals.token_1),
+                scale_ui_value(report.unrealized_pnl, decimals.token_1),
+                scale_ui_value(report.realized_pnl, decimals.token_1),
+                scale_ui_value(report.untracked_value, decimals.token_1)
+            ),
+        ],
+        json: json!({
+            "position": position_json(&position),
+            "report": {
+                "lp_amount": report.lp_amount,
+                "entry_token_0_amount": report.entry_token_0_amount,
+                "entry_token_1_amount": report.entry_token_1_amount,
+                "token_0_amount": report.token_0_amount,
+                "token_1_amount": report.token_1_amount,
+                "hold_value": report.hold_value,
+                "position_value": report.position_value,
+                "impermanent_loss": report.impermanent_loss,
+                "fees_earned": report.fees_earned,
+                "unrealized_pnl": report.unrealized_pnl,
+                "realized_pnl": report.realized_pnl,
+                "untracked_value": report.untracked_value,
+            },
+        }),
+    })
+}
 
-    let pool_liquidity = raydium.get_pool_liquidity(init_keys.pool_state)?;
+fn keystore_encrypt(args: &KeystoreEncryptArgs) -> Result<Output> {
+    let keypair = read_keypair_file(&args.input)
+        .map_err(|e| anyhow!("failed to read keypair file: {}", e))?;
 
-    log::info!(
-        "Current pool liquidity: token_0_amount={}, token_1_amount={}, lp_supply={}",
-        pool_liquidity.token_0_amount,
-        pool_liquidity.token_1_amount,
-        pool_liquidity.lp_supply
-    );
+    let passphrase = rpassword::prompt_password("New keystore passphrase: ")?;
+    if passphrase.is_empty() {
+        return Err(anyhow!("keystore passphrase must not be empty"));
+    }
+    if rpassword::prompt_password("Repeat passphrase: ")? != passphrase {
+        return Err(anyhow!("passphrases do not match"));
+    }
+    write_keystore(&args.output, &keypair, &passphrase)?;
+
+    Ok(Output {
+        lines: vec![
+            format!("Public key: {}", keypair.pubkey()),
+            format!("Keystore: {}", args.output.display()),
+            format!("Use it with --keypair keystore:{}", args.output.display()),
+        ],
+        json: json!({
+            "pubkey": keypair.pubkey().to_string(),
+            "keystore": args.output.display().to_string(),
+        }),
+    })
+}
 
-    // STEP 3: Remove the same amount of liquidity from the CP-AMM pool.
+/// Prints a sent operation with its events, or the multisig proposal created for it.
+fn sent_output(action: &str, sent: &SentTransaction, decimals_a: u8, decimals_b: u8) -> Output {
+    let decimals = vec![(decimals_a, decimals_b); sent.events.len()];
+    events_output(action, sent, &decimals)
+}
 
-    let signature = raydium.remove_liquidity(
-        init_keys.pool_state,
-        init_keys.pool_authority,
-        init_keys.lp_mint,
-        token_0_mint,
-        token_1_mint,
-        init_keys.token_0_vault,
-        init_keys.token_1_vault,
-        init_keys.creator_token_0,
-        init_keys.creator_token_1,
-        init_keys.creator_lp_ata,
-        LP_TOKEN_AMOUNT,
-    )?;
+/// Prints a sent operation with each event formatted with its own pair of decimals, or the
+/// multisig proposal created for it.
+fn events_output(action: &str, sent: &SentTransaction, decimals: &[(u8, u8)]) -> Output {
+    let Some(proposal) = &sent.proposal else {
+        let mut lines = vec![format!("{action}: {}", sent.signature)];
+        lines.extend(
+            sent.events
+                .iter()
+                .zip(decimals)
+                .map(|(event, (decimals_a, decimals_b))| {
+                    event_line(event, *decimals_a, *decimals_b)
+                }),
+        );
 
-    log::info!("Removed liquidity: {signature}");
+        return Output {
+            lines,
+            json: json!({
+                "signature": sent.signature.to_string(),
+                "events": sent.events.iter().map(event_json).collect::<Vec<_>>(),
+            }),
+        };
+    };
+
+    Output {
+        lines: vec![
+            format!("Proposed to multisig: {}", sent.signature),
+            format!("Multisig: {}", proposal.multisig),
+            format!("Vault: {}", proposal.vault),
+            format!("Transaction index: {}", proposal.transaction_index),
+            format!("Vault transaction: {}", proposal.transaction),
+            format!("Proposal: {}", proposal.proposal),
+        ],
+        json: json!({
+            "signature": sent.signature.to_string(),
+            "proposal": {
+                "multisig": proposal.multisig.to_string(),
+                "vault": proposal.vault.to_string(),
+                "transaction_index": proposal.transaction_index,
+                "transaction": proposal.transaction.to_string(),
+                "proposal": proposal.proposal.to_string(),
+            },
+        }),
+    }
+}
 
-    let pool_liquidity = raydium.get_pool_liquidity(init_keys.pool_state)?;
+/// Asks for confirmation on the terminal.
+fn confirm(prompt: &str) -> Result<bool> {
+    eprint!("{prompt} [y/N] ");
+    io::stderr().flush()?;
 
-    log::info!(
-        "Current pool liquidity: token_0_amount={}, token_1_amount={}, lp_supply={}",
-        pool_liquidity.token_0_amount,
-        pool_liquidity.token_1_amount,
-        pool_liquidity.lp_supply
-    );
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
 
-    // STEP 4: Add and remove liquidity from the CP-AMM pool in the same transaction.
+/// Decimals of the pool's token and LP mints used to print display amounts.
+struct PoolDecimals {
+    token_0: u8,
+    token_1: u8,
+    lp: u8,
+}
 
-    let signature = raydium.add_and_remove_liquidity(
-        init_keys.pool_state,
-        init_keys.pool_authority,
-        init_keys.lp_mint,
-        token_0_mint,
-        token_1_mint,
-        init_keys.token_0_vault,
-        init_keys.token_1_vault,
-        init_keys.creator_token_0,
-        init_keys.creator_token_1,
-        init_keys.creator_lp_ata,
-        LP_TOKEN_AMOUNT,
-    )?;
+impl PoolDecimals {
+    fn fetch(
+        raydium: &RaydiumIntegration,
+        token_0_mint: Pubkey,
+        token_1_mint: Pubkey,
+        lp_mint: Pubkey,
+    ) -> Result<Self> {
+        Ok(Self {
+            token_0: raydium.get_mint_decimals(token_0_mint)?,
+            token_1: raydium.get_mint_decimals(token_1_mint)?,
+            lp: raydium.get_mint_decimals(lp_mint)?,
+        })
+    }
+}
 
-    log::info!("Added and removed liquidity in the same transaction: {signature}");
+/// Resolves the accounts of the pool for two mints under an AMM config index.
+fn resolve_pool(raydium: &RaydiumIntegration, args: &PoolArgs) -> Result<InitializationKeys> {
+    let (amm_config_key, _) = raydium.get_amm_config_by_index(args.config_index)?;
+    let pool_state = raydium.get_pool_address(amm_config_key, args.mint_a, args.mint_b);
+    raydium.get_pool_keys(pool_state)
+}
 
-    let pool_liquidity = raydium.get_pool_liquidity(init_keys.pool_state)?;
+fn keys_lines(keys: &InitializationKeys) -> Vec<String> {
+    vec![
+        format!("Pool state: {}", keys.pool_state),
+        format!("Pool authority: {}", keys.pool_authority),
+        format!("LP mint: {}", keys.lp_mint),
+        format!("Token 0 vault: {}", keys.token_0_vault),
+        format!("Token 1 vault: {}", keys.token_1_vault),
+        format!("Owner token 0 account: {}", keys.creator_token_0),
+        format!("Owner token 1 account: {}", keys.creator_token_1),
+        format!("Owner LP account: {}", keys.creator_lp_ata),
+    ]
+}
 
-    log::info!(
-        "Current pool liquidity: token_0_amount={}, token_1_amount={}, lp_supply={}",
-        pool_liquidity.token_0_amount,
-        pool_liquidity.token_1_amount,
-        pool_liquidity.lp_supply
-    );
+fn keys_json(keys: &InitializationKeys) -> Value {
+    json!({
+        "pool_state": keys.pool_state.to_string(),
+        "pool_authority": keys.pool_authority.to_string(),
+        "lp_mint": keys.lp_mint.to_string(),
+        "token_0_vault": keys.token_0_vault.to_string(),
+        "token_1_vault": keys.token_1_vault.to_string(),
+        "creator_token_0": keys.creator_token_0.to_string(),
+        "creator_token_1": keys.creator_token_1.to_string(),
+        "creator_lp_ata": keys.creator_lp_ata.to_string(),
+    })
+}
 
-    Ok(())
+/// Formats an event with the decimals of the input and output (or token 0 and token 1) mints.
+fn event_line(event: &PoolEvent, decimals_a: u8, decimals_b: u8) -> String {
+    match event {
+        PoolEvent::Swap(swap) => format!(
+            "Swap event: input_amount={}, output_amount={}, input_transfer_fee={}, output_transfer_fee={}",
+            format_ui_amount(swap.input_amount, decimals_a),
+            format_ui_amount(swap.output_amount, decimals_b),
+            format_ui_amount(swap.input_transfer_fee, decimals_a),
+            format_ui_amount(swap.output_transfer_fee, decimals_b)
+        ),
+        PoolEvent::LpChange(change) => format!(
+            "LP change event ({:?}): token_0_amount={}, token_1_amount={}, token_0_transfer_fee={}, token_1_transfer_fee={}",
+            change.change_type,
+            format_ui_amount(change.token_0_amount, decimals_a),
+            format_ui_amount(change.token_1_amount, decimals_b),
+            format_ui_amount(change.token_0_transfer_fee, decimals_a),
+            format_ui_amount(change.token_1_transfer_fee, decimals_b)
+        ),
+    }
 }
 
-/// Helper function used to order tokens when creating the CP-AMM pool.
-fn order_tokens(token_a: Pubkey, token_b: Pubkey) -> (Pubkey, Pubkey) {
-    if token_a < token_b {
-        (token_a, token_b)
-    } else {
-        (token_b, token_a)
+fn event_json(event: &PoolEvent) -> Value {
+    match event {
+        PoolEvent::Swap(swap) => json!({
+            "type": "swap",
+            "pool_id": swap.pool_id.to_string(),
+            "input_vault_before": swap.input_vault_before,
+            "output_vault_before": swap.output_vault_before,
+            "input_amount": swap.input_amount,
+            "output_amount": swap.output_amount,
+            "input_transfer_fee": swap.input_transfer_fee,
+            "output_transfer_fee": swap.output_transfer_fee,
+            "base_input": swap.base_input,
+        }),
+        PoolEvent::LpChange(change) => json!({
+            "type": match change.change_type {
+                LpChangeType::Deposit => "deposit",
+                LpChangeType::Withdraw => "withdraw",
+            },
+            "pool_id": change.pool_id.to_string(),
+            "lp_amount_before": change.lp_amount_before,
+            "token_0_vault_before": change.token_0_vault_before,
+            "token_1_vault_before": change.token_1_vault_before,
+            "token_0_amount": change.token_0_amount,
+            "token_1_amount": change.token_1_amount,
+            "token_0_transfer_fee": change.token_0_transfer_fee,
+            "token_1_transfer_fee": change.token_1_transfer_fee,
+        }),
     }
 }
+
+fn position_line(raydium: &RaydiumIntegration, position: &LpPosition) -> Result<String> {
+    let decimals = PoolDecimals::fetch(
+        raydium,
+        position.token_0_mint,
+        position.token_1_mint,
+        position.lp_mint,
+    )?;
+
+    Ok(format!(
+        "LP position in pool {}: lp_amount={}, token_0_amount={}, token_1_amount={}, pool_share={:.4}%, value_in_token_0={}, value_in_token_1={}",
+        position.pool_state,
+        format_ui_amount(position.lp_amount, decimals.lp),
+        format_ui_amount(position.token_0_amount, decimals.token_0),
+        format_ui_amount(position.token_1_amount, decimals.token_1),
+        position.pool_share * 100.0,
+        scale_ui_value(position.value_in_token_0, decimals.token_0),
+        scale_ui_value(position.value_in_token_1, decimals.token_1)
+    ))
+}
+
+fn position_json(position: &LpPosition) -> Value {
+    json!({
+        "pool_state": position.pool_state.to_string(),
+        "token_0_mint": position.token_0_mint.to_string(),
+        "token_1_mint": position.token_1_mint.to_string(),
+        "lp_mint": position.lp_mint.to_string(),
+        "owner_lp": position.owner_lp.to_string(),
+        "lp_amount": position.lp_amount,
+        "token_0_amount": position.token_0_amount,
+        "token_1_amount": position.token_1_amount,
+        "pool_share": position.pool_share,
+        "value_in_token_0": position.value_in_token_0,
+        "value_in_token_1": position.value_in_token_1,
+    })
+}
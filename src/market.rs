@@ -0,0 +1,211 @@
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anyhow::{anyhow, Result};
+use raydium_cp_swap::{
+    curve::{CurveCalculator, TradeDirection},
+    states::AmmConfig,
+};
+
+use crate::{cache::PoolSnapshot, PoolLiquidity, RaydiumIntegration};
+
+#[derive(Debug)]
+pub struct SpotPrice {
+    /// Price of token 0 denominated in token 1 (adjusted by mint decimals).
+    pub token_0_price: f64,
+    /// Price of token 1 denominated in token 0 (adjusted by mint decimals).
+    pub token_1_price: f64,
+}
+
+#[derive(Debug)]
+pub struct PriceImpact {
+    /// Amount of the input token sold.
+    pub amount_in: u64,
+    /// Amount of the output token received.
+    pub amount_out: u64,
+    /// Trade fee charged on the input amount.
+    pub trade_fee: u64,
+    /// Spot price of the input token before the trade (adjusted by mint decimals).
+    pub spot_price: f64,
+    /// Average price of the input token over the trade (adjusted by mint decimals).
+    pub execution_price: f64,
+    /// Relative difference between the execution price and the spot price, including the trade fee.
+    pub price_impact: f64,
+}
+
+#[derive(Debug)]
+pub struct DepthLevel {
+    /// Maximum price impact allowed for this level.
+    pub price_impact: f64,
+    /// Largest input amount that stays within the price impact.
+    pub amount_in: u64,
+    /// Output amount received for the input amount.
+    pub amount_out: u64,
+}
+
+/// Pool data required to quote trades against the constant-product curve.
+pub(crate) struct Market {
+    pub(crate) liquidity: PoolLiquidity,
+    pub(crate) amm_config: AmmConfig,
+    pub(crate) decimals_0: u8,
+    pub(crate) decimals_1: u8,
+}
+
+impl Market {
+    /// Returns the input reserve, output reserve and their decimals for the trade direction.
+    fn sides(&self, direction: TradeDirection) -> (u64, u64, u8, u8) {
+        match direction {
+            TradeDirection::ZeroForOne => (
+                self.liquidity.token_0_amount,
+                self.liquidity.token_1_amount,
+                self.decimals_0,
+                self.decimals_1,
+            ),
+            TradeDirection::OneForZero => (
+                self.liquidity.token_1_amount,
+                self.liquidity.token_0_amount,
+                self.decimals_1,
+                self.decimals_0,
+            ),
+        }
+    }
+
+    /// Quotes a trade of `amount_in` input tokens in the given direction.
+    pub(crate) fn quote(&self, direction: TradeDirection, amount_in: u64) -> Result<PriceImpact> {
+        let (reserve_in, reserve_out, decimals_in, decimals_out) = self.sides(direction);
+        if reserve_in == 0 || reserve_out == 0 {
+            return Err(anyhow!("pool has no liquidity"));
+        }
+
+        let result = CurveCalculator::swap_base_input(
+            u128::from(amount_in),
+            u128::from(reserve_in),
+            u128::from(reserve_out),
+            self.amm_config.trade_fee_rate,
+            self.amm_config.protocol_fee_rate,
+            self.amm_config.fund_fee_rate,
+        )
+        .ok_or(anyhow!("failed to calculate swap amounts"))?;
+
+        let amount_out = u64::try_from(result.destination_amount_swapped)
+            .map_err(|_| anyhow!("token amount too large for u64"))?;
+        let trade_fee = u64::try_from(result.trade_fee)
+            .map_err(|_| anyhow!("token amount too large for u64"))?;
+
+        let spot_price = scale_price(
+            reserve_out as f64 / reserve_in as f64,
+            decimals_in,
+            decimals_out,
+        );
+        let execution_price = if amount_in == 0 {
+            spot_price
+        } else {
+            scale_price(
+                amount_out as f64 / amount_in as f64,
+                decimals_in,
+                decimals_out,
+            )
+        };
+
+        Ok(PriceImpact {
+            amount_in,
+            amount_out,
+            trade_fee,
+            spot_price,
+            execution_price,
+            price_impact: 1.0 - execution_price / spot_price,
+        })
+    }
+}
+
+impl From<&PoolSnapshot> for Market {
+    fn from(snapshot: &PoolSnapshot) -> Self {
+        Self {
+            liquidity: snapshot.liquidity,
+            amm_config: snapshot.amm_config.clone(),
+            decimals_0: snapshot.pool_data.mint_0_decimals,
+            decimals_1: snapshot.pool_data.mint_1_decimals,
+        }
+    }
+}
+
+impl PoolSnapshot {
+    /// Quotes a trade of `amount_in` input tokens in the given direction at the snapshot's slot.
+    pub fn quote(&self, direction: TradeDirection, amount_in: u64) -> Result<PriceImpact> {
+        Market::from(self).quote(direction, amount_in)
+    }
+}
+
+impl RaydiumIntegration {
+    /// Returns the spot price of both pool tokens adjusted by mint decimals.
+    pub fn get_spot_price(&self, pool_state: Pubkey) -> Result<SpotPrice> {
+        let market = self.get_market(pool_state)?;
+        let (reserve_0, reserve_1, decimals_0, decimals_1) =
+            market.sides(TradeDirection::ZeroForOne);
+        if reserve_0 == 0 || reserve_1 == 0 {
+            return Err(anyhow!("pool has no liquidity"));
+        }
+
+        Ok(SpotPrice {
+            token_0_price: scale_price(reserve_1 as f64 / reserve_0 as f64, decimals_0, decimals_1),
+            token_1_price: scale_price(reserve_0 as f64 / reserve_1 as f64, decimals_1, decimals_0),
+        })
+    }
+
+    /// Calculates the price impact of selling `amount_in` input tokens in the given direction.
+    pub fn get_price_impact(
+        &self,
+        pool_state: Pubkey,
+        direction: TradeDirection,
+        amount_in: u64,
+    ) -> Result<PriceImpact> {
+        self.get_market(pool_state)?.quote(direction, amount_in)
+    }
+
+    /// Builds a depth table with the largest input amount tradable before each price impact level.
+    ///
+    /// Price impact includes the trade fee, so levels below the fee rate have no depth.
+    pub fn get_depth(
+        &self,
+        pool_state: Pubkey,
+        direction: TradeDirection,
+        price_impacts: &[f64],
+    ) -> Result<Vec<DepthLevel>> {
+        let market = self.get_market(pool_state)?;
+
+        price_impacts
+            .iter()
+            .map(|&price_impact| {
+                // Price impact grows monotonically with the input amount so binary search for the bound.
+                let (mut low, mut high) = (0u64, u64::MAX);
+                while low < high {
+                    let mid = low + (high - low) / 2 + 1;
+                    match market.quote(direction, mid) {
+                        Ok(quote) if quote.price_impact <= price_impact => low = mid,
+                        _ => high = mid - 1,
+                    }
+                }
+
+                let amount_out = if low == 0 {
+                    0
+                } else {
+                    market.quote(direction, low)?.amount_out
+                };
+
+                Ok(DepthLevel {
+                    price_impact,
+                    amount_in: low,
+                    amount_out,
+                })
+            })
+            .collect()
+    }
+
+    /// Fetches the pool reserves, fee configuration and mint decimals at a single slot.
+    fn get_market(&self, pool_state: Pubkey) -> Result<Market> {
+        Ok(Market::from(&self.get_pool_snapshot(pool_state)?))
+    }
+}
+
+/// Converts a price in base units into a price in whole tokens.
+fn scale_price(raw_price: f64, decimals_in: u8, decimals_out: u8) -> f64 {
+    raw_price * 10f64.powi(i32::from(decimals_in) - i32::from(decimals_out))
+}
@@ -0,0 +1,375 @@
+use anchor_client::{
+    anchor_lang::{
+        prelude::borsh,
+        solana_program::{hash::hash, system_program},
+        AnchorSerialize,
+    },
+    solana_sdk::{message::Message, pubkey, pubkey::Pubkey},
+};
+use anyhow::{anyhow, Context, Result};
+use solana_program::instruction::{AccountMeta, Instruction};
+
+use crate::RaydiumIntegration;
+
+/// Squads v4 multisig program.
+pub const SQUADS_PROGRAM_ID: Pubkey = pubkey!("SQDS4ep65T869zMMBKyuUq6aD6EgTu8psMjkvj52pCf");
+
+const SEED_PREFIX: &[u8] = b"multisig";
+const SEED_VAULT: &[u8] = b"vault";
+const SEED_TRANSACTION: &[u8] = b"transaction";
+const SEED_PROPOSAL: &[u8] = b"proposal";
+
+/// Offset of `transaction_index` in the multisig account: discriminator, create key, config
+/// authority, threshold (u16) and time lock (u32).
+const TRANSACTION_INDEX_OFFSET: usize = 8 + 32 + 32 + 2 + 4;
+
+/// Squads v4 multisig whose vault owns the positions.
+///
+/// When attached to the integration, deposits, withdrawals and swaps use the vault as the owner
+/// of the token accounts and are not sent. Instead the payer, which must be a member with the
+/// initiate permission, creates a vault transaction and a proposal for the members to approve
+/// and execute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Multisig {
+    /// Multisig account.
+    pub multisig: Pubkey,
+    /// Index of the vault that owns the positions.
+    pub vault_index: u8,
+    /// Squads program.
+    pub program_id: Pubkey,
+}
+
+/// Vault transaction and proposal created for an operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MultisigProposal {
+    /// Multisig account.
+    pub multisig: Pubkey,
+    /// Vault executing the transaction.
+    pub vault: Pubkey,
+    /// Index of the transaction in the multisig.
+    pub transaction_index: u64,
+    /// Vault transaction account.
+    pub transaction: Pubkey,
+    /// Proposal account the members vote on.
+    pub proposal: Pubkey,
+}
+
+#[derive(AnchorSerialize)]
+struct VaultTransactionCreateArgs {
+    vault_index: u8,
+    ephemeral_signers: u8,
+    transaction_message: Vec<u8>,
+    memo: Option<String>,
+}
+
+#[derive(AnchorSerialize)]
+struct ProposalCreateArgs {
+    transaction_index: u64,
+    draft: bool,
+}
+
+impl Multisig {
+    /// Creates a multisig for the vault index under the Squads v4 program.
+    pub fn new(multisig: Pubkey, vault_index: u8) -> Self {
+        Self {
+            multisig,
+            vault_index,
+            program_id: SQUADS_PROGRAM_ID,
+        }
+    }
+
+    /// Returns the vault account that owns the positions.
+    pub fn vault(&self) -> Pubkey {
+        Pubkey::find_program_address(
+            &[
+                SEED_PREFIX,
+                self.multisig.as_ref(),
+                SEED_VAULT,
+                &[self.vault_index],
+            ],
+            &self.program_id,
+        )
+        .0
+    }
+
+    /// Returns the vault transaction account for a transaction index.
+    pub fn transaction(&self, transaction_index: u64) -> Pubkey {
+        Pubkey::find_program_address(
+            &[
+                SEED_PREFIX,
+                self.multisig.as_ref(),
+                SEED_TRANSACTION,
+                &transaction_index.to_le_bytes(),
+            ],
+            &self.program_id,
+        )
+        .0
+    }
+
+    /// Returns the proposal account for a transaction index.
+    pub fn proposal(&self, transaction_index: u64) -> Pubkey {
+        Pubkey::find_program_address(
+            &[
+                SEED_PREFIX,
+                self.multisig.as_ref(),
+                SEED_TRANSACTION,
+                &transaction_index.to_le_bytes(),
+                SEED_PROPOSAL,
+            ],
+            &self.program_id,
+        )
+        .0
+    }
+
+    /// Creates the instructions proposing the vault's instructions as a new vault transaction.
+    ///
+    /// The creator must be a member allowed to initiate transactions and pays the rent of the
+    /// transaction and proposal accounts.
+    pub fn proposal_instructions(
+        &self,
+        creator: &Pubkey,
+        transaction_index: u64,
+        instructions: &[Instruction],
+        memo: Option<String>,
+    ) -> Result<(Vec<Instruction>, MultisigProposal)> {
+        let vault = self.vault();
+        let transaction = self.transaction(transaction_index);
+        let proposal = self.proposal(transaction_index);
+
+        let vault_transaction_create = Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new(self.multisig, false),
+                AccountMeta::new(transaction, false),
+                AccountMeta::new_readonly(*creator, true),
+                AccountMeta::new(*creator, true),
+                AccountMeta::new_readonly(system_program::ID, false),
+            ],
+            data: instruction_data(
+                "vault_transaction_create",
+                &VaultTransactionCreateArgs {
+                    vault_index: self.vault_index,
+                    ephemeral_signers: 0,
+                    transaction_message: transaction_message(&vault, instructions)?,
+                    memo,
+                },
+            )?,
+        };
+
+        let proposal_create = Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(self.multisig, false),
+                AccountMeta::new(proposal, false),
+                AccountMeta::new_readonly(*creator, true),
+                AccountMeta::new(*creator, true),
+                AccountMeta::new_readonly(system_program::ID, false),
+            ],
+            data: instruction_data(
+                "proposal_create",
+                &ProposalCreateArgs {
+                    transaction_index,
+                    draft: false,
+                },
+            )?,
+        };
+
+        Ok((
+            vec![vault_transaction_create, proposal_create],
+            MultisigProposal {
+                multisig: self.multisig,
+                vault,
+                transaction_index,
+                transaction,
+                proposal,
+            },
+        ))
+    }
+}
+
+impl RaydiumIntegration {
+    /// Routes deposits, withdrawals and swaps through proposals to the multisig's vault.
+    pub fn with_multisig(mut self, multisig: Multisig) -> Self {
+        self.multisig = Some(multisig);
+        self
+    }
+
+    /// Returns the index the multisig assigns to its next transaction.
+    pub fn get_next_transaction_index(&self, multisig: &Multisig) -> Result<u64> {
+        let data = self
+            .client_rpc
+            .get_account_data(&multisig.multisig)
+            .context("failed to fetch multisig account")?;
+        next_transaction_index(&multisig.multisig, &data)
+    }
+}
+
+/// Reads the index of the next transaction from the multisig account data.
+fn next_transaction_index(multisig: &Pubkey, data: &[u8]) -> Result<u64> {
+    if data.get(..8) != Some(&hash(b"account:Multisig").to_bytes()[..8]) {
+        return Err(anyhow!("account {multisig} is not a Squads multisig"));
+    }
+    let index = data
+        .get(TRANSACTION_INDEX_OFFSET..TRANSACTION_INDEX_OFFSET + 8)
+        .ok_or(anyhow!("multisig account data too short"))?;
+
+    Ok(u64::from_le_bytes(index.try_into()?) + 1)
+}
+
+/// Serializes an Anchor instruction's discriminator and arguments.
+fn instruction_data(name: &str, args: &impl AnchorSerialize) -> Result<Vec<u8>> {
+    let mut data = hash(format!("global:{name}").as_bytes()).to_bytes()[..8].to_vec();
+    args.serialize(&mut data)
+        .context("failed to serialize multisig instruction")?;
+    Ok(data)
+}
+
+/// Serializes the instructions as the Squads `TransactionMessage` executed by the vault.
+///
+/// The layout matches a legacy message, with single byte lengths for the account keys, the
+/// instructions and their accounts, and two byte lengths for instruction data.
+fn transaction_message(vault: &Pubkey, instructions: &[Instruction]) -> Result<Vec<u8>> {
+    let message = Message::new(instructions, Some(vault));
+    let header = message.header;
+    let num_signers = header.num_required_signatures;
+    let num_keys = u8::try_from(message.account_keys.len())
+        .context("too many accounts for a vault transaction")?;
+
+    let mut data = vec![
+        num_signers,
+        num_signers - header.num_readonly_signed_accounts,
+        num_keys - num_signers - header.num_readonly_unsigned_accounts,
+        num_keys,
+    ];
+    for key in &message.account_keys {
+        data.extend_from_slice(key.as_ref());
+    }
+
+    data.push(u8::try_from(message.instructions.len()).context("too many instructions")?);
+    for instruction in &message.instructions {
+        data.push(instruction.program_id_index);
+        data.push(u8::try_from(instruction.accounts.len()).context("too many accounts")?);
+        data.extend_from_slice(&instruction.accounts);
+        data.extend_from_slice(
+            &u16::try_from(instruction.data.len())
+                .context("instruction data too long")?
+                .to_le_bytes(),
+        );
+        data.extend_from_slice(&instruction.data);
+    }
+
+    // No address lookup tables.
+    data.push(0);
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::{instructions::deposit_instruction, InitializationKeys};
+
+    #[test]
+    fn serializes_deposit_as_transaction_message() {
+        let vault = Pubkey::new_unique();
+        let keys = InitializationKeys {
+            token_0_vault: Pubkey::new_unique(),
+            token_1_vault: Pubkey::new_unique(),
+            pool_state: Pubkey::new_unique(),
+            pool_authority: Pubkey::new_unique(),
+            lp_mint: Pubkey::new_unique(),
+            creator_token_0: Pubkey::new_unique(),
+            creator_token_1: Pubkey::new_unique(),
+            creator_lp_ata: Pubkey::new_unique(),
+        };
+        let (token_0_mint, token_1_mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let deposit = deposit_instruction(
+            &raydium_cp_swap::id(),
+            &vault,
+            &keys,
+            token_0_mint,
+            token_1_mint,
+            1_000,
+            2_000,
+            3_000,
+        );
+
+        let data = transaction_message(&vault, &[deposit.clone()]).unwrap();
+
+        // The vault is the only signer and, as the fee payer, writable. The pool state, the
+        // vault's three token accounts, both pool vaults and the LP mint are written, while the
+        // authority, both token programs, both mints and the Raydium program are read.
+        let writable = HashSet::from([
+            keys.pool_state,
+            keys.creator_lp_ata,
+            keys.creator_token_0,
+            keys.creator_token_1,
+            keys.token_0_vault,
+            keys.token_1_vault,
+            keys.lp_mint,
+        ]);
+        let readonly = HashSet::from([
+            keys.pool_authority,
+            spl_token::id(),
+            spl_token_2022::id(),
+            token_0_mint,
+            token_1_mint,
+            raydium_cp_swap::id(),
+        ]);
+        assert_eq!(data[..4], [1, 1, 7, 14]);
+
+        let (account_keys, rest) = data[4..].split_at(14 * 32);
+        let account_keys: Vec<Pubkey> = account_keys
+            .chunks(32)
+            .map(|key| Pubkey::try_from(key).unwrap())
+            .collect();
+        assert_eq!(account_keys[0], vault);
+        assert_eq!(
+            account_keys[1..8].iter().copied().collect::<HashSet<_>>(),
+            writable
+        );
+        assert_eq!(
+            account_keys[8..].iter().copied().collect::<HashSet<_>>(),
+            readonly
+        );
+
+        // One instruction: program index, u8 account count and indexes, u16 data length and
+        // data, followed by an empty list of address lookup tables.
+        assert_eq!(rest[0], 1);
+        assert_eq!(account_keys[usize::from(rest[1])], raydium_cp_swap::id());
+        assert_eq!(usize::from(rest[2]), deposit.accounts.len());
+        let (indexes, rest) = rest[3..].split_at(deposit.accounts.len());
+        let accounts: Vec<Pubkey> = indexes
+            .iter()
+            .map(|&index| account_keys[usize::from(index)])
+            .collect();
+        let expected: Vec<Pubkey> = deposit.accounts.iter().map(|meta| meta.pubkey).collect();
+        assert_eq!(accounts, expected);
+
+        assert_eq!(deposit.data.len(), 8 + 3 * 8);
+        assert_eq!(rest[..2], [32, 0]);
+        assert_eq!(rest[2..34], deposit.data[..]);
+        assert_eq!(rest[34..], [0]);
+    }
+
+    #[test]
+    fn reads_transaction_index_after_multisig_header() {
+        let multisig = Pubkey::new_unique();
+        let mut data = hash(b"account:Multisig").to_bytes()[..8].to_vec();
+        data.extend_from_slice(Pubkey::new_unique().as_ref()); // Create key.
+        data.extend_from_slice(Pubkey::default().as_ref()); // Config authority.
+        data.extend_from_slice(&2u16.to_le_bytes()); // Threshold.
+        data.extend_from_slice(&3_600u32.to_le_bytes()); // Time lock.
+        assert_eq!(data.len(), TRANSACTION_INDEX_OFFSET);
+        data.extend_from_slice(&41u64.to_le_bytes()); // Transaction index.
+        data.extend_from_slice(&40u64.to_le_bytes()); // Stale transaction index.
+
+        assert_eq!(next_transaction_index(&multisig, &data).unwrap(), 42);
+        assert!(next_transaction_index(&multisig, &data[..TRANSACTION_INDEX_OFFSET + 4]).is_err());
+
+        data[0] ^= 1;
+        assert!(next_transaction_index(&multisig, &data).is_err());
+    }
+}
@@ -0,0 +1,390 @@
+use std::sync::Arc;
+
+use anchor_client::{
+    anchor_lang::AccountDeserialize,
+    solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcTransactionConfig},
+    solana_sdk::{
+        commitment_config::CommitmentConfig,
+        pubkey::Pubkey,
+        signature::{Signature, Signer},
+        transaction::Transaction,
+    },
+    Cluster,
+};
+use anyhow::{anyhow, Context, Result};
+use raydium_cp_swap::{
+    curve::TradeDirection,
+    states::{AmmConfig, PoolState},
+};
+use solana_program::instruction::Instruction;
+use solana_transaction_status::{EncodedConfirmedTransactionWithStatusMeta, UiTransactionEncoding};
+use spl_associated_token_account::get_associated_token_address;
+use spl_token::native_mint;
+
+use crate::{
+    cache::vault_liquidity,
+    config::{Config, PriorityFee},
+    events::{transaction_events, SentTransaction},
+    instructions::{deposit_instructions, swap_instructions, withdrawal_instructions, PoolKeys},
+    market::Market,
+    minimum_amount_out, pda,
+    preflight::token_account_amount,
+    route::TransferFees,
+    signer::PayerSigner,
+    swap_direction, token_amounts_with_slippage, InitializationKeys, PoolLiquidity,
+};
+
+/// Asynchronous Raydium CP-AMM integration built on the nonblocking `RpcClient`.
+///
+/// The client is `Send + Sync`, which is asserted at compile time below, and cheap to clone, so
+/// clones can run pool operations concurrently from any async runtime. Unlike `RaydiumIntegration` it does not run pre-flight
+/// balance checks and does not record to a position ledger or operation store.
+#[derive(Clone)]
+pub struct AsyncRaydiumIntegration {
+    client_rpc: Arc<RpcClient>,
+    payer: PayerSigner,
+    program_id: Pubkey,
+    slippage_bps: u64,
+    priority_fee: PriorityFee,
+}
+
+// Clones are shared across the tasks of multi-threaded runtimes.
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<AsyncRaydiumIntegration>();
+};
+
+impl AsyncRaydiumIntegration {
+    /// Creates a new asynchronous Raydium integration for the cluster with the payer wallet.
+    pub fn new(payer: PayerSigner, cluster: Cluster) -> Self {
+        Self::from_config(
+            payer,
+            &Config {
+                cluster,
+                ..Config::default()
+            },
+        )
+    }
+
+    /// Creates a new asynchronous Raydium integration from resolved configuration settings.
+    pub fn from_config(payer: PayerSigner, config: &Config) -> Self {
+        Self {
+            client_rpc: Arc::new(RpcClient::new_with_commitment(
+                config.cluster.url().to_string(),
+                config.commitment,
+            )),
+            payer,
+            program_id: config.program_ids.raydium_cp_swap,
+            slippage_bps: config.slippage_bps,
+            priority_fee: config.priority_fee,
+        }
+    }
+
+    /// Sets the slippage applied to deposits, withdrawals and swaps in basis points.
+    pub fn with_slippage_bps(mut self, slippage_bps: u64) -> Self {
+        self.slippage_bps = slippage_bps;
+        self
+    }
+
+    /// Sets the compute unit price policy for sent transactions.
+    pub fn with_priority_fee(mut self, priority_fee: PriorityFee) -> Self {
+        self.priority_fee = priority_fee;
+        self
+    }
+
+    /// Returns the payer's public key.
+    pub fn payer(&self) -> Pubkey {
+        self.payer.pubkey()
+    }
+
+    /// Fetches and deserializes a pool's state.
+    pub async fn get_pool_state(&self, pool_state: Pubkey) -> Result<PoolState> {
+        self.get_program_account(pool_state)
+            .await
+            .context("failed to fetch pool state")
+    }
+
+    /// Returns an AMM configuration for the specified index if it exists.
+    pub async fn get_amm_config_by_index(&self, index: u16) -> Result<(Pubkey, AmmConfig)> {
+        let amm_config_key = pda::amm_config(&self.program_id, index).address;
+
+        let config = self
+            .get_program_account(amm_config_key)
+            .await
+            .context("failed to fetch amm config")?;
+        Ok((amm_config_key, config))
+    }
+
+    /// Returns the pool's accounts and the payer's token accounts for an existing pool.
+    pub async fn get_pool_keys(&self, pool_state: Pubkey) -> Result<InitializationKeys> {
+        let pool_data = self.get_pool_state(pool_state).await?;
+        Ok(self.pool_keys(pool_state, &pool_data))
+    }
+
+    /// Fetches the current liquidity amounts from a Raydium CP-AMM pool.
+    pub async fn get_pool_liquidity(&self, pool_state: Pubkey) -> Result<PoolLiquidity> {
+        let pool_data = self.get_pool_state(pool_state).await?;
+        self.get_vault_liquidity(&pool_data).await
+    }
+
+    /// Adds liquidity to a Raydium CP-AMM pool for the requested amount of LP tokens.
+    pub async fn add_liquidity(
+        &self,
+        pool_state: Pubkey,
+        lp_token_amount: u64,
+    ) -> Result<SentTransaction> {
+        let pool_data = self.get_pool_state(pool_state).await?;
+        let liquidity = self.get_vault_liquidity(&pool_data).await?;
+        let (max_token_0, max_token_1) =
+            token_amounts_with_slippage(&liquidity, lp_token_amount, self.slippage_bps, true)?;
+
+        let keys = self.pool_keys(pool_state, &pool_data);
+        let owner = self.payer.pubkey();
+
+        let tx_instructions = deposit_instructions(
+            &self.program_id,
+            &owner,
+            &keys,
+            pool_data.token_0_mint,
+            pool_data.token_1_mint,
+            lp_token_amount,
+            max_token_0,
+            max_token_1,
+            self.held_wsol(&[pool_data.token_0_mint, pool_data.token_1_mint])
+                .await?,
+        )?;
+
+        self.send_transaction(&tx_instructions)
+            .await
+            .context("failed to send add_liquidity transaction")
+    }
+
+    /// Removes liquidity from a Raydium CP-AMM pool by redeeming LP tokens.
+    pub async fn remove_liquidity(
+        &self,
+        pool_state: Pubkey,
+        lp_token_amount: u64,
+    ) -> Result<SentTransaction> {
+        let pool_data = self.get_pool_state(pool_state).await?;
+        let liquidity = self.get_vault_liquidity(&pool_data).await?;
+        let (min_token_0, min_token_1) =
+            token_amounts_with_slippage(&liquidity, lp_token_amount, self.slippage_bps, false)?;
+
+        let keys = self.pool_keys(pool_state, &pool_data);
+        let owner = self.payer.pubkey();
+
+        let tx_instructions = withdrawal_instructions(
+            &self.program_id,
+            &owner,
+            &keys,
+            pool_data.token_0_mint,
+            pool_data.token_1_mint,
+            pool_data.token_0_program,
+            pool_data.token_1_program,
+            lp_token_amount,
+            min_token_0,
+            min_token_1,
+            self.held_wsol(&[pool_data.token_0_mint, pool_data.token_1_mint])
+                .await?,
+        )?;
+
+        self.send_transaction(&tx_instructions)
+            .await
+            .context("failed to send remove_liquidity transaction")
+    }
+
+    /// Swaps an exact amount of input tokens through a Raydium CP-AMM pool.
+    pub async fn swap(
+        &self,
+        pool_state: Pubkey,
+        input_mint: Pubkey,
+        amount_in: u64,
+    ) -> Result<SentTransaction> {
+        let pool_data = self.get_pool_state(pool_state).await?;
+        let amm_config: AmmConfig = self
+            .get_program_account(pool_data.amm_config)
+            .await
+            .context("failed to fetch amm config")?;
+        let liquidity = self.get_vault_liquidity(&pool_data).await?;
+
+        let direction = swap_direction(pool_state, &pool_data, input_mint)?;
+        let market = Market {
+            liquidity,
+            amm_config,
+            decimals_0: pool_data.mint_0_decimals,
+            decimals_1: pool_data.mint_1_decimals,
+        };
+        let output_mint = match direction {
+            TradeDirection::ZeroForOne => pool_data.token_1_mint,
+            TradeDirection::OneForZero => pool_data.token_0_mint,
+        };
+        let transfer_fees = self
+            .get_transfer_fees(&[pool_data.token_0_mint, pool_data.token_1_mint])
+            .await?;
+        let quote =
+            transfer_fees.quote(&market, direction, &input_mint, &output_mint, amount_in)?;
+
+        let tx_instructions = swap_instructions(
+            &self.program_id,
+            &self.payer.pubkey(),
+            &PoolKeys::from_pool_state(&self.program_id, pool_state, &pool_data),
+            direction,
+            amount_in,
+            minimum_amount_out(quote.amount_out, self.slippage_bps),
+            self.held_wsol(&[pool_data.token_0_mint, pool_data.token_1_mint])
+                .await?,
+        )?;
+
+        self.send_transaction(&tx_instructions)
+            .await
+            .context("failed to send swap transaction")
+    }
+
+    /// Fetches and deserializes an account owned by the program.
+    async fn get_program_account<T: AccountDeserialize>(&self, address: Pubkey) -> Result<T> {
+        let account = self.client_rpc.get_account(&address).await?;
+        if account.owner != self.program_id {
+            return Err(anyhow!("account {address} is not owned by the program"));
+        }
+        Ok(T::try_deserialize(&mut account.data.as_slice())?)
+    }
+
+    /// Returns whether the payer's WSOL ATA holds WSOL, checked only if any mint is the native
+    /// mint, as `RaydiumIntegration::held_wsol` does.
+    async fn held_wsol(&self, mints: &[Pubkey]) -> Result<bool> {
+        if !mints.contains(&native_mint::id()) {
+            return Ok(false);
+        }
+
+        let wsol_account = get_associated_token_address(&self.payer.pubkey(), &native_mint::id());
+        let account = self
+            .client_rpc
+            .get_account_with_commitment(&wsol_account, self.client_rpc.commitment())
+            .await?
+            .value;
+        Ok(token_account_amount(account.as_ref())? > 0)
+    }
+
+    /// Reads the current Token-2022 transfer fee configuration of the mints that have one.
+    async fn get_transfer_fees(&self, mints: &[Pubkey]) -> Result<TransferFees> {
+        let epoch = self
+            .client_rpc
+            .get_epoch_info()
+            .await
+            .context("failed to get epoch info")?
+            .epoch;
+        let accounts = self
+            .client_rpc
+            .get_multiple_accounts(mints)
+            .await
+            .context("failed to fetch mint accounts")?;
+
+        TransferFees::from_mint_accounts(
+            epoch,
+            mints
+                .iter()
+                .zip(&accounts)
+                .filter_map(|(mint, account)| Some((*mint, account.as_ref()?))),
+        )
+    }
+
+    /// Reads the current vault balances for an already fetched pool state.
+    async fn get_vault_liquidity(&self, pool_data: &PoolState) -> Result<PoolLiquidity> {
+        let vault_accounts = self
+            .client_rpc
+            .get_multiple_accounts(&[pool_data.token_0_vault, pool_data.token_1_vault])
+            .await?;
+        vault_liquidity(pool_data, &vault_accounts)
+    }
+
+    /// Returns the pool's accounts and the payer's token accounts for a fetched pool state.
+    fn pool_keys(&self, pool_state: Pubkey, pool_data: &PoolState) -> InitializationKeys {
+        PoolKeys::from_pool_state(&self.program_id, pool_state, pool_data)
+            .owner_keys(&self.payer.pubkey())
+    }
+
+    /// Signs and sends a transaction, decoding the events it emitted.
+    async fn send_transaction(&self, instructions: &[Instruction]) -> Result<SentTransaction> {
+        let mut tx_instructions = self.priority_fee_instructions(instructions).await?;
+        tx_instructions.extend_from_slice(instructions);
+
+        let recent_blockhash = self
+            .client_rpc
+            .get_latest_blockhash()
+            .await
+            .context("failed to get recent blockhash")?;
+
+        // Remote signers block on HTTP requests, which must not run on the runtime's workers.
+        let payer = self.payer.clone();
+        let transaction = tokio::task::spawn_blocking(move || -> Result<Transaction> {
+            let mut transaction =
+                Transaction::new_with_payer(&tx_instructions, Some(&payer.pubkey()));
+            transaction
+                .try_sign(&[payer.as_ref() as &dyn Signer], recent_blockhash)
+                .context("failed to sign transaction")?;
+            Ok(transaction)
+        })
+        .await
+        .context("signing task failed")??;
+
+        let signature = self
+            .client_rpc
+            .send_and_confirm_transaction(&transaction)
+            .await?;
+
+        let events = match self.get_confirmed_transaction(signature).await {
+            Ok(confirmed) => transaction_events(&self.program_id, &confirmed).unwrap_or_else(|e| {
+                log::warn!("Failed to decode events of transaction {signature}: {e}");
+                Vec::new()
+            }),
+            Err(e) => {
+                log::warn!("Failed to fetch transaction {signature}: {e}");
+                Vec::new()
+            }
+        };
+
+        Ok(SentTransaction {
+            signature,
+            events,
+            proposal: None,
+        })
+    }
+
+    /// Creates the compute budget instructions for the priority fee policy.
+    async fn priority_fee_instructions(
+        &self,
+        instructions: &[Instruction],
+    ) -> Result<Vec<Instruction>> {
+        let recent_fees = match self.priority_fee.recent_fee_accounts(instructions) {
+            Some(accounts) => self
+                .client_rpc
+                .get_recent_prioritization_fees(&accounts)
+                .await
+                .context("failed to get recent prioritization fees")?
+                .into_iter()
+                .map(|fee| fee.prioritization_fee)
+                .collect(),
+            None => Vec::new(),
+        };
+
+        Ok(self.priority_fee.compute_budget_instructions(recent_fees))
+    }
+
+    /// Fetches a confirmed transaction with its status meta.
+    async fn get_confirmed_transaction(
+        &self,
+        signature: Signature,
+    ) -> Result<EncodedConfirmedTransactionWithStatusMeta> {
+        Ok(self
+            .client_rpc
+            .get_transaction_with_config(
+                &signature,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::Base64),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    max_supported_transaction_version: Some(0),
+                },
+            )
+            .await?)
+    }
+}
@@ -0,0 +1,242 @@
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anyhow::{anyhow, Context, Result};
+use raydium_cp_swap::states::{Observation, ObservationState, PoolState, OBSERVATION_NUM};
+
+use crate::RaydiumIntegration;
+
+/// Scale of the Q32 fixed point prices accumulated by the pool's observations.
+const Q32: f64 = 4_294_967_296.0;
+
+#[derive(Debug)]
+pub struct PoolTwap {
+    /// Timestamp of the oldest observation used for the average.
+    pub start_timestamp: u64,
+    /// Timestamp of the newest observation used for the average.
+    pub end_timestamp: u64,
+    /// Average price of token 0 denominated in token 1 (adjusted by mint decimals).
+    pub token_0_price: f64,
+    /// Average price of token 1 denominated in token 0 (adjusted by mint decimals).
+    pub token_1_price: f64,
+}
+
+impl PoolTwap {
+    /// Length of the window actually covered by the observations, in seconds.
+    pub fn window(&self) -> u64 {
+        self.end_timestamp - self.start_timestamp
+    }
+}
+
+impl RaydiumIntegration {
+    /// Computes time-weighted average prices over the requested window from the pool's observations.
+    pub fn get_pool_twap(&self, pool_state: Pubkey, window: u64) -> Result<PoolTwap> {
+        let pool_data = self
+            .program
+            .account::<PoolState>(pool_state)
+            .context("failed to fetch pool state")?;
+
+        let observation_data = self
+            .program
+            .account::<ObservationState>(pool_data.observation_key)
+            .context("failed to fetch observation state")?;
+
+        // Copy the ring buffer out of the packed account before indexing into it.
+        let observations = observation_data.observations;
+        let observation_index = usize::from(observation_data.observation_index);
+
+        let (start, end) = observation_window(&observations, observation_index, window).ok_or(
+            anyhow!("not enough observation history for pool {pool_state}"),
+        )?;
+
+        Ok(twap(
+            &start,
+            &end,
+            pool_data.mint_0_decimals,
+            pool_data.mint_1_decimals,
+        ))
+    }
+}
+
+/// Averages the prices accumulated between two observations.
+fn twap(start: &Observation, end: &Observation, decimals_0: u8, decimals_1: u8) -> PoolTwap {
+    // Observations only advance on swaps, so the window ends at the most recent swap.
+    let elapsed = u128::from(end.block_timestamp - start.block_timestamp);
+    let token_0_price_x32 = end
+        .cumulative_token_0_price_x32
+        .wrapping_sub(start.cumulative_token_0_price_x32)
+        / elapsed;
+    let token_1_price_x32 = end
+        .cumulative_token_1_price_x32
+        .wrapping_sub(start.cumulative_token_1_price_x32)
+        / elapsed;
+
+    // Prices are accumulated in base units, scale them to whole tokens.
+    let decimals_0 = i32::from(decimals_0);
+    let decimals_1 = i32::from(decimals_1);
+    let token_0_price = token_0_price_x32 as f64 / Q32 * 10f64.powi(decimals_0 - decimals_1);
+    let token_1_price = token_1_price_x32 as f64 / Q32 * 10f64.powi(decimals_1 - decimals_0);
+
+    PoolTwap {
+        start_timestamp: start.block_timestamp,
+        end_timestamp: end.block_timestamp,
+        token_0_price,
+        token_1_price,
+    }
+}
+
+/// Selects the newest observation and the newest observation at least `window` seconds older than it.
+///
+/// If the ring buffer does not reach back far enough the oldest available observation is used instead.
+/// Returns `None` when fewer than two distinct observations exist.
+fn observation_window(
+    observations: &[Observation; OBSERVATION_NUM],
+    observation_index: usize,
+    window: u64,
+) -> Option<(Observation, Observation)> {
+    let end = *observations.get(observation_index)?;
+    if end.block_timestamp == 0 {
+        return None;
+    }
+
+    let target = end.block_timestamp.saturating_sub(window);
+    let mut start = end;
+
+    for offset in 1..OBSERVATION_NUM {
+        let observation =
+            observations[(observation_index + OBSERVATION_NUM - offset) % OBSERVATION_NUM];
+
+        // Stop at slots that were never written or that belong to a later lap of the buffer.
+        if observation.block_timestamp == 0 || observation.block_timestamp > start.block_timestamp {
+            break;
+        }
+
+        start = observation;
+        if start.block_timestamp <= target {
+            break;
+        }
+    }
+
+    if start.block_timestamp == end.block_timestamp {
+        return None;
+    }
+    Some((start, end))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn observation(
+        block_timestamp: u64,
+        token_0_price_x32: u128,
+        token_1_price_x32: u128,
+    ) -> Observation {
+        Observation {
+            block_timestamp,
+            cumulative_token_0_price_x32: token_0_price_x32,
+            cumulative_token_1_price_x32: token_1_price_x32,
+        }
+    }
+
+    /// Ring buffer with observations at the timestamps, written from slot 0 onwards.
+    fn ring(timestamps: &[u64]) -> [Observation; OBSERVATION_NUM] {
+        let mut observations = [observation(0, 0, 0); OBSERVATION_NUM];
+        for (slot, &timestamp) in timestamps.iter().enumerate() {
+            observations[slot % OBSERVATION_NUM] = observation(timestamp, 0, 0);
+        }
+        observations
+    }
+
+    fn window_timestamps(
+        observations: &[Observation; OBSERVATION_NUM],
+        observation_index: usize,
+        window: u64,
+    ) -> Option<(u64, u64)> {
+        let (start, end) = observation_window(observations, observation_index, window)?;
+        Some((start.block_timestamp, end.block_timestamp))
+    }
+
+    #[test]
+    fn window_starts_at_newest_observation_old_enough() {
+        let observations = ring(&[1_000, 1_015, 1_030, 1_045, 1_060]);
+
+        // 1_060 - 30 = 1_030 is observed exactly, and 1_060 - 20 = 1_040 first reaches 1_030.
+        assert_eq!(
+            window_timestamps(&observations, 4, 30),
+            Some((1_030, 1_060))
+        );
+        assert_eq!(
+            window_timestamps(&observations, 4, 20),
+            Some((1_030, 1_060))
+        );
+        // Ending at an older observation ignores the newer ones.
+        assert_eq!(
+            window_timestamps(&observations, 2, 15),
+            Some((1_015, 1_030))
+        );
+    }
+
+    #[test]
+    fn short_history_falls_back_to_oldest_observation() {
+        let observations = ring(&[1_000, 1_015, 1_030]);
+        assert_eq!(
+            window_timestamps(&observations, 2, 3_600),
+            Some((1_000, 1_030))
+        );
+    }
+
+    #[test]
+    fn window_wraps_around_the_ring_buffer() {
+        // Slots 0 and 1 hold the newest lap, and slot 2 onwards the previous one.
+        let timestamps: Vec<u64> = (0..OBSERVATION_NUM as u64 + 2)
+            .map(|i| 1_000 + 10 * i)
+            .collect();
+        let observations = ring(&timestamps);
+        let newest = 1_000 + 10 * (OBSERVATION_NUM as u64 + 1);
+
+        assert_eq!(
+            window_timestamps(&observations, 1, 25),
+            Some((newest - 30, newest))
+        );
+        // The whole buffer reaches back to slot 2, the oldest of the previous lap.
+        assert_eq!(
+            window_timestamps(&observations, 1, u64::MAX),
+            Some((1_020, newest))
+        );
+    }
+
+    #[test]
+    fn window_needs_two_observations() {
+        assert_eq!(window_timestamps(&ring(&[]), 0, 60), None);
+        assert_eq!(window_timestamps(&ring(&[1_000]), 0, 60), None);
+        assert_eq!(window_timestamps(&ring(&[1_000, 1_000]), 1, 60), None);
+    }
+
+    #[test]
+    fn twap_of_accumulated_prices() {
+        // Token 0 traded at 2 and token 1 at 0.5 base units for 100 seconds.
+        let start = observation(1_000, 0, 0);
+        let end = observation(1_100, 200 << 32, 50 << 32);
+
+        let same_decimals = twap(&start, &end, 6, 6);
+        assert_eq!(same_decimals.window(), 100);
+        assert_eq!(same_decimals.token_0_price, 2.0);
+        assert_eq!(same_decimals.token_1_price, 0.5);
+
+        // With 9 decimals for token 0 and 6 for token 1, a whole token 0 is worth 2_000 whole
+        // token 1.
+        let scaled = twap(&start, &end, 9, 6);
+        assert_eq!(scaled.token_0_price, 2_000.0);
+        assert!((scaled.token_1_price - 0.0005).abs() < 1e-15);
+    }
+
+    #[test]
+    fn twap_across_accumulator_overflow() {
+        // The accumulators wrap around u128, and differences are taken modulo 2^128.
+        let start = observation(1_000, u128::MAX - (10 << 32) + 1, u128::MAX);
+        let end = observation(1_010, 30 << 32, (40 << 32) - 1);
+
+        let twap = twap(&start, &end, 6, 6);
+        assert_eq!(twap.token_0_price, 4.0);
+        assert_eq!(twap.token_1_price, 4.0);
+    }
+}
@@ -0,0 +1,255 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+};
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use raydium_cp_swap::{
+    states::{
+        pool::{POOL_LP_MINT_SEED, POOL_SEED, POOL_VAULT_SEED},
+        AMM_CONFIG_SEED, OBSERVATION_SEED,
+    },
+    AUTH_SEED,
+};
+
+/// Program derived address with its bump seed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pda {
+    /// Derived address.
+    pub address: Pubkey,
+    /// Bump seed the program signs with.
+    pub bump: u8,
+}
+
+/// Addresses of a pool derived from its AMM config and mints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolPdas {
+    /// Pool's state account.
+    pub pool_state: Pda,
+    /// Mint account for the pool's LP tokens.
+    pub lp_mint: Pda,
+    /// Pool's vault account for token 0.
+    pub token_0_vault: Pda,
+    /// Pool's vault account for token 1.
+    pub token_1_vault: Pda,
+    /// Pool's price observation account.
+    pub observation_state: Pda,
+}
+
+impl Pda {
+    fn find(seeds: &[&[u8]], program_id: &Pubkey) -> Self {
+        let (address, bump) = Pubkey::find_program_address(seeds, program_id);
+        Self { address, bump }
+    }
+}
+
+impl PoolPdas {
+    /// Derives the addresses of the pool for two mints, ordered as by `order_tokens`, under an
+    /// AMM config.
+    pub fn derive(
+        program_id: &Pubkey,
+        amm_config: &Pubkey,
+        token_0_mint: &Pubkey,
+        token_1_mint: &Pubkey,
+    ) -> Self {
+        let pool_state = pool_state(program_id, amm_config, token_0_mint, token_1_mint);
+
+        Self {
+            pool_state,
+            lp_mint: lp_mint(program_id, &pool_state.address),
+            token_0_vault: pool_vault(program_id, &pool_state.address, token_0_mint),
+            token_1_vault: pool_vault(program_id, &pool_state.address, token_1_mint),
+            observation_state: observation_state(program_id, &pool_state.address),
+        }
+    }
+}
+
+/// Returns the authority shared by all pools of the program.
+///
+/// The authority only depends on the program, so it is derived once per program and reused.
+pub fn authority(program_id: &Pubkey) -> Pda {
+    static AUTHORITIES: OnceLock<Mutex<HashMap<Pubkey, Pda>>> = OnceLock::new();
+
+    // The map only ever gains entries, so a poisoned lock still holds valid ones.
+    let mut authorities = AUTHORITIES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    *authorities
+        .entry(*program_id)
+        .or_insert_with(|| Pda::find(&[AUTH_SEED.as_bytes()], program_id))
+}
+
+/// Returns the AMM config for an index.
+pub fn amm_config(program_id: &Pubkey, index: u16) -> Pda {
+    Pda::find(
+        &[AMM_CONFIG_SEED.as_bytes(), &index.to_be_bytes()],
+        program_id,
+    )
+}
+
+/// Returns the pool's state account for two mints, ordered as by `order_tokens`, under an AMM
+/// config.
+pub fn pool_state(
+    program_id: &Pubkey,
+    amm_config: &Pubkey,
+    token_0_mint: &Pubkey,
+    token_1_mint: &Pubkey,
+) -> Pda {
+    Pda::find(
+        &[
+            POOL_SEED.as_bytes(),
+            amm_config.as_ref(),
+            token_0_mint.as_ref(),
+            token_1_mint.as_ref(),
+        ],
+        program_id,
+    )
+}
+
+/// Returns the pool's vault for one of its mints.
+pub fn pool_vault(program_id: &Pubkey, pool_state: &Pubkey, mint: &Pubkey) -> Pda {
+    Pda::find(
+        &[
+            POOL_VAULT_SEED.as_bytes(),
+            pool_state.as_ref(),
+            mint.as_ref(),
+        ],
+        program_id,
+    )
+}
+
+/// Returns the pool's LP mint.
+pub fn lp_mint(program_id: &Pubkey, pool_state: &Pubkey) -> Pda {
+    Pda::find(
+        &[POOL_LP_MINT_SEED.as_bytes(), pool_state.as_ref()],
+        program_id,
+    )
+}
+
+/// Returns the pool's price observation account.
+pub fn observation_state(program_id: &Pubkey, pool_state: &Pubkey) -> Pda {
+    Pda::find(
+        &[OBSERVATION_SEED.as_bytes(), pool_state.as_ref()],
+        program_id,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use anchor_client::{anchor_lang::AccountDeserialize, solana_sdk::program_pack::Pack};
+    use anyhow::Result;
+    use raydium_cp_swap::states::{AmmConfig, ObservationState, PoolState};
+    use solana_program::program_option::COption;
+
+    use super::*;
+    use crate::testing::LocalValidator;
+
+    /// Asserts that the bump signs for the address with the seeds it was derived from.
+    fn assert_signs(pda: &Pda, seeds: &[&[u8]], program_id: &Pubkey) {
+        let bump = [pda.bump];
+        let mut seeds = seeds.to_vec();
+        seeds.push(&bump);
+        assert_eq!(
+            Pubkey::create_program_address(&seeds, program_id).unwrap(),
+            pda.address
+        );
+    }
+
+    #[test]
+    #[ignore = "requires a local test validator"]
+    fn derives_accounts_of_created_pool() -> Result<()> {
+        let validator = LocalValidator::connect()?;
+        let (amm_config_key, keys) = validator.create_pool()?;
+        let program_id = validator.integration.program.id();
+        let rpc = &validator.integration.client_rpc;
+        let account_data = |address: &Pubkey| rpc.get_account_data(address).unwrap();
+
+        let pool_data = PoolState::try_deserialize(&mut account_data(&keys.pool_state).as_slice())?;
+        let config = AmmConfig::try_deserialize(&mut account_data(&amm_config_key).as_slice())?;
+        let observation = ObservationState::try_deserialize(
+            &mut account_data(&pool_data.observation_key).as_slice(),
+        )?;
+
+        let pdas = PoolPdas::derive(
+            &program_id,
+            &amm_config_key,
+            &pool_data.token_0_mint,
+            &pool_data.token_1_mint,
+        );
+        assert_eq!(pdas.pool_state.address, keys.pool_state);
+        assert_eq!(pdas.lp_mint.address, pool_data.lp_mint);
+        assert_eq!(pdas.token_0_vault.address, pool_data.token_0_vault);
+        assert_eq!(pdas.token_1_vault.address, pool_data.token_1_vault);
+        assert_eq!(pdas.observation_state.address, pool_data.observation_key);
+        assert_eq!(observation.pool_id, keys.pool_state);
+
+        // The program stores the bumps of the authority and the AMM config.
+        let authority = authority(&program_id);
+        assert_eq!(authority.address, keys.pool_authority);
+        assert_eq!(authority.bump, pool_data.auth_bump);
+        let config_pda = amm_config(&program_id, config.index);
+        assert_eq!(config_pda.address, amm_config_key);
+        assert_eq!(config_pda.bump, config.bump);
+
+        // The program created the other accounts at the canonical bumps of their seeds.
+        let pool_state = keys.pool_state.to_bytes();
+        assert_signs(
+            &pdas.pool_state,
+            &[
+                POOL_SEED.as_bytes(),
+                amm_config_key.as_ref(),
+                pool_data.token_0_mint.as_ref(),
+                pool_data.token_1_mint.as_ref(),
+            ],
+            &program_id,
+        );
+        assert_signs(
+            &pdas.lp_mint,
+            &[POOL_LP_MINT_SEED.as_bytes(), &pool_state],
+            &program_id,
+        );
+        assert_signs(
+            &pdas.token_0_vault,
+            &[
+                POOL_VAULT_SEED.as_bytes(),
+                &pool_state,
+                pool_data.token_0_mint.as_ref(),
+            ],
+            &program_id,
+        );
+        assert_signs(
+            &pdas.token_1_vault,
+            &[
+                POOL_VAULT_SEED.as_bytes(),
+                &pool_state,
+                pool_data.token_1_mint.as_ref(),
+            ],
+            &program_id,
+        );
+        assert_signs(
+            &pdas.observation_state,
+            &[OBSERVATION_SEED.as_bytes(), &pool_state],
+            &program_id,
+        );
+
+        // The vaults and the LP mint are controlled by the authority.
+        let lp_mint = spl_token::state::Mint::unpack(&account_data(&pool_data.lp_mint))?;
+        assert_eq!(lp_mint.mint_authority, COption::Some(authority.address));
+        for vault in [pool_data.token_0_vault, pool_data.token_1_vault] {
+            let vault = spl_token::state::Account::unpack(&account_data(&vault))?;
+            assert_eq!(vault.owner, authority.address);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn memoizes_authority() {
+        let program_id = Pubkey::new_unique();
+        let expected = Pda::find(&[AUTH_SEED.as_bytes()], &program_id);
+
+        assert_eq!(authority(&program_id), expected);
+        assert_eq!(authority(&program_id), expected);
+    }
+}
@@ -0,0 +1,220 @@
+use anchor_client::{
+    solana_client::{
+        rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+        rpc_filter::{Memcmp, RpcFilterType},
+    },
+    solana_sdk::{program_pack::Pack, pubkey::Pubkey, signature::Signer},
+};
+use anyhow::{anyhow, Context, Result};
+use raydium_cp_swap::{
+    curve::{CurveCalculator, RoundDirection},
+    states::PoolState,
+};
+use solana_account_decoder::UiAccountEncoding;
+use solana_program::program_option::COption;
+use spl_associated_token_account::get_associated_token_address;
+use spl_token::state::{Account, Mint};
+
+use crate::{pda, PoolLiquidity, RaydiumIntegration};
+
+/// Offset of the LP mint in the pool state account data (after the discriminator and four keys).
+const POOL_LP_MINT_OFFSET: usize = 8 + 32 * 4;
+
+/// Offset of the owner in the token account data (after the mint).
+const TOKEN_ACCOUNT_OWNER_OFFSET: usize = 32;
+
+#[derive(Debug)]
+pub struct LpPosition {
+    /// Pool's state account.
+    pub pool_state: Pubkey,
+    /// Mint account for the pool's token 0.
+    pub token_0_mint: Pubkey,
+    /// Mint account for the pool's token 1.
+    pub token_1_mint: Pubkey,
+    /// Mint account for the pool's LP tokens.
+    pub lp_mint: Pubkey,
+    /// Owner ATA for LP tokens.
+    pub owner_lp: Pubkey,
+    /// Amount of LP tokens held by the owner.
+    pub lp_amount: u64,
+    /// Amount of token 0 redeemable for the LP tokens.
+    pub token_0_amount: u64,
+    /// Amount of token 1 redeemable for the LP tokens.
+    pub token_1_amount: u64,
+    /// Fraction of the LP supply held by the owner.
+    pub pool_share: f64,
+    /// Value of the position in token 0 base units at the current pool price.
+    pub value_in_token_0: f64,
+    /// Value of the position in token 1 base units at the current pool price.
+    pub value_in_token_1: f64,
+}
+
+impl RaydiumIntegration {
+    /// Values the owner's LP position in a Raydium CP-AMM pool.
+    ///
+    /// Only LP tokens in the owner's ATA are counted, since withdrawals burn LP tokens from it.
+    pub fn get_lp_position(&self, pool_state: Pubkey) -> Result<LpPosition> {
+        let pool_data = self
+            .program
+            .account::<PoolState>(pool_state)
+            .context("failed to fetch pool state")?;
+
+        let lp_mint = pool_data.lp_mint;
+        let owner_lp = get_associated_token_address(&self.owner(), &lp_mint);
+
+        // A missing LP ATA means the owner holds no LP tokens.
+        let lp_amount = match self
+            .client_rpc
+            .get_account_with_commitment(&owner_lp, self.client_rpc.commitment())?
+            .value
+        {
+            Some(account) => Account::unpack(&account.data)?.amount,
+            None => 0,
+        };
+
+        let liquidity = self.get_vault_liquidity(&pool_data)?;
+        let (token_0_amount, token_1_amount) = redeemable_amounts(lp_amount, &liquidity)?;
+
+        let pool_share = if liquidity.lp_supply == 0 {
+            0.0
+        } else {
+            lp_amount as f64 / liquidity.lp_supply as f64
+        };
+
+        let (value_in_token_0, value_in_token_1) = if liquidity.token_0_amount == 0
+            || liquidity.token_1_amount == 0
+        {
+            (token_0_amount as f64, token_1_amount as f64)
+        } else {
+            let token_0_price = liquidity.token_1_amount as f64 / liquidity.token_0_amount as f64;
+            let value_in_token_1 = token_0_amount as f64 * token_0_price + token_1_amount as f64;
+            (value_in_token_1 / token_0_price, value_in_token_1)
+        };
+
+        Ok(LpPosition {
+            pool_state,
+            token_0_mint: pool_data.token_0_mint,
+            token_1_mint: pool_data.token_1_mint,
+            lp_mint,
+            owner_lp,
+            lp_amount,
+            token_0_amount,
+            token_1_amount,
+            pool_share,
+            value_in_token_0,
+            value_in_token_1,
+        })
+    }
+
+    /// Values the owner's LP positions in every Raydium CP-AMM pool where it holds LP tokens.
+    ///
+    /// As in `get_lp_position`, only LP tokens in the owner's ATAs are counted. Token accounts,
+    /// mints and pools that cannot be read are logged and skipped, so one bad entry does not
+    /// hide the other positions.
+    pub fn get_lp_positions(&self) -> Result<Vec<LpPosition>> {
+        let owner = self.owner();
+
+        let token_accounts = self
+            .client_rpc
+            .get_program_accounts_with_config(
+                &spl_token::id(),
+                RpcProgramAccountsConfig {
+                    filters: Some(vec![
+                        RpcFilterType::DataSize(Account::LEN as u64),
+                        RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                            TOKEN_ACCOUNT_OWNER_OFFSET,
+                            &owner.to_bytes(),
+                        )),
+                    ]),
+                    account_config: RpcAccountInfoConfig {
+                        encoding: Some(UiAccountEncoding::Base64),
+                        ..RpcAccountInfoConfig::default()
+                    },
+                    ..RpcProgramAccountsConfig::default()
+                },
+            )
+            .context("failed to fetch owner token accounts")?;
+
+        let mut mints = Vec::new();
+        for (address, account) in token_accounts {
+            let token_account = match Account::unpack(&account.data) {
+                Ok(token_account) => token_account,
+                Err(e) => {
+                    log::warn!("Skipping token account {address}: {e}");
+                    continue;
+                }
+            };
+            if token_account.amount > 0
+                && address == get_associated_token_address(&owner, &token_account.mint)
+            {
+                mints.push(token_account.mint);
+            }
+        }
+
+        // LP mints are owned by the pool authority, which filters out unrelated tokens cheaply.
+        let pool_authority = pda::authority(&self.program.id()).address;
+
+        let mut lp_mints = Vec::new();
+        for chunk in mints.chunks(100) {
+            let mint_accounts = self
+                .client_rpc
+                .get_multiple_accounts(chunk)
+                .context("failed to fetch mint accounts")?;
+            for (mint, account) in chunk.iter().zip(mint_accounts) {
+                let Some(account) = account else { continue };
+                let Ok(mint_data) = Mint::unpack(&account.data) else {
+                    continue;
+                };
+                if mint_data.mint_authority == COption::Some(pool_authority) {
+                    lp_mints.push(*mint);
+                }
+            }
+        }
+
+        let mut positions = Vec::new();
+        for lp_mint in lp_mints {
+            let pools: Vec<(Pubkey, PoolState)> =
+                match self.program.accounts(vec![RpcFilterType::Memcmp(
+                    Memcmp::new_base58_encoded(POOL_LP_MINT_OFFSET, &lp_mint.to_bytes()),
+                )]) {
+                    Ok(pools) => pools,
+                    Err(e) => {
+                        log::warn!("Skipping LP mint {lp_mint}: failed to fetch pool: {e}");
+                        continue;
+                    }
+                };
+
+            for (pool_state, _) in pools {
+                match self.get_lp_position(pool_state) {
+                    Ok(position) => positions.push(position),
+                    Err(e) => log::warn!("Skipping pool {pool_state}: {e:#}"),
+                }
+            }
+        }
+
+        Ok(positions)
+    }
+}
+
+/// Calculates the token amounts redeemable for LP tokens, rounded down as on withdrawal.
+pub fn redeemable_amounts(lp_amount: u64, liquidity: &PoolLiquidity) -> Result<(u64, u64)> {
+    if lp_amount == 0 || liquidity.lp_supply == 0 {
+        return Ok((0, 0));
+    }
+
+    let results = CurveCalculator::lp_tokens_to_trading_tokens(
+        u128::from(lp_amount),
+        u128::from(liquidity.lp_supply),
+        u128::from(liquidity.token_0_amount),
+        u128::from(liquidity.token_1_amount),
+        RoundDirection::Floor,
+    )
+    .ok_or(anyhow!("failed to calculate amounts"))?;
+
+    let token_0_amount = u64::try_from(results.token_0_amount)
+        .map_err(|_| anyhow!("token amount too large for u64"))?;
+    let token_1_amount = u64::try_from(results.token_1_amount)
+        .map_err(|_| anyhow!("token amount too large for u64"))?;
+
+    Ok((token_0_amount, token_1_amount))
+}
@@ -0,0 +1,245 @@
+use anchor_client::solana_sdk::{
+    account::Account as RpcAccount, message::Message, program_pack::Pack, pubkey::Pubkey,
+    signature::Signer,
+};
+use anyhow::{anyhow, Context, Result};
+use raydium_cp_swap::states::{AmmConfig, ObservationState, PoolState};
+use solana_program::instruction::Instruction;
+use spl_token::native_mint;
+use spl_token_2022::{
+    extension::{BaseStateWithExtensions, ExtensionType, StateWithExtensions},
+    state::{Account, Mint},
+};
+
+use crate::{amount::format_ui_amount, RaydiumIntegration};
+
+/// Tokens an operation draws from one of the owner's token accounts.
+pub(crate) struct TokenRequirement {
+    /// Mint of the tokens.
+    pub mint: Pubkey,
+    /// Owner's token account the tokens are drawn from.
+    pub account: Pubkey,
+    /// Maximum amount drawn in base units.
+    pub amount: u64,
+}
+
+impl RaydiumIntegration {
+    /// Checks that the owner's token balances and lamports cover a transaction.
+    ///
+    /// Native SOL requirements are checked against the owner's lamports since they are wrapped
+    /// in the same transaction. Lamports must also cover the transaction fee, rent for the ATAs
+    /// the instructions create and `extra_lamports` (e.g. rent for new program accounts).
+    pub(crate) fn check_balances(
+        &self,
+        instructions: &[Instruction],
+        tokens: &[TokenRequirement],
+        extra_lamports: u64,
+    ) -> Result<()> {
+        let mut shortfalls = Vec::new();
+        let mut required_lamports = extra_lamports;
+
+        for requirement in tokens {
+            if requirement.mint == native_mint::id() {
+                required_lamports = required_lamports
+                    .checked_add(requirement.amount)
+                    .ok_or_else(|| anyhow!("required lamports overflow"))?;
+                continue;
+            }
+
+            let balance = self.get_token_balance(&requirement.account)?;
+
+            if balance < requirement.amount {
+                let decimals = self.get_mint_decimals(requirement.mint)?;
+                shortfalls.push(format!(
+                    "token account {} holds {} of mint {} but {} is required (short {})",
+                    requirement.account,
+                    format_ui_amount(balance, decimals),
+                    requirement.mint,
+                    format_ui_amount(requirement.amount, decimals),
+                    format_ui_amount(requirement.amount - balance, decimals)
+                ));
+            }
+        }
+
+        required_lamports = required_lamports
+            .checked_add(self.get_ata_creation_rent(instructions)?)
+            .ok_or_else(|| anyhow!("required lamports overflow"))?;
+        let fee = self.get_transaction_fee(instructions)?;
+
+        // A multisig vault funds the operation while the payer only pays to propose it.
+        let owner = self.owner();
+        if owner == self.payer.pubkey() {
+            let required_lamports = required_lamports
+                .checked_add(fee)
+                .ok_or_else(|| anyhow!("required lamports overflow"))?;
+            self.check_lamports("payer", owner, required_lamports, &mut shortfalls)?;
+        } else {
+            self.check_lamports("vault", owner, required_lamports, &mut shortfalls)?;
+            self.check_lamports("payer", self.payer.pubkey(), fee, &mut shortfalls)?;
+        }
+
+        if shortfalls.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!("insufficient balance: {}", shortfalls.join("; ")))
+        }
+    }
+
+    /// Adds a shortfall if the account holds less than the required lamports.
+    fn check_lamports(
+        &self,
+        name: &str,
+        account: Pubkey,
+        required_lamports: u64,
+        shortfalls: &mut Vec<String>,
+    ) -> Result<()> {
+        let lamports = self
+            .client_rpc
+            .get_balance(&account)
+            .with_context(|| format!("failed to get {name} balance"))?;
+
+        if lamports < required_lamports {
+            shortfalls.push(format!(
+                "{name} {account} holds {} SOL but {} SOL is required for fees, rent and wrapped SOL (short {})",
+                format_ui_amount(lamports, native_mint::DECIMALS),
+                format_ui_amount(required_lamports, native_mint::DECIMALS),
+                format_ui_amount(required_lamports - lamports, native_mint::DECIMALS)
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the lamports needed for the pool creation fee and rent of the new pool accounts.
+    ///
+    /// The program creates the creator's LP token ATA itself, so its rent is included here
+    /// rather than found among the instructions by `get_ata_creation_rent`.
+    pub(crate) fn get_pool_creation_cost(
+        &self,
+        amm_config: &AmmConfig,
+        token_0_mint: Pubkey,
+        token_1_mint: Pubkey,
+    ) -> Result<u64> {
+        let accounts = [
+            PoolState::LEN,
+            ObservationState::LEN,
+            spl_token::state::Mint::LEN,
+            self.get_token_account_len(token_0_mint)?,
+            self.get_token_account_len(token_1_mint)?,
+            // Creator LP token ATA, the LP mint being an SPL Token mint.
+            spl_token::state::Account::LEN,
+        ];
+
+        // Rent is linear in the account size, with a fixed overhead per account.
+        let account_rent = self
+            .client_rpc
+            .get_minimum_balance_for_rent_exemption(0)
+            .context("failed to get rent exemption")?;
+        let rent = self
+            .client_rpc
+            .get_minimum_balance_for_rent_exemption(accounts.iter().sum())
+            .context("failed to get rent exemption")?
+            .checked_add(account_rent * (accounts.len() as u64 - 1))
+            .ok_or_else(|| anyhow!("pool creation rent overflow"))?;
+
+        amm_config
+            .create_pool_fee
+            .checked_add(rent)
+            .ok_or_else(|| anyhow!("pool creation cost overflow"))
+    }
+
+    /// Returns the rent for the ATAs created by the instructions that do not exist yet.
+    fn get_ata_creation_rent(&self, instructions: &[Instruction]) -> Result<u64> {
+        let mut atas: Vec<(Pubkey, Pubkey)> = instructions
+            .iter()
+            .filter(|ix| ix.program_id == spl_associated_token_account::id())
+            // Create instructions list the funder, the ATA, the wallet and the mint.
+            .filter_map(|ix| Some((ix.accounts.get(1)?.pubkey, ix.accounts.get(3)?.pubkey)))
+            .collect();
+        atas.sort();
+        atas.dedup();
+
+        let addresses: Vec<Pubkey> = atas.iter().map(|(ata, _)| *ata).collect();
+        let existing = self
+            .client_rpc
+            .get_multiple_accounts(&addresses)
+            .context("failed to fetch token accounts")?;
+
+        let mut rent = 0;
+        for ((_, mint), account) in atas.iter().zip(existing) {
+            if account.is_none() {
+                rent += self
+                    .client_rpc
+                    .get_minimum_balance_for_rent_exemption(self.get_token_account_len(*mint)?)
+                    .context("failed to get rent exemption")?;
+            }
+        }
+
+        Ok(rent)
+    }
+
+    /// Returns the size of a token account for the mint, including required extensions.
+    fn get_token_account_len(&self, mint: Pubkey) -> Result<usize> {
+        let account = self
+            .client_rpc
+            .get_account(&mint)
+            .context("failed to fetch mint account")?;
+
+        if account.owner != spl_token_2022::id() {
+            return Ok(spl_token::state::Account::LEN);
+        }
+
+        let mint_data = StateWithExtensions::<Mint>::unpack(&account.data)
+            .context("failed to unpack mint account")?;
+        let mut extensions =
+            ExtensionType::get_required_init_account_extensions(&mint_data.get_extension_types()?);
+        extensions.push(ExtensionType::ImmutableOwner);
+
+        Ok(ExtensionType::try_calculate_account_len::<Account>(
+            &extensions,
+        )?)
+    }
+
+    /// Returns the balance of an SPL Token or Token-2022 account.
+    ///
+    /// A missing token account holds nothing.
+    pub(crate) fn get_token_balance(&self, account: &Pubkey) -> Result<u64> {
+        let account = self
+            .client_rpc
+            .get_account_with_commitment(account, self.client_rpc.commitment())?
+            .value;
+        token_account_amount(account.as_ref())
+    }
+
+    /// Returns the fee for a transaction with the instructions, including the priority fee.
+    pub(crate) fn get_transaction_fee(&self, instructions: &[Instruction]) -> Result<u64> {
+        let mut tx_instructions = self.priority_fee_instructions(instructions)?;
+        tx_instructions.extend_from_slice(instructions);
+
+        let recent_blockhash = self
+            .client_rpc
+            .get_latest_blockhash()
+            .context("failed to get recent blockhash")?;
+        let message = Message::new_with_blockhash(
+            &tx_instructions,
+            Some(&self.payer.pubkey()),
+            &recent_blockhash,
+        );
+
+        self.client_rpc
+            .get_fee_for_message(&message)
+            .context("failed to get transaction fee")
+    }
+}
+
+/// Returns the balance of a fetched SPL Token or Token-2022 account.
+///
+/// A missing token account holds nothing.
+pub(crate) fn token_account_amount(account: Option<&RpcAccount>) -> Result<u64> {
+    match account {
+        Some(account) => Ok(StateWithExtensions::<Account>::unpack(&account.data)
+            .context("failed to unpack token account")?
+            .base
+            .amount),
+        None => Ok(0),
+    }
+}
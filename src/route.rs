@@ -0,0 +1,780 @@
+use std::collections::HashMap;
+
+use anchor_client::{
+    solana_client::rpc_filter::{Memcmp, RpcFilterType},
+    solana_sdk::{account::Account, pubkey::Pubkey},
+};
+use anyhow::{anyhow, Context, Result};
+use raydium_cp_swap::{
+    curve::TradeDirection,
+    states::{AmmConfig, PoolState},
+};
+use solana_program::instruction::Instruction;
+use spl_associated_token_account::{
+    get_associated_token_address_with_program_id,
+    instruction::create_associated_token_account_idempotent,
+};
+use spl_token_2022::{
+    extension::{transfer_fee::TransferFeeConfig, BaseStateWithExtensions, StateWithExtensions},
+    state::Mint,
+};
+
+use crate::{
+    cache::{vault_amount, PoolSnapshot},
+    events::SentTransaction,
+    instructions::{swap_base_input_instruction, PoolKeys},
+    market::Market,
+    preflight::TokenRequirement,
+    store::{Operation, OperationKind},
+    RaydiumIntegration,
+};
+
+/// Offset of token 0's mint in the pool state account data (after the discriminator, four keys
+/// and the LP mint).
+const POOL_TOKEN_0_MINT_OFFSET: usize = 8 + 32 * 5;
+
+/// Offset of token 1's mint in the pool state account data.
+const POOL_TOKEN_1_MINT_OFFSET: usize = POOL_TOKEN_0_MINT_OFFSET + 32;
+
+/// Pools whose vaults are fetched per `getMultipleAccounts` request.
+const POOLS_PER_REQUEST: usize = 50;
+
+/// Mints fetched per `getMultipleAccounts` request.
+const MAX_MINTS_PER_REQUEST: usize = 100;
+
+/// Swap through one pool of a route.
+#[derive(Debug, Clone)]
+pub struct RouteHop {
+    /// Accounts of the pool.
+    pub pool: PoolKeys,
+    /// Direction of the swap in the pool.
+    pub direction: TradeDirection,
+    /// Mint of the token sold.
+    pub input_mint: Pubkey,
+    /// Mint of the token bought.
+    pub output_mint: Pubkey,
+    /// Amount of input tokens sold.
+    pub amount_in: u64,
+    /// Amount of output tokens expected.
+    pub amount_out: u64,
+    /// Minimum amount of output tokens accepted.
+    pub minimum_amount_out: u64,
+    /// Trade fee charged on the input amount.
+    pub trade_fee: u64,
+    /// Token-2022 transfer fee charged on the input amount.
+    pub input_transfer_fee: u64,
+    /// Token-2022 transfer fee charged on the output amount.
+    pub output_transfer_fee: u64,
+}
+
+/// Swaps through one or two pools from an input mint to an output mint.
+#[derive(Debug, Clone)]
+pub struct Route {
+    /// Mint of the token sold.
+    pub input_mint: Pubkey,
+    /// Mint of the token bought.
+    pub output_mint: Pubkey,
+    /// Amount of input tokens sold.
+    pub amount_in: u64,
+    /// Amount of output tokens expected.
+    pub amount_out: u64,
+    /// Minimum amount of output tokens accepted for the whole route.
+    pub minimum_amount_out: u64,
+    /// Swaps in execution order.
+    pub hops: Vec<RouteHop>,
+}
+
+/// Pool considered for a route with its reserves and fee configuration.
+pub(crate) struct RoutePool {
+    pub(crate) keys: PoolKeys,
+    pub(crate) market: Market,
+}
+
+/// Token-2022 transfer fee configurations of mints in the current epoch.
+pub(crate) struct TransferFees {
+    epoch: u64,
+    configs: HashMap<Pubkey, TransferFeeConfig>,
+}
+
+/// Swap quote net of the Token-2022 transfer fees of both mints.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct NetQuote {
+    /// Amount of output tokens received, after the output transfer fee.
+    pub(crate) amount_out: u64,
+    /// Trade fee charged on the input amount.
+    pub(crate) trade_fee: u64,
+    /// Transfer fee charged on the input amount.
+    pub(crate) input_transfer_fee: u64,
+    /// Transfer fee charged on the output amount.
+    pub(crate) output_transfer_fee: u64,
+}
+
+impl TransferFees {
+    /// Reads the transfer fee configurations of the Token-2022 mints among the mint accounts.
+    pub(crate) fn from_mint_accounts<'a>(
+        epoch: u64,
+        accounts: impl IntoIterator<Item = (Pubkey, &'a Account)>,
+    ) -> Result<Self> {
+        let mut configs = HashMap::new();
+        for (mint, account) in accounts {
+            if account.owner != spl_token_2022::id() {
+                continue;
+            }
+            let mint_data = StateWithExtensions::<Mint>::unpack(&account.data)
+                .context("failed to unpack mint account")?;
+            if let Ok(config) = mint_data.get_extension::<TransferFeeConfig>() {
+                configs.insert(mint, *config);
+            }
+        }
+        Ok(Self { epoch, configs })
+    }
+
+    /// Quotes a swap as the program executes it.
+    ///
+    /// The curve is applied to the input amount net of the input mint's transfer fee, and the
+    /// output amount is the amount received, net of the output mint's transfer fee, which is
+    /// what the program checks the minimum output against.
+    pub(crate) fn quote(
+        &self,
+        market: &Market,
+        direction: TradeDirection,
+        input_mint: &Pubkey,
+        output_mint: &Pubkey,
+        amount_in: u64,
+    ) -> Result<NetQuote> {
+        let input_transfer_fee = self.fee(input_mint, amount_in)?;
+        let quote = market.quote(direction, amount_in - input_transfer_fee)?;
+        let output_transfer_fee = self.fee(output_mint, quote.amount_out)?;
+        Ok(NetQuote {
+            amount_out: quote.amount_out - output_transfer_fee,
+            trade_fee: quote.trade_fee,
+            input_transfer_fee,
+            output_transfer_fee,
+        })
+    }
+
+    /// Returns the fee withheld when transferring the amount of the mint.
+    fn fee(&self, mint: &Pubkey, amount: u64) -> Result<u64> {
+        match self.configs.get(mint) {
+            Some(config) => config
+                .calculate_epoch_fee(self.epoch, amount)
+                .ok_or(anyhow!("failed to calculate transfer fee")),
+            None => Ok(0),
+        }
+    }
+}
+
+impl RouteHop {
+    /// Returns the operation recorded for the hop.
+    fn operation(&self) -> Operation {
+        let operation = Operation::new(
+            OperationKind::Swap,
+            self.pool.pool_state,
+            self.pool.token_0_mint,
+            self.pool.token_1_mint,
+            self.pool.lp_mint,
+        );
+        match self.direction {
+            TradeDirection::ZeroForOne => Operation {
+                token_0_amount: Some(self.amount_in),
+                token_1_bound: Some(self.minimum_amount_out),
+                ..operation
+            },
+            TradeDirection::OneForZero => Operation {
+                token_1_amount: Some(self.amount_in),
+                token_0_bound: Some(self.minimum_amount_out),
+                ..operation
+            },
+        }
+    }
+
+    /// Returns the input and output token programs.
+    fn token_programs(&self) -> (Pubkey, Pubkey) {
+        match self.direction {
+            TradeDirection::ZeroForOne => (self.pool.token_0_program, self.pool.token_1_program),
+            TradeDirection::OneForZero => (self.pool.token_1_program, self.pool.token_0_program),
+        }
+    }
+}
+
+impl RaydiumIntegration {
+    /// Finds the routes from the input mint to the output mint through at most two pools of
+    /// any AMM config, best output first.
+    ///
+    /// Quotes are net of the Token-2022 transfer fees of every mint along the route.
+    ///
+    /// Each intermediate hop requires at least its output less slippage and passes exactly that
+    /// amount on to the next hop, so any surplus of the intermediate token stays with the owner.
+    /// Only the last hop's minimum bounds the whole route.
+    pub fn find_routes(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        amount_in: u64,
+    ) -> Result<Vec<Route>> {
+        if input_mint == output_mint {
+            return Err(anyhow!("input and output mints are the same"));
+        }
+
+        let input_pools = self.get_pools_with_mint(input_mint)?;
+        let output_pools = self.get_pools_with_mint(output_mint)?;
+
+        let paths = candidate_paths(input_mint, output_mint, &input_pools, &output_pools);
+
+        let pool_data: HashMap<Pubkey, PoolState> =
+            input_pools.into_iter().chain(output_pools).collect();
+        let mut mints: Vec<Pubkey> = pool_data
+            .values()
+            .flat_map(|data| [data.token_0_mint, data.token_1_mint])
+            .collect();
+        mints.sort();
+        mints.dedup();
+
+        let pools = self.get_route_pools(&pool_data)?;
+        let transfer_fees = self.get_transfer_fees(&mints)?;
+
+        Ok(rank_routes(
+            input_mint,
+            output_mint,
+            amount_in,
+            &paths,
+            &pools,
+            self.slippage(),
+            &transfer_fees,
+        ))
+    }
+
+    /// Returns the route with the best output from the input mint to the output mint.
+    pub fn get_best_route(
+        &self,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        amount_in: u64,
+    ) -> Result<Route> {
+        self.find_routes(input_mint, output_mint, amount_in)?
+            .into_iter()
+            .next()
+            .ok_or(anyhow!("no route from {input_mint} to {output_mint}"))
+    }
+
+    /// Swaps along a route in a single transaction, failing unless the last hop returns at
+    /// least the route's minimum output.
+    pub fn swap_route(&self, route: &Route) -> Result<SentTransaction> {
+        let first_hop = route.hops.first().ok_or(anyhow!("route has no hops"))?;
+        let (input_program, _) = first_hop.token_programs();
+
+        let tx_instructions = self.create_route_instructions(route)?;
+        let operations: Vec<Operation> = route.hops.iter().map(RouteHop::operation).collect();
+
+        self.check_balances(
+            &tx_instructions,
+            &[TokenRequirement {
+                mint: route.input_mint,
+                account: get_associated_token_address_with_program_id(
+                    &self.owner(),
+                    &route.input_mint,
+                    &input_program,
+                ),
+                amount: route.amount_in,
+            }],
+            0,
+        )?;
+
+        self.send_transaction(&tx_instructions, &operations)
+            .context("failed to send route swap transaction")
+    }
+
+    /// Creates instructions for swapping along every hop of a route.
+    pub(crate) fn create_route_instructions(&self, route: &Route) -> Result<Vec<Instruction>> {
+        let owner = self.owner();
+
+        // Fund the WSOL ATA if the input token is native SOL.
+        let mut tx_instructions =
+            self.create_wrap_sol_instructions(&[(route.input_mint, route.amount_in)])?;
+
+        let mut mints = vec![route.input_mint];
+        for hop in &route.hops {
+            let (input_program, output_program) = hop.token_programs();
+
+            // Create the hop's output token ATA if it doesn't exist.
+            tx_instructions.push(create_associated_token_account_idempotent(
+                &owner,
+                &owner,
+                &hop.output_mint,
+                &output_program,
+            ));
+            tx_instructions.push(swap_base_input_instruction(
+                &self.program.id(),
+                &owner,
+                &hop.pool,
+                hop.direction,
+                get_associated_token_address_with_program_id(
+                    &owner,
+                    &hop.input_mint,
+                    &input_program,
+                ),
+                get_associated_token_address_with_program_id(
+                    &owner,
+                    &hop.output_mint,
+                    &output_program,
+                ),
+                hop.amount_in,
+                hop.minimum_amount_out,
+            ));
+            mints.push(hop.output_mint);
+        }
+
+        // Unwrap WSOL received or left over back to SOL.
+        tx_instructions.extend(self.create_unwrap_sol_instructions(&mints)?);
+        Ok(tx_instructions)
+    }
+
+    /// Reads the current Token-2022 transfer fee configuration of the mints that have one.
+    pub(crate) fn get_transfer_fees(&self, mints: &[Pubkey]) -> Result<TransferFees> {
+        let epoch = self
+            .client_rpc
+            .get_epoch_info()
+            .context("failed to get epoch info")?
+            .epoch;
+
+        let mut accounts = Vec::with_capacity(mints.len());
+        for chunk in mints.chunks(MAX_MINTS_PER_REQUEST) {
+            let (_, chunk_accounts) = self.cache.get_multiple_accounts(&self.client_rpc, chunk)?;
+            accounts.extend(chunk.iter().copied().zip(chunk_accounts));
+        }
+
+        TransferFees::from_mint_accounts(
+            epoch,
+            accounts
+                .iter()
+                .filter_map(|(mint, account)| Some((*mint, account.as_ref()?))),
+        )
+    }
+
+    /// Scans the program for pools with the mint as either token.
+    pub(crate) fn get_pools_with_mint(&self, mint: Pubkey) -> Result<Vec<(Pubkey, PoolState)>> {
+        let mut pools = Vec::new();
+        for offset in [POOL_TOKEN_0_MINT_OFFSET, POOL_TOKEN_1_MINT_OFFSET] {
+            let found: Vec<(Pubkey, PoolState)> = self
+                .program
+                .accounts(vec![RpcFilterType::Memcmp(Memcmp::new_base58_encoded(
+                    offset,
+                    &mint.to_bytes(),
+                ))])
+                .context("failed to fetch pools for mint")?;
+            pools.extend(found);
+        }
+        Ok(pools)
+    }
+
+    /// Reads the reserves and fee configuration of the pools.
+    pub(crate) fn get_route_pools(
+        &self,
+        pool_data: &HashMap<Pubkey, PoolState>,
+    ) -> Result<HashMap<Pubkey, RoutePool>> {
+        let amm_configs: HashMap<Pubkey, AmmConfig> =
+            self.list_amm_configs()?.into_iter().collect();
+
+        let entries: Vec<(&Pubkey, &PoolState)> = pool_data.iter().collect();
+        let mut pools = HashMap::new();
+        for chunk in entries.chunks(POOLS_PER_REQUEST) {
+            let vaults: Vec<Pubkey> = chunk
+                .iter()
+                .flat_map(|(_, data)| [data.token_0_vault, data.token_1_vault])
+                .collect();
+            let (slot, accounts) = self
+                .cache
+                .get_multiple_accounts(&self.client_rpc, &vaults)?;
+
+            for ((pool_state, data), vault_accounts) in chunk.iter().zip(accounts.chunks(2)) {
+                let (Some(amm_config), [Some(vault_0), Some(vault_1)]) =
+                    (amm_configs.get(&data.amm_config), vault_accounts)
+                else {
+                    continue;
+                };
+                let snapshot = PoolSnapshot::new(
+                    slot,
+                    **pool_state,
+                    **data,
+                    amm_config.clone(),
+                    vault_amount(vault_0)?,
+                    vault_amount(vault_1)?,
+                );
+
+                pools.insert(
+                    **pool_state,
+                    RoutePool {
+                        keys: PoolKeys::from_pool_state(&self.program.id(), **pool_state, data),
+                        market: Market::from(&snapshot),
+                    },
+                );
+            }
+        }
+
+        Ok(pools)
+    }
+}
+
+/// Returns candidate paths as pool sequences: direct pools, then pools through a shared mint.
+fn candidate_paths(
+    input_mint: Pubkey,
+    output_mint: Pubkey,
+    input_pools: &[(Pubkey, PoolState)],
+    output_pools: &[(Pubkey, PoolState)],
+) -> Vec<Vec<Pubkey>> {
+    let mut paths = Vec::new();
+    for (pool_state, pool_data) in input_pools {
+        let intermediate = other_mint(pool_data, &input_mint);
+        if intermediate == output_mint {
+            paths.push(vec![*pool_state]);
+            continue;
+        }
+        for (next_pool_state, next_pool_data) in output_pools {
+            if other_mint(next_pool_data, &output_mint) == intermediate {
+                paths.push(vec![*pool_state, *next_pool_state]);
+            }
+        }
+    }
+    paths
+}
+
+/// Quotes the paths, best output first.
+///
+/// Paths through pools that are missing or cannot be quoted, e.g. without liquidity, are
+/// skipped.
+fn rank_routes(
+    input_mint: Pubkey,
+    output_mint: Pubkey,
+    amount_in: u64,
+    paths: &[Vec<Pubkey>],
+    pools: &HashMap<Pubkey, RoutePool>,
+    slippage: f64,
+    transfer_fees: &TransferFees,
+) -> Vec<Route> {
+    let mut routes: Vec<Route> = paths
+        .iter()
+        .filter_map(|path| {
+            let hops: Vec<&RoutePool> = path
+                .iter()
+                .map(|key| pools.get(key))
+                .collect::<Option<_>>()?;
+            quote_route(
+                input_mint,
+                output_mint,
+                amount_in,
+                &hops,
+                slippage,
+                transfer_fees,
+            )
+            .ok()
+        })
+        .collect();
+    routes.sort_by(|a, b| b.amount_out.cmp(&a.amount_out));
+    routes
+}
+
+/// Quotes the hops in order, each selling the previous hop's minimum output.
+///
+/// Amounts out are received amounts, net of the output mint's transfer fee, and the curve
+/// is applied to the input amount net of the input mint's transfer fee, as by the program.
+pub(crate) fn quote_route(
+    input_mint: Pubkey,
+    output_mint: Pubkey,
+    amount_in: u64,
+    pools: &[&RoutePool],
+    slippage: f64,
+    transfer_fees: &TransferFees,
+) -> Result<Route> {
+    let mut hops = Vec::with_capacity(pools.len());
+    let mut hop_input_mint = input_mint;
+    let mut hop_amount_in = amount_in;
+
+    for pool in pools {
+        let direction = if hop_input_mint == pool.keys.token_0_mint {
+            TradeDirection::ZeroForOne
+        } else {
+            TradeDirection::OneForZero
+        };
+        let hop_output_mint = match direction {
+            TradeDirection::ZeroForOne => pool.keys.token_1_mint,
+            TradeDirection::OneForZero => pool.keys.token_0_mint,
+        };
+
+        let quote = transfer_fees.quote(
+            &pool.market,
+            direction,
+            &hop_input_mint,
+            &hop_output_mint,
+            hop_amount_in,
+        )?;
+
+        let minimum_amount_out = (quote.amount_out as f64 * (1.0 - slippage)).floor() as u64;
+        if minimum_amount_out == 0 {
+            return Err(anyhow!("route output rounds down to zero"));
+        }
+
+        hops.push(RouteHop {
+            pool: pool.keys,
+            direction,
+            input_mint: hop_input_mint,
+            output_mint: hop_output_mint,
+            amount_in: hop_amount_in,
+            amount_out: quote.amount_out,
+            minimum_amount_out,
+            trade_fee: quote.trade_fee,
+            input_transfer_fee: quote.input_transfer_fee,
+            output_transfer_fee: quote.output_transfer_fee,
+        });
+        hop_input_mint = hop_output_mint;
+        hop_amount_in = minimum_amount_out;
+    }
+
+    let last_hop = hops.last().ok_or(anyhow!("route has no hops"))?;
+    if last_hop.output_mint != output_mint {
+        return Err(anyhow!("route does not end in {output_mint}"));
+    }
+
+    Ok(Route {
+        input_mint,
+        output_mint,
+        amount_in,
+        amount_out: last_hop.amount_out,
+        minimum_amount_out: last_hop.minimum_amount_out,
+        hops,
+    })
+}
+
+/// Returns the pool's mint other than `mint`.
+pub(crate) fn other_mint(pool_data: &PoolState, mint: &Pubkey) -> Pubkey {
+    if pool_data.token_0_mint == *mint {
+        pool_data.token_1_mint
+    } else {
+        pool_data.token_0_mint
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use spl_token_2022::extension::{
+        transfer_fee::TransferFee, ExtensionType, StateWithExtensionsMut,
+    };
+
+    use super::*;
+    use crate::PoolLiquidity;
+
+    /// Pool of the mints with the given reserves and no trade fee.
+    fn pool(mints: (Pubkey, Pubkey), reserves: (u64, u64)) -> (Pubkey, PoolState, RoutePool) {
+        let pool_state = Pubkey::new_unique();
+        let mut pool_data = PoolState::default();
+        pool_data.token_0_mint = mints.0;
+        pool_data.token_1_mint = mints.1;
+        let route_pool = RoutePool {
+            keys: PoolKeys::from_pool_state(&raydium_cp_swap::id(), pool_state, &pool_data),
+            market: Market {
+                liquidity: PoolLiquidity {
+                    token_0_amount: reserves.0,
+                    token_1_amount: reserves.1,
+                    lp_supply: 1_000_000,
+                },
+                amm_config: AmmConfig::default(),
+                decimals_0: 6,
+                decimals_1: 6,
+            },
+        };
+        (pool_state, pool_data, route_pool)
+    }
+
+    /// Token-2022 mint account charging the given transfer fee in every epoch.
+    fn transfer_fee_mint(basis_points: u16) -> Account {
+        let len =
+            ExtensionType::try_calculate_account_len::<Mint>(&[ExtensionType::TransferFeeConfig])
+                .unwrap();
+        let mut data = vec![0; len];
+        let mut state = StateWithExtensionsMut::<Mint>::unpack_uninitialized(&mut data).unwrap();
+        let fee = TransferFee {
+            epoch: 0.into(),
+            maximum_fee: u64::MAX.into(),
+            transfer_fee_basis_points: basis_points.into(),
+        };
+        let config = state.init_extension::<TransferFeeConfig>(true).unwrap();
+        config.older_transfer_fee = fee;
+        config.newer_transfer_fee = fee;
+        state.base = Mint {
+            decimals: 6,
+            is_initialized: true,
+            ..Mint::default()
+        };
+        state.pack_base();
+        state.init_account_type().unwrap();
+
+        Account {
+            owner: spl_token_2022::id(),
+            data,
+            ..Account::default()
+        }
+    }
+
+    /// Finds the routes through the pools as `find_routes` does.
+    fn routes(
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+        amount_in: u64,
+        pools: Vec<(Pubkey, PoolState, RoutePool)>,
+        slippage: f64,
+        transfer_fees: &TransferFees,
+    ) -> Vec<Route> {
+        let with_mint = |mint: Pubkey| -> Vec<(Pubkey, PoolState)> {
+            pools
+                .iter()
+                .filter(|(_, data, _)| data.token_0_mint == mint || data.token_1_mint == mint)
+                .map(|(pool_state, data, _)| (*pool_state, *data))
+                .collect()
+        };
+        let paths = candidate_paths(
+            input_mint,
+            output_mint,
+            &with_mint(input_mint),
+            &with_mint(output_mint),
+        );
+        let pools: HashMap<Pubkey, RoutePool> = pools
+            .into_iter()
+            .map(|(pool_state, _, route_pool)| (pool_state, route_pool))
+            .collect();
+        rank_routes(
+            input_mint,
+            output_mint,
+            amount_in,
+            &paths,
+            &pools,
+            slippage,
+            transfer_fees,
+        )
+    }
+
+    fn no_transfer_fees() -> TransferFees {
+        TransferFees::from_mint_accounts(0, []).unwrap()
+    }
+
+    #[test]
+    fn direct_route() {
+        let (mint_a, mint_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let direct = pool((mint_a, mint_b), (1_000_000, 2_000_000));
+        let pool_state = direct.0;
+
+        let routes = routes(
+            mint_a,
+            mint_b,
+            1_000,
+            vec![direct],
+            0.01,
+            &no_transfer_fees(),
+        );
+        assert_eq!(routes.len(), 1);
+        let route = &routes[0];
+
+        // 1_000 * 2_000_000 / 1_001_000 = 1_998.002, less 1% slippage is 1_978.02.
+        assert_eq!(route.hops.len(), 1);
+        assert_eq!(route.hops[0].pool.pool_state, pool_state);
+        assert_eq!(route.hops[0].direction, TradeDirection::ZeroForOne);
+        assert_eq!(route.amount_in, 1_000);
+        assert_eq!(route.amount_out, 1_998);
+        assert_eq!(route.minimum_amount_out, 1_978);
+    }
+
+    #[test]
+    fn two_hop_route_through_intermediate_mint() {
+        let (mint_a, mint_b, mint_c) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        // The second pool holds the output mint as token 0, so it is swapped one for zero.
+        let first = pool((mint_a, mint_c), (1_000_000, 1_000_000));
+        let second = pool((mint_b, mint_c), (1_000_000, 1_000_000));
+        let unrelated = pool((mint_c, Pubkey::new_unique()), (1_000_000, 1_000_000));
+
+        let routes = routes(
+            mint_a,
+            mint_b,
+            1_000,
+            vec![first, second, unrelated],
+            0.0,
+            &no_transfer_fees(),
+        );
+        assert_eq!(routes.len(), 1);
+        let hops = &routes[0].hops;
+
+        // 1_000 * 1_000_000 / 1_001_000 = 999.001, then 999 * 1_000_000 / 1_000_999 = 998.002.
+        assert_eq!(hops.len(), 2);
+        assert_eq!((hops[0].input_mint, hops[0].output_mint), (mint_a, mint_c));
+        assert_eq!((hops[1].input_mint, hops[1].output_mint), (mint_c, mint_b));
+        assert_eq!(hops[1].direction, TradeDirection::OneForZero);
+        assert_eq!(hops[0].amount_out, 999);
+        assert_eq!(hops[1].amount_in, 999);
+        assert_eq!(routes[0].amount_out, 998);
+    }
+
+    #[test]
+    fn routes_are_ordered_by_output() {
+        let (mint_a, mint_b, mint_c) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let direct = pool((mint_a, mint_b), (1_000_000, 1_000_000));
+        let empty = pool((mint_a, mint_b), (0, 0));
+        let first = pool((mint_a, mint_c), (1_000_000, 2_000_000));
+        let second = pool((mint_c, mint_b), (1_000_000, 1_000_000));
+
+        let routes = routes(
+            mint_a,
+            mint_b,
+            1_000,
+            vec![direct, empty, first, second],
+            0.0,
+            &no_transfer_fees(),
+        );
+
+        // The pool without liquidity is skipped. Through `mint_c`, 1_000 buys 1_998 and then
+        // 1_998 * 1_000_000 / 1_001_998 = 1_994.01, against 999.001 directly.
+        let outputs: Vec<(usize, u64)> = routes
+            .iter()
+            .map(|route| (route.hops.len(), route.amount_out))
+            .collect();
+        assert_eq!(outputs, [(2, 1_994), (1, 999)]);
+    }
+
+    #[test]
+    fn transfer_fee_on_input_mint() {
+        let (mint_a, mint_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let transfer_fees =
+            TransferFees::from_mint_accounts(0, [(mint_a, &transfer_fee_mint(100))]).unwrap();
+        let direct = pool((mint_a, mint_b), (1_000_000, 2_000_000));
+
+        let routes = routes(mint_a, mint_b, 1_000, vec![direct], 0.0, &transfer_fees);
+        let hop = &routes[0].hops[0];
+
+        // 1% of 1_000 is withheld, and 990 * 2_000_000 / 1_000_990 = 1_978.04.
+        assert_eq!(hop.amount_in, 1_000);
+        assert_eq!(hop.input_transfer_fee, 10);
+        assert_eq!(hop.output_transfer_fee, 0);
+        assert_eq!(routes[0].amount_out, 1_978);
+    }
+
+    #[test]
+    fn transfer_fee_on_output_mint() {
+        let (mint_a, mint_b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let transfer_fees =
+            TransferFees::from_mint_accounts(0, [(mint_b, &transfer_fee_mint(100))]).unwrap();
+        let direct = pool((mint_a, mint_b), (1_000_000, 2_000_000));
+
+        let routes = routes(mint_a, mint_b, 1_000, vec![direct], 0.0, &transfer_fees);
+        let hop = &routes[0].hops[0];
+
+        // The curve returns 1_998, of which 1% rounded up, 20, is withheld.
+        assert_eq!(hop.input_transfer_fee, 0);
+        assert_eq!(hop.output_transfer_fee, 20);
+        assert_eq!(routes[0].amount_out, 1_978);
+        assert_eq!(routes[0].minimum_amount_out, 1_978);
+    }
+}
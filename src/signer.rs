@@ -0,0 +1,644 @@
+use std::{
+    env, fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+use aes_gcm_siv::{
+    aead::{Aead, NewAead},
+    Aes256GcmSiv, Key, Nonce,
+};
+use anchor_client::solana_sdk::{
+    bs58,
+    pubkey::Pubkey,
+    signature::{read_keypair_file, Keypair, Signature, Signer, SignerError},
+};
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use hmac::Hmac;
+use rand::RngCore;
+use reqwest::blocking::Client as HttpClient;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+/// Environment variable holding the passphrase of an encrypted keystore.
+pub const KEYSTORE_PASSPHRASE_ENV: &str = "RAYDIUM_KEYSTORE_PASSPHRASE";
+/// Environment variable holding the bearer token sent to an HTTP signing service.
+pub const SIGNER_TOKEN_ENV: &str = "RAYDIUM_SIGNER_TOKEN";
+
+/// Version of the keystore file format written by `write_keystore`.
+const KEYSTORE_VERSION: u8 = 1;
+/// Key derivation function of the keystore file format.
+const KEYSTORE_KDF: &str = "pbkdf2-sha256";
+/// PBKDF2 iterations for new keystores.
+const KEYSTORE_ITERATIONS: u32 = 600_000;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Payer signer shared by the blocking and asynchronous integrations.
+pub type PayerSigner = Arc<dyn Signer + Send + Sync>;
+
+/// Where the payer's signing key comes from.
+///
+/// Parsed from the `keypair` setting:
+/// - `env:VAR` reads a base58 secret key or a JSON byte array from the environment variable,
+/// - `keystore:PATH` decrypts a keystore file written by `write_keystore`,
+/// - `http://URL` or `https://URL` asks an HTTP signing service to sign,
+/// - anything else is the path of a plaintext keypair file.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum SignerSource {
+    /// Plaintext JSON keypair file.
+    File(PathBuf),
+    /// Environment variable holding the secret key.
+    Env(String),
+    /// Passphrase-encrypted keystore file.
+    Keystore(PathBuf),
+    /// Base URL of a remote signing service.
+    Http(String),
+}
+
+impl SignerSource {
+    /// Loads the signer, reading a keystore's passphrase from `RAYDIUM_KEYSTORE_PASSPHRASE` or
+    /// prompting for it on the terminal.
+    pub fn load(&self) -> Result<PayerSigner> {
+        Ok(match self {
+            Self::File(path) => Arc::new(
+                read_keypair_file(path)
+                    .map_err(|e| anyhow!("failed to read keypair file: {}", e))?,
+            ),
+            Self::Env(var) => Arc::new(keypair_from_env(var)?),
+            Self::Keystore(path) => {
+                let passphrase = match env::var(KEYSTORE_PASSPHRASE_ENV) {
+                    Ok(passphrase) => passphrase,
+                    Err(_) => {
+                        rpassword::prompt_password(format!("Passphrase for {}: ", path.display()))
+                            .context("failed to read keystore passphrase")?
+                    }
+                };
+                Arc::new(read_keystore(path, &passphrase)?)
+            }
+            Self::Http(url) => {
+                let mut signer = HttpSigner::new(url.clone());
+                if let Ok(token) = env::var(SIGNER_TOKEN_ENV) {
+                    signer = signer.with_token(token);
+                }
+                Arc::new(RemoteKeypair::new(signer)?)
+            }
+        })
+    }
+}
+
+impl FromStr for SignerSource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some(var) = s.strip_prefix("env:") {
+            Ok(Self::Env(var.to_string()))
+        } else if let Some(path) = s.strip_prefix("keystore:") {
+            Ok(Self::Keystore(PathBuf::from(path)))
+        } else if s.starts_with("http://") || s.starts_with("https://") {
+            Ok(Self::Http(s.trim_end_matches('/').to_string()))
+        } else if s.is_empty() {
+            Err(anyhow!("empty signer source"))
+        } else {
+            Ok(Self::File(PathBuf::from(s)))
+        }
+    }
+}
+
+impl TryFrom<String> for SignerSource {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for SignerSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "{}", path.display()),
+            Self::Env(var) => write!(f, "env:{var}"),
+            Self::Keystore(path) => write!(f, "keystore:{}", path.display()),
+            Self::Http(url) => write!(f, "{url}"),
+        }
+    }
+}
+
+/// Decodes a keypair from its base58 encoded 64 byte secret key.
+pub fn keypair_from_base58(encoded: &str) -> Result<Keypair> {
+    let bytes = bs58::decode(encoded.trim())
+        .into_vec()
+        .context("failed to decode base58 keypair")?;
+    Keypair::from_bytes(&bytes).map_err(|e| anyhow!("invalid keypair: {e}"))
+}
+
+/// Reads a keypair from an environment variable holding either a base58 secret key or the JSON
+/// byte array of a keypair file.
+pub fn keypair_from_env(var: &str) -> Result<Keypair> {
+    let value = env::var(var).with_context(|| format!("environment variable {var} not set"))?;
+    if value.trim_start().starts_with('[') {
+        let bytes: Vec<u8> =
+            serde_json::from_str(&value).context("failed to parse keypair byte array")?;
+        Keypair::from_bytes(&bytes).map_err(|e| anyhow!("invalid keypair: {e}"))
+    } else {
+        keypair_from_base58(&value)
+    }
+}
+
+/// Passphrase-encrypted keypair file.
+///
+/// The secret key is encrypted with AES-256-GCM-SIV under a key derived from the passphrase with
+/// PBKDF2-HMAC-SHA256. The public key is stored in clear so the payer can be identified without
+/// the passphrase.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Keystore {
+    pub version: u8,
+    #[serde(with = "crate::ledger::serde_display")]
+    pub pubkey: Pubkey,
+    pub kdf: String,
+    pub iterations: u32,
+    /// Base64 encoded key derivation salt.
+    pub salt: String,
+    /// Base64 encoded encryption nonce.
+    pub nonce: String,
+    /// Base64 encoded encrypted secret key.
+    pub ciphertext: String,
+}
+
+impl Keystore {
+    /// Encrypts the keypair with the passphrase.
+    pub fn encrypt(keypair: &Keypair, passphrase: &str) -> Result<Self> {
+        Self::encrypt_with_iterations(keypair, passphrase, KEYSTORE_ITERATIONS)
+    }
+
+    fn encrypt_with_iterations(
+        keypair: &Keypair,
+        passphrase: &str,
+        iterations: u32,
+    ) -> Result<Self> {
+        let mut salt = [0; SALT_LEN];
+        let mut nonce = [0; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut salt);
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let cipher = keystore_cipher(passphrase, &salt, iterations);
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), keypair.to_bytes().as_ref())
+            .map_err(|_| anyhow!("failed to encrypt keypair"))?;
+
+        Ok(Self {
+            version: KEYSTORE_VERSION,
+            pubkey: keypair.pubkey(),
+            kdf: KEYSTORE_KDF.to_string(),
+            iterations,
+            salt: BASE64.encode(salt),
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        })
+    }
+
+    /// Decrypts the keypair, failing if the passphrase is wrong or the file was tampered with.
+    pub fn decrypt(&self, passphrase: &str) -> Result<Keypair> {
+        if self.version != KEYSTORE_VERSION || self.kdf != KEYSTORE_KDF {
+            return Err(anyhow!(
+                "unsupported keystore version {} with kdf {}",
+                self.version,
+                self.kdf
+            ));
+        }
+        let salt = BASE64.decode(&self.salt).context("invalid keystore salt")?;
+        let nonce = BASE64
+            .decode(&self.nonce)
+            .context("invalid keystore nonce")?;
+        let ciphertext = BASE64
+            .decode(&self.ciphertext)
+            .context("invalid keystore ciphertext")?;
+        if nonce.len() != NONCE_LEN {
+            return Err(anyhow!("invalid keystore nonce length {}", nonce.len()));
+        }
+
+        let cipher = keystore_cipher(passphrase, &salt, self.iterations);
+        let secret = cipher
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+            .map_err(|_| anyhow!("failed to decrypt keystore, wrong passphrase?"))?;
+        let keypair = Keypair::from_bytes(&secret).map_err(|e| anyhow!("invalid keypair: {e}"))?;
+
+        if keypair.pubkey() != self.pubkey {
+            return Err(anyhow!(
+                "keystore public key {} does not match the decrypted keypair {}",
+                self.pubkey,
+                keypair.pubkey()
+            ));
+        }
+        Ok(keypair)
+    }
+}
+
+/// Derives the keystore encryption key from the passphrase.
+fn keystore_cipher(passphrase: &str, salt: &[u8], iterations: u32) -> Aes256GcmSiv {
+    let mut key = [0; 32];
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(passphrase.as_bytes(), salt, iterations, &mut key);
+    Aes256GcmSiv::new(Key::from_slice(&key))
+}
+
+/// Encrypts the keypair with the passphrase and writes it to a keystore file.
+pub fn write_keystore(path: impl AsRef<Path>, keypair: &Keypair, passphrase: &str) -> Result<()> {
+    let keystore = Keystore::encrypt(keypair, passphrase)?;
+    let data = serde_json::to_string_pretty(&keystore).context("failed to serialize keystore")?;
+    fs::write(path, data).context("failed to write keystore file")
+}
+
+/// Reads a keystore file and decrypts its keypair with the passphrase.
+pub fn read_keystore(path: impl AsRef<Path>, passphrase: &str) -> Result<Keypair> {
+    let data = fs::read_to_string(path).context("failed to read keystore file")?;
+    let keystore: Keystore =
+        serde_json::from_str(&data).context("failed to parse keystore file")?;
+    keystore.decrypt(passphrase)
+}
+
+/// Signing service holding a key outside of this process, e.g. an HSM or KMS backed signer.
+///
+/// Implementations are wrapped in `RemoteKeypair` to be used as the payer. `LocalSigner` stands
+/// in for the service with an in-memory keypair.
+///
+/// Implementations may block. The asynchronous integration signs on tokio's blocking threads.
+pub trait RemoteSigner: Send + Sync {
+    /// Returns the public key of the service's signing key.
+    fn pubkey(&self) -> Result<Pubkey>;
+
+    /// Signs the serialized transaction message.
+    fn sign(&self, message: &[u8]) -> Result<Signature>;
+}
+
+/// `Signer` backed by a `RemoteSigner`.
+///
+/// The public key is fetched once when created, and every signature returned by the service is
+/// verified against it before it is used.
+pub struct RemoteKeypair<S> {
+    signer: S,
+    pubkey: Pubkey,
+}
+
+impl<S: RemoteSigner> RemoteKeypair<S> {
+    /// Fetches the service's public key.
+    pub fn new(signer: S) -> Result<Self> {
+        let pubkey = signer
+            .pubkey()
+            .context("failed to get remote signer public key")?;
+        Ok(Self { signer, pubkey })
+    }
+}
+
+impl<S: RemoteSigner> Signer for RemoteKeypair<S> {
+    fn try_pubkey(&self) -> Result<Pubkey, SignerError> {
+        Ok(self.pubkey)
+    }
+
+    fn try_sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        let signature = self
+            .signer
+            .sign(message)
+            .map_err(|e| SignerError::Custom(format!("remote signer failed: {e:#}")))?;
+        if !signature.verify(self.pubkey.as_ref(), message) {
+            return Err(SignerError::Custom(format!(
+                "remote signer returned an invalid signature for {}",
+                self.pubkey
+            )));
+        }
+        Ok(signature)
+    }
+
+    fn is_interactive(&self) -> bool {
+        false
+    }
+}
+
+/// Remote signer standing in for a signing service with an in-memory keypair, for tests and
+/// local development.
+pub struct LocalSigner(Keypair);
+
+impl LocalSigner {
+    /// Creates a signer signing with the keypair.
+    pub fn new(keypair: Keypair) -> Self {
+        Self(keypair)
+    }
+}
+
+impl RemoteSigner for LocalSigner {
+    fn pubkey(&self) -> Result<Pubkey> {
+        Ok(self.0.pubkey())
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<Signature> {
+        Ok(self.0.sign_message(message))
+    }
+}
+
+/// Remote signer calling an HTTP signing service.
+///
+/// The service answers `GET {url}/pubkey` with `{"pubkey": "<base58>"}` and
+/// `POST {url}/sign` with body `{"message": "<base64>"}` with `{"signature": "<base58>"}`.
+///
+/// Requests use a blocking HTTP client, which cannot be created or used on an async runtime's
+/// threads: load the signer before entering the runtime or in `tokio::task::spawn_blocking`.
+pub struct HttpSigner {
+    client: HttpClient,
+    url: String,
+    token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PubkeyResponse {
+    #[serde(with = "crate::ledger::serde_display")]
+    pubkey: Pubkey,
+}
+
+#[derive(Serialize)]
+struct SignRequest {
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct SignResponse {
+    #[serde(with = "crate::ledger::serde_display")]
+    signature: Signature,
+}
+
+impl HttpSigner {
+    /// Creates a signer for the service at the base URL.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            client: HttpClient::new(),
+            url: url.into(),
+            token: None,
+        }
+    }
+
+    /// Sets the bearer token sent with every request.
+    pub fn with_token(mut self, token: String) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    fn request(
+        &self,
+        builder: reqwest::blocking::RequestBuilder,
+    ) -> reqwest::blocking::RequestBuilder {
+        match &self.token {
+            Some(token) => builder.bearer_auth(token),
+            None => builder,
+        }
+    }
+}
+
+impl RemoteSigner for HttpSigner {
+    fn pubkey(&self) -> Result<Pubkey> {
+        let response: PubkeyResponse = self
+            .request(self.client.get(format!("{}/pubkey", self.url)))
+            .send()
+            .and_then(|response| response.error_for_status())
+            .context("failed to request signer public key")?
+            .json()
+            .context("failed to parse signer public key response")?;
+        Ok(response.pubkey)
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<Signature> {
+        let response: SignResponse = self
+            .request(self.client.post(format!("{}/sign", self.url)))
+            .json(&SignRequest {
+                message: BASE64.encode(message),
+            })
+            .send()
+            .and_then(|response| response.error_for_status())
+            .context("failed to request signature")?
+            .json()
+            .context("failed to parse signature response")?;
+        Ok(response.signature)
+    }
+}
+
+/// Adapts a shared payer signer to the sized signer anchor's `Client` requires.
+pub(crate) struct SharedSigner(pub(crate) PayerSigner);
+
+impl Signer for SharedSigner {
+    fn try_pubkey(&self) -> Result<Pubkey, SignerError> {
+        self.0.try_pubkey()
+    }
+
+    fn try_sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        self.0.try_sign_message(message)
+    }
+
+    fn is_interactive(&self) -> bool {
+        self.0.is_interactive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    /// Iterations of the test keystores, far fewer than real ones to keep the tests fast.
+    const TEST_ITERATIONS: u32 = 1_000;
+
+    /// Signer reporting one public key and signing with another key.
+    struct MismatchedSigner {
+        pubkey: Pubkey,
+        signer: LocalSigner,
+    }
+
+    impl RemoteSigner for MismatchedSigner {
+        fn pubkey(&self) -> Result<Pubkey> {
+            Ok(self.pubkey)
+        }
+
+        fn sign(&self, message: &[u8]) -> Result<Signature> {
+            self.signer.sign(message)
+        }
+    }
+
+    /// Signer returning a signature that verifies for no message.
+    struct InvalidSigner(Pubkey);
+
+    impl RemoteSigner for InvalidSigner {
+        fn pubkey(&self) -> Result<Pubkey> {
+            Ok(self.0)
+        }
+
+        fn sign(&self, _message: &[u8]) -> Result<Signature> {
+            Ok(Signature::default())
+        }
+    }
+
+    #[test]
+    fn signs_with_local_signer() {
+        let keypair = Keypair::new();
+        let pubkey = keypair.pubkey();
+        let signer = RemoteKeypair::new(LocalSigner::new(keypair)).unwrap();
+        assert_eq!(signer.pubkey(), pubkey);
+
+        let signature = signer.try_sign_message(b"message").unwrap();
+        assert!(signature.verify(pubkey.as_ref(), b"message"));
+    }
+
+    #[test]
+    fn rejects_signature_of_another_key() {
+        let signer = RemoteKeypair::new(MismatchedSigner {
+            pubkey: Keypair::new().pubkey(),
+            signer: LocalSigner::new(Keypair::new()),
+        })
+        .unwrap();
+
+        let error = signer.try_sign_message(b"message").unwrap_err();
+        assert!(error.to_string().contains("invalid signature"));
+    }
+
+    #[test]
+    fn rejects_invalid_signature() {
+        let signer = RemoteKeypair::new(InvalidSigner(Keypair::new().pubkey())).unwrap();
+
+        let error = signer.try_sign_message(b"message").unwrap_err();
+        assert!(error.to_string().contains("invalid signature"));
+    }
+
+    /// Serves the HTTP signing API on a local port, reporting `pubkey` and signing with
+    /// `signing_key`, and returns its base URL.
+    ///
+    /// Requests without the bearer token, if one is given, are answered with 401.
+    fn serve_http_signer(pubkey: Pubkey, signing_key: Keypair, token: Option<&str>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let authorization = token.map(|token| format!("bearer {token}"));
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut content_length = 0;
+                let mut authorized = authorization.is_none();
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    let header = header.trim_end().to_lowercase();
+                    if header.is_empty() {
+                        break;
+                    }
+                    if let Some(length) = header.strip_prefix("content-length:") {
+                        content_length = length.trim().parse().unwrap();
+                    }
+                    if let Some(value) = header.strip_prefix("authorization:") {
+                        authorized |= authorization.as_deref() == Some(value.trim());
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+
+                let (status, response) = if !authorized {
+                    ("401 Unauthorized", json!({}))
+                } else if request_line.starts_with("GET /pubkey ") {
+                    ("200 OK", json!({ "pubkey": pubkey.to_string() }))
+                } else if request_line.starts_with("POST /sign ") {
+                    let request: Value = serde_json::from_slice(&body).unwrap();
+                    let message = BASE64.decode(request["message"].as_str().unwrap()).unwrap();
+                    let signature = signing_key.sign_message(&message);
+                    ("200 OK", json!({ "signature": signature.to_string() }))
+                } else {
+                    ("404 Not Found", json!({}))
+                };
+
+                let response = response.to_string();
+                write!(
+                    stream,
+                    "HTTP/1.1 {status}\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{response}",
+                    response.len()
+                )
+                .unwrap();
+            }
+        });
+
+        url
+    }
+
+    #[test]
+    fn signs_with_http_signer() {
+        let keypair = Keypair::new();
+        let pubkey = keypair.pubkey();
+        let url = serve_http_signer(pubkey, keypair, Some("secret"));
+
+        let signer =
+            RemoteKeypair::new(HttpSigner::new(url).with_token("secret".to_string())).unwrap();
+        assert_eq!(signer.pubkey(), pubkey);
+
+        let signature = signer.try_sign_message(b"message").unwrap();
+        assert!(signature.verify(pubkey.as_ref(), b"message"));
+    }
+
+    #[test]
+    fn http_signer_sends_token() {
+        let keypair = Keypair::new();
+        let url = serve_http_signer(keypair.pubkey(), keypair, Some("secret"));
+
+        let error = RemoteKeypair::new(HttpSigner::new(url)).err().unwrap();
+        assert!(format!("{error:#}").contains("401"));
+    }
+
+    #[test]
+    fn rejects_wrong_signature_from_http_signer() {
+        let url = serve_http_signer(Keypair::new().pubkey(), Keypair::new(), None);
+
+        let signer = RemoteKeypair::new(HttpSigner::new(url)).unwrap();
+        let error = signer.try_sign_message(b"message").unwrap_err();
+        assert!(error.to_string().contains("invalid signature"));
+    }
+
+    #[test]
+    fn keystore_round_trip() {
+        let keypair = Keypair::new();
+        let keystore =
+            Keystore::encrypt_with_iterations(&keypair, "passphrase", TEST_ITERATIONS).unwrap();
+        assert_eq!(keystore.pubkey, keypair.pubkey());
+
+        // The keystore is read back from its file format.
+        let keystore: Keystore =
+            serde_json::from_str(&serde_json::to_string(&keystore).unwrap()).unwrap();
+        let decrypted = keystore.decrypt("passphrase").unwrap();
+        assert_eq!(decrypted.to_bytes(), keypair.to_bytes());
+    }
+
+    #[test]
+    fn keystore_rejects_wrong_passphrase() {
+        let keystore =
+            Keystore::encrypt_with_iterations(&Keypair::new(), "passphrase", TEST_ITERATIONS)
+                .unwrap();
+
+        let error = keystore.decrypt("wrong passphrase").err().unwrap();
+        assert!(error.to_string().contains("wrong passphrase"));
+    }
+
+    #[test]
+    fn keystore_rejects_mismatched_pubkey() {
+        let mut keystore =
+            Keystore::encrypt_with_iterations(&Keypair::new(), "passphrase", TEST_ITERATIONS)
+                .unwrap();
+        keystore.pubkey = Keypair::new().pubkey();
+
+        let error = keystore.decrypt("passphrase").err().unwrap();
+        assert!(error.to_string().contains("does not match"));
+    }
+}
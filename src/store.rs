@@ -0,0 +1,1283 @@
+use std::{
+    io::Write,
+    path::Path,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anchor_client::{
+    solana_client::client_error::ClientError,
+    solana_sdk::{
+        pubkey::Pubkey,
+        signature::{Signature, Signer},
+    },
+};
+use anyhow::{anyhow, Context, Result};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::Serialize;
+use solana_transaction_status::{
+    EncodedConfirmedTransactionWithStatusMeta, UiTransactionTokenBalance,
+};
+use spl_token::native_mint;
+
+use crate::{
+    events::{LpChangeType, PoolEvent},
+    RaydiumIntegration,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationKind {
+    Initialize,
+    Deposit,
+    Withdraw,
+    Swap,
+}
+
+impl OperationKind {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            OperationKind::Initialize => "initialize",
+            OperationKind::Deposit => "deposit",
+            OperationKind::Withdraw => "withdraw",
+            OperationKind::Swap => "swap",
+        }
+    }
+}
+
+impl FromStr for OperationKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "initialize" => Ok(OperationKind::Initialize),
+            "deposit" => Ok(OperationKind::Deposit),
+            "withdraw" => Ok(OperationKind::Withdraw),
+            "swap" => Ok(OperationKind::Swap),
+            _ => Err(anyhow!("unknown operation kind: {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationStatus {
+    Success,
+    Failed,
+}
+
+impl OperationStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            OperationStatus::Success => "success",
+            OperationStatus::Failed => "failed",
+        }
+    }
+}
+
+impl FromStr for OperationStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "success" => Ok(OperationStatus::Success),
+            "failed" => Ok(OperationStatus::Failed),
+            _ => Err(anyhow!("unknown operation status: {s}")),
+        }
+    }
+}
+
+/// Operation requested by the caller, recorded together with the transaction outcome.
+#[derive(Debug, Clone)]
+pub struct Operation {
+    /// Kind of operation.
+    pub kind: OperationKind,
+    /// Pool's state account.
+    pub pool_state: Pubkey,
+    /// Mint account for token 0.
+    pub token_0_mint: Pubkey,
+    /// Mint account for token 1.
+    pub token_1_mint: Pubkey,
+    /// Mint account for the pool's LP tokens.
+    pub lp_mint: Pubkey,
+    /// Amount of LP tokens requested.
+    pub lp_amount: Option<u64>,
+    /// Amount of token 0 requested.
+    pub token_0_amount: Option<u64>,
+    /// Amount of token 1 requested.
+    pub token_1_amount: Option<u64>,
+    /// Slippage bound for token 0 (maximum in or minimum out).
+    pub token_0_bound: Option<u64>,
+    /// Slippage bound for token 1 (maximum in or minimum out).
+    pub token_1_bound: Option<u64>,
+}
+
+impl Operation {
+    /// Creates an operation on a pool without any requested amounts.
+    pub fn new(
+        kind: OperationKind,
+        pool_state: Pubkey,
+        token_0_mint: Pubkey,
+        token_1_mint: Pubkey,
+        lp_mint: Pubkey,
+    ) -> Self {
+        Self {
+            kind,
+            pool_state,
+            token_0_mint,
+            token_1_mint,
+            lp_mint,
+            lp_amount: None,
+            token_0_amount: None,
+            token_1_amount: None,
+            token_0_bound: None,
+            token_1_bound: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OperationRecord {
+    /// Row identifier in the store.
+    pub id: i64,
+    /// Unix timestamp at which the operation was recorded.
+    pub timestamp: u64,
+    /// Signature of the transaction containing the operation.
+    pub signature: String,
+    /// Slot in which the transaction was confirmed.
+    pub slot: Option<u64>,
+    /// Pool's state account.
+    pub pool_state: String,
+    /// Kind of operation.
+    pub kind: OperationKind,
+    /// Whether the transaction succeeded.
+    pub status: OperationStatus,
+    /// Error returned when sending the transaction.
+    pub error: Option<String>,
+    /// Amount of LP tokens requested.
+    pub lp_amount: Option<u64>,
+    /// Amount of token 0 requested.
+    pub token_0_amount: Option<u64>,
+    /// Amount of token 1 requested.
+    pub token_1_amount: Option<u64>,
+    /// Slippage bound for token 0.
+    pub token_0_bound: Option<u64>,
+    /// Slippage bound for token 1.
+    pub token_1_bound: Option<u64>,
+    /// Change of the owner's token 0 balance caused by the operation.
+    pub token_0_change: Option<i64>,
+    /// Change of the owner's token 1 balance caused by the operation.
+    pub token_1_change: Option<i64>,
+    /// Change of the owner's LP token balance caused by the operation.
+    pub lp_change: Option<i64>,
+    /// Transaction fee in lamports.
+    pub fee: Option<u64>,
+}
+
+impl OperationRecord {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let parse_error = |index: usize, e: anyhow::Error| {
+            rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, e.into())
+        };
+
+        Ok(Self {
+            id: row.get(0)?,
+            timestamp: row.get(1)?,
+            signature: row.get(2)?,
+            slot: row.get(3)?,
+            pool_state: row.get(4)?,
+            kind: row
+                .get::<_, String>(5)?
+                .parse()
+                .map_err(|e| parse_error(5, e))?,
+            status: row
+                .get::<_, String>(6)?
+                .parse()
+                .map_err(|e| parse_error(6, e))?,
+            error: row.get(7)?,
+            lp_amount: row.get(8)?,
+            token_0_amount: row.get(9)?,
+            token_1_amount: row.get(10)?,
+            token_0_bound: row.get(11)?,
+            token_1_bound: row.get(12)?,
+            token_0_change: row.get(13)?,
+            token_1_change: row.get(14)?,
+            lp_change: row.get(15)?,
+            fee: row.get(16)?,
+        })
+    }
+}
+
+/// Filter applied when querying recorded operations.
+#[derive(Debug, Default)]
+pub struct OperationFilter {
+    /// Only return operations on this pool.
+    pub pool_state: Option<Pubkey>,
+    /// Only return operations of this kind.
+    pub kind: Option<OperationKind>,
+    /// Only return operations recorded at or after this Unix timestamp.
+    pub since: Option<u64>,
+}
+
+/// Pool activity decoded from one program instruction, a point of the pool's timeline.
+#[derive(Debug, Clone, Serialize)]
+pub struct PoolActivity {
+    /// Pool's state account.
+    pub pool_state: String,
+    /// Signature of the transaction containing the instruction.
+    pub signature: String,
+    /// Position of the instruction among the transaction's instructions on the pool.
+    pub instruction_index: u32,
+    /// Slot in which the transaction was confirmed.
+    pub slot: u64,
+    /// Unix timestamp of the block containing the transaction.
+    pub block_time: Option<i64>,
+    /// Kind of operation.
+    pub kind: OperationKind,
+    /// Change of the token 0 vault balance.
+    pub token_0_change: i64,
+    /// Change of the token 1 vault balance.
+    pub token_1_change: i64,
+    /// Change of the LP supply.
+    pub lp_change: i64,
+    /// Amount of token 0 swapped into or out of the pool.
+    pub token_0_volume: u64,
+    /// Amount of token 1 swapped into or out of the pool.
+    pub token_1_volume: u64,
+    /// Trade fee charged in token 0, including the protocol and fund shares.
+    pub token_0_fee: u64,
+    /// Trade fee charged in token 1, including the protocol and fund shares.
+    pub token_1_fee: u64,
+    /// Token 0 vault balance after the instruction.
+    pub token_0_reserve: u64,
+    /// Token 1 vault balance after the instruction.
+    pub token_1_reserve: u64,
+}
+
+impl PoolActivity {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            pool_state: row.get(0)?,
+            signature: row.get(1)?,
+            instruction_index: row.get(2)?,
+            slot: row.get(3)?,
+            block_time: row.get(4)?,
+            kind: row
+                .get::<_, String>(5)?
+                .parse()
+                .map_err(|e: anyhow::Error| {
+                    rusqlite::Error::FromSqlConversionFailure(
+                        5,
+                        rusqlite::types::Type::Text,
+                        e.into(),
+                    )
+                })?,
+            token_0_change: row.get(6)?,
+            token_1_change: row.get(7)?,
+            lp_change: row.get(8)?,
+            token_0_volume: row.get(9)?,
+            token_1_volume: row.get(10)?,
+            token_0_fee: row.get(11)?,
+            token_1_fee: row.get(12)?,
+            token_0_reserve: row.get(13)?,
+            token_1_reserve: row.get(14)?,
+        })
+    }
+}
+
+/// Signature of a pool transaction found by the indexer and waiting to be processed.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct QueuedSignature {
+    pub(crate) signature: Signature,
+    pub(crate) slot: u64,
+    /// Whether the transaction failed, in which case it did not change the pool.
+    pub(crate) failed: bool,
+}
+
+/// Token balances and fee of a confirmed transaction.
+struct TransactionBalances {
+    slot: u64,
+    fee: u64,
+    pre: Vec<UiTransactionTokenBalance>,
+    post: Vec<UiTransactionTokenBalance>,
+    /// Change of the owner's lamports, excluding the transaction fee.
+    lamport_change: i64,
+}
+
+impl TransactionBalances {
+    /// Reads the token balances, the owner's lamport change and the fee of a confirmed
+    /// transaction.
+    fn from_transaction(
+        transaction: &EncodedConfirmedTransactionWithStatusMeta,
+        owner: &Pubkey,
+    ) -> Result<Self> {
+        let meta = transaction
+            .transaction
+            .meta
+            .as_ref()
+            .ok_or(anyhow!("transaction has no status meta"))?;
+        let versioned = transaction
+            .transaction
+            .transaction
+            .decode()
+            .ok_or(anyhow!("failed to decode transaction"))?;
+
+        let pre: Option<Vec<_>> = meta.pre_token_balances.clone().into();
+        let post: Option<Vec<_>> = meta.post_token_balances.clone().into();
+
+        Ok(Self {
+            slot: transaction.slot,
+            fee: meta.fee,
+            pre: pre.unwrap_or_default(),
+            post: post.unwrap_or_default(),
+            lamport_change: owner_lamport_change(
+                versioned.message.static_account_keys(),
+                &meta.pre_balances,
+                &meta.post_balances,
+                meta.fee,
+                owner,
+            )?,
+        })
+    }
+
+    /// Derives the owner's balance changes for the mints of an operation.
+    ///
+    /// SOL wrapped into and unwrapped from a WSOL account the transaction creates and closes is
+    /// absent from the token balances, so the native mint's change also includes the owner's
+    /// lamport change.
+    fn changes(&self, owner: &Pubkey, operation: &Operation) -> Result<TransactionChanges> {
+        let change = |mint: Pubkey| -> Result<i64> {
+            let post = owner_balance(&self.post, owner, &mint)?;
+            let pre = owner_balance(&self.pre, owner, &mint)?;
+            let lamport_change = if mint == native_mint::id() {
+                self.lamport_change
+            } else {
+                0
+            };
+            i64::try_from(i128::from(post) - i128::from(pre) + i128::from(lamport_change))
+                .map_err(|_| anyhow!("balance change too large for i64"))
+        };
+
+        Ok(TransactionChanges {
+            slot: self.slot,
+            fee: self.fee,
+            token_0_change: change(operation.token_0_mint)?,
+            token_1_change: change(operation.token_1_mint)?,
+            lp_change: change(operation.lp_mint)?,
+        })
+    }
+
+    /// Derives the owner's balance changes for an operation from the event it emitted.
+    ///
+    /// Used when a transaction holds several operations, whose balance changes cannot be told
+    /// apart from the owner's balances alone. The transaction fee is charged to `fee`.
+    fn event_changes(
+        &self,
+        operation: &Operation,
+        event: &PoolEvent,
+        fee: u64,
+    ) -> Result<TransactionChanges> {
+        let to_i64 = |amount: i128| {
+            i64::try_from(amount).map_err(|_| anyhow!("balance change too large for i64"))
+        };
+        // The owner pays transfer fees on top of what the pool receives, and receives what the
+        // pool sends less transfer fees.
+        let paid = |amount: u64, transfer_fee: u64| {
+            to_i64(-(i128::from(amount) + i128::from(transfer_fee)))
+        };
+        let received =
+            |amount: u64, transfer_fee: u64| to_i64(i128::from(amount) - i128::from(transfer_fee));
+        let lp_amount = |sign: i64| -> Result<i64> {
+            let lp_amount = operation
+                .lp_amount
+                .ok_or(anyhow!("liquidity operation without an LP amount"))?;
+            Ok(sign * i64::try_from(lp_amount)?)
+        };
+
+        let (token_0_change, token_1_change, lp_change) = match event {
+            PoolEvent::LpChange(event) if event.change_type == LpChangeType::Deposit => (
+                paid(event.token_0_amount, event.token_0_transfer_fee)?,
+                paid(event.token_1_amount, event.token_1_transfer_fee)?,
+                lp_amount(1)?,
+            ),
+            PoolEvent::LpChange(event) => (
+                received(event.token_0_amount, event.token_0_transfer_fee)?,
+                received(event.token_1_amount, event.token_1_transfer_fee)?,
+                lp_amount(-1)?,
+            ),
+            PoolEvent::Swap(event) => {
+                let input = paid(event.input_amount, event.input_transfer_fee)?;
+                let output = received(event.output_amount, event.output_transfer_fee)?;
+                // Swaps record their exact input amount on the input side.
+                if operation.token_0_amount.is_some() {
+                    (input, output, 0)
+                } else {
+                    (output, input, 0)
+                }
+            }
+        };
+
+        Ok(TransactionChanges {
+            slot: self.slot,
+            fee,
+            token_0_change,
+            token_1_change,
+            lp_change,
+        })
+    }
+}
+
+/// Returns whether an event was emitted by an operation of the kind on the same pool.
+fn matches_operation(event: &PoolEvent, operation: &Operation) -> bool {
+    match (event, operation.kind) {
+        (PoolEvent::Swap(event), OperationKind::Swap) => event.pool_id == operation.pool_state,
+        (PoolEvent::LpChange(event), OperationKind::Deposit) => {
+            event.pool_id == operation.pool_state && event.change_type == LpChangeType::Deposit
+        }
+        (PoolEvent::LpChange(event), OperationKind::Withdraw) => {
+            event.pool_id == operation.pool_state && event.change_type == LpChangeType::Withdraw
+        }
+        _ => false,
+    }
+}
+
+/// Outcome of an operation as seen from the owner's balances.
+struct TransactionChanges {
+    slot: u64,
+    fee: u64,
+    token_0_change: i64,
+    token_1_change: i64,
+    lp_change: i64,
+}
+
+/// Local SQLite database of executed operations.
+pub struct OperationStore {
+    connection: Connection,
+}
+
+impl OperationStore {
+    /// Opens the store at the given path, creating the database and schema if needed.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let connection = Connection::open(path).context("failed to open operation store")?;
+
+        connection
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS operations (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    timestamp INTEGER NOT NULL,
+                    signature TEXT NOT NULL,
+                    slot INTEGER,
+                    pool_state TEXT NOT NULL,
+                    kind TEXT NOT NULL,
+                    status TEXT NOT NULL,
+                    error TEXT,
+                    lp_amount INTEGER,
+                    token_0_amount INTEGER,
+                    token_1_amount INTEGER,
+                    token_0_bound INTEGER,
+                    token_1_bound INTEGER,
+                    token_0_change INTEGER,
+                    token_1_change INTEGER,
+                    lp_change INTEGER,
+                    fee INTEGER
+                );
+                CREATE INDEX IF NOT EXISTS operations_pool_state ON operations (pool_state);
+                CREATE INDEX IF NOT EXISTS operations_signature ON operations (signature);
+                CREATE TABLE IF NOT EXISTS pool_activity (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    pool_state TEXT NOT NULL,
+                    signature TEXT NOT NULL,
+                    instruction_index INTEGER NOT NULL,
+                    slot INTEGER NOT NULL,
+                    block_time INTEGER,
+                    kind TEXT NOT NULL,
+                    token_0_change INTEGER NOT NULL,
+                    token_1_change INTEGER NOT NULL,
+                    lp_change INTEGER NOT NULL,
+                    token_0_volume INTEGER NOT NULL,
+                    token_1_volume INTEGER NOT NULL,
+                    token_0_fee INTEGER NOT NULL,
+                    token_1_fee INTEGER NOT NULL,
+                    token_0_reserve INTEGER NOT NULL,
+                    token_1_reserve INTEGER NOT NULL,
+                    UNIQUE (pool_state, signature, instruction_index)
+                );
+                CREATE TABLE IF NOT EXISTS indexer_cursors (
+                    pool_state TEXT PRIMARY KEY,
+                    signature TEXT NOT NULL,
+                    slot INTEGER NOT NULL
+                );
+                CREATE TABLE IF NOT EXISTS indexer_queue (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    pool_state TEXT NOT NULL,
+                    walk INTEGER NOT NULL,
+                    signature TEXT NOT NULL,
+                    slot INTEGER NOT NULL,
+                    failed INTEGER NOT NULL,
+                    UNIQUE (pool_state, signature)
+                );",
+            )
+            .context("failed to create operation store schema")?;
+
+        Ok(Self { connection })
+    }
+
+    /// Returns recorded operations matching the filter, oldest first.
+    pub fn list(&self, filter: &OperationFilter) -> Result<Vec<OperationRecord>> {
+        let mut statement = self.connection.prepare(
+            "SELECT id, timestamp, signature, slot, pool_state, kind, status, error,
+                    lp_amount, token_0_amount, token_1_amount, token_0_bound, token_1_bound,
+                    token_0_change, token_1_change, lp_change, fee
+             FROM operations
+             WHERE (?1 IS NULL OR pool_state = ?1)
+               AND (?2 IS NULL OR kind = ?2)
+               AND (?3 IS NULL OR timestamp >= ?3)
+             ORDER BY id",
+        )?;
+
+        let records = statement
+            .query_map(
+                params![
+                    filter.pool_state.map(|key| key.to_string()),
+                    filter.kind.map(|kind| kind.as_str()),
+                    filter.since,
+                ],
+                OperationRecord::from_row,
+            )?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(records)
+    }
+
+    /// Writes the operations matching the filter as CSV.
+    pub fn export_csv(&self, filter: &OperationFilter, writer: impl Write) -> Result<()> {
+        let mut writer = csv::Writer::from_writer(writer);
+        for record in self.list(filter)? {
+            writer.serialize(record)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes the operations matching the filter as a JSON array.
+    pub fn export_json(&self, filter: &OperationFilter, writer: impl Write) -> Result<()> {
+        serde_json::to_writer_pretty(writer, &self.list(filter)?)?;
+        Ok(())
+    }
+
+    /// Returns the indexed activity of a pool in execution order, optionally from a Unix timestamp.
+    pub fn list_pool_activity(
+        &self,
+        pool_state: Pubkey,
+        since: Option<i64>,
+    ) -> Result<Vec<PoolActivity>> {
+        let mut statement = self.connection.prepare(
+            "SELECT pool_state, signature, instruction_index, slot, block_time, kind,
+                    token_0_change, token_1_change, lp_change, token_0_volume, token_1_volume,
+                    token_0_fee, token_1_fee, token_0_reserve, token_1_reserve
+             FROM pool_activity
+             WHERE pool_state = ?1 AND (?2 IS NULL OR block_time >= ?2)
+             ORDER BY id",
+        )?;
+
+        let activity = statement
+            .query_map(
+                params![pool_state.to_string(), since],
+                PoolActivity::from_row,
+            )?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(activity)
+    }
+
+    /// Returns the last transaction indexed for a pool.
+    pub fn get_indexer_cursor(&self, pool_state: Pubkey) -> Result<Option<Signature>> {
+        let signature: Option<String> = self
+            .connection
+            .query_row(
+                "SELECT signature FROM indexer_cursors WHERE pool_state = ?1",
+                params![pool_state.to_string()],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        signature
+            .map(|signature| Signature::from_str(&signature).context("invalid indexer cursor"))
+            .transpose()
+    }
+
+    /// Returns the newest transaction of a pool known to the indexer, queued or indexed.
+    pub(crate) fn get_newest_known_signature(
+        &self,
+        pool_state: Pubkey,
+    ) -> Result<Option<Signature>> {
+        let signature: Option<String> = self
+            .connection
+            .query_row(
+                "SELECT signature FROM indexer_queue WHERE pool_state = ?1
+                 ORDER BY walk DESC, id ASC LIMIT 1",
+                params![pool_state.to_string()],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        match signature {
+            Some(signature) => Ok(Some(
+                Signature::from_str(&signature).context("invalid queued signature")?,
+            )),
+            None => self.get_indexer_cursor(pool_state),
+        }
+    }
+
+    /// Queues the pool signatures returned by `next_page`, newest first, until it returns `None`.
+    ///
+    /// The pages are written in one transaction, so an interrupted walk queues nothing and the
+    /// next one starts again from the newest known signature. Returns the number of signatures
+    /// queued.
+    pub(crate) fn queue_signatures(
+        &self,
+        pool_state: Pubkey,
+        mut next_page: impl FnMut() -> Result<Option<Vec<QueuedSignature>>>,
+    ) -> Result<usize> {
+        let transaction = self.connection.unchecked_transaction()?;
+
+        // Every walk only finds signatures newer than the previous ones.
+        let walk: i64 = transaction.query_row(
+            "SELECT COALESCE(MAX(walk), 0) + 1 FROM indexer_queue WHERE pool_state = ?1",
+            params![pool_state.to_string()],
+            |row| row.get(0),
+        )?;
+
+        let mut queued = 0;
+        while let Some(page) = next_page()? {
+            for item in page {
+                queued += transaction.execute(
+                    "INSERT OR IGNORE INTO indexer_queue (pool_state, walk, signature, slot, failed)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        pool_state.to_string(),
+                        walk,
+                        item.signature.to_string(),
+                        item.slot,
+                        item.failed,
+                    ],
+                )?;
+            }
+        }
+
+        transaction.commit()?;
+        Ok(queued)
+    }
+
+    /// Returns the queued signatures of a pool, oldest first, at most `limit` if given.
+    pub(crate) fn list_queued_signatures(
+        &self,
+        pool_state: Pubkey,
+        limit: Option<usize>,
+    ) -> Result<Vec<QueuedSignature>> {
+        let mut statement = self.connection.prepare(
+            "SELECT signature, slot, failed FROM indexer_queue WHERE pool_state = ?1
+             ORDER BY walk ASC, id DESC LIMIT ?2",
+        )?;
+
+        let limit = limit.map_or(-1, |limit| i64::try_from(limit).unwrap_or(i64::MAX));
+        let rows = statement
+            .query_map(params![pool_state.to_string(), limit], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, u64>(1)?,
+                    row.get::<_, bool>(2)?,
+                ))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        rows.into_iter()
+            .map(|(signature, slot, failed)| {
+                Ok(QueuedSignature {
+                    signature: Signature::from_str(&signature)
+                        .context("invalid queued signature")?,
+                    slot,
+                    failed,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the number of queued signatures of a pool.
+    pub(crate) fn count_queued_signatures(&self, pool_state: Pubkey) -> Result<usize> {
+        let count: i64 = self.connection.query_row(
+            "SELECT COUNT(*) FROM indexer_queue WHERE pool_state = ?1",
+            params![pool_state.to_string()],
+            |row| row.get(0),
+        )?;
+        Ok(usize::try_from(count)?)
+    }
+
+    /// Inserts the activity decoded from an indexed transaction, removes it from the queue and
+    /// moves the pool's cursor to it.
+    ///
+    /// All three are written atomically so an interrupted backfill resumes after the last complete
+    /// transaction. Activity that was already indexed is left unchanged.
+    pub(crate) fn insert_indexed_transaction(
+        &self,
+        pool_state: Pubkey,
+        signature: Signature,
+        slot: u64,
+        activity: &[PoolActivity],
+    ) -> Result<()> {
+        let transaction = self.connection.unchecked_transaction()?;
+
+        for item in activity {
+            transaction.execute(
+                "INSERT OR IGNORE INTO pool_activity (
+                    pool_state, signature, instruction_index, slot, block_time, kind,
+                    token_0_change, token_1_change, lp_change, token_0_volume, token_1_volume,
+                    token_0_fee, token_1_fee, token_0_reserve, token_1_reserve
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+                params![
+                    item.pool_state,
+                    item.signature,
+                    item.instruction_index,
+                    item.slot,
+                    item.block_time,
+                    item.kind.as_str(),
+                    item.token_0_change,
+                    item.token_1_change,
+                    item.lp_change,
+                    item.token_0_volume,
+                    item.token_1_volume,
+                    item.token_0_fee,
+                    item.token_1_fee,
+                    item.token_0_reserve,
+                    item.token_1_reserve,
+                ],
+            )?;
+        }
+
+        transaction.execute(
+            "INSERT INTO indexer_cursors (pool_state, signature, slot) VALUES (?1, ?2, ?3)
+             ON CONFLICT (pool_state) DO UPDATE SET signature = ?2, slot = ?3",
+            params![pool_state.to_string(), signature.to_string(), slot],
+        )?;
+        transaction.execute(
+            "DELETE FROM indexer_queue WHERE pool_state = ?1 AND signature = ?2",
+            params![pool_state.to_string(), signature.to_string()],
+        )?;
+
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Inserts an operation together with the outcome of its transaction.
+    fn insert(
+        &self,
+        signature: Signature,
+        operation: &Operation,
+        error: Option<String>,
+        changes: Option<&TransactionChanges>,
+    ) -> Result<()> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let status = match error {
+            Some(_) => OperationStatus::Failed,
+            None => OperationStatus::Success,
+        };
+
+        self.connection.execute(
+            "INSERT INTO operations (
+                timestamp, signature, slot, pool_state, kind, status, error,
+                lp_amount, token_0_amount, token_1_amount, token_0_bound, token_1_bound,
+                token_0_change, token_1_change, lp_change, fee
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+            params![
+                timestamp,
+                signature.to_string(),
+                changes.map(|c| c.slot),
+                operation.pool_state.to_string(),
+                operation.kind.as_str(),
+                status.as_str(),
+                error,
+                operation.lp_amount,
+                operation.token_0_amount,
+                operation.token_1_amount,
+                operation.token_0_bound,
+                operation.token_1_bound,
+                changes.map(|c| c.token_0_change),
+                changes.map(|c| c.token_1_change),
+                changes.map(|c| c.lp_change),
+                changes.map(|c| c.fee),
+            ],
+        )?;
+
+        Ok(())
+    }
+}
+
+impl RaydiumIntegration {
+    /// Attaches an operation store that records every sent transaction.
+    pub fn with_store(mut self, store: OperationStore) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Records the operations of a sent transaction if a store is attached.
+    ///
+    /// Failures to record are logged rather than returned so they never mask the transaction result.
+    pub(crate) fn record_operations(
+        &self,
+        signature: Signature,
+        operations: &[Operation],
+        transaction: Option<&EncodedConfirmedTransactionWithStatusMeta>,
+        events: &[PoolEvent],
+        error: Option<&ClientError>,
+    ) {
+        let Some(store) = &self.store else {
+            return;
+        };
+
+        let owner = self.owner();
+        // Balances are only available for transactions that were confirmed.
+        let balances = match transaction
+            .map(|transaction| TransactionBalances::from_transaction(transaction, &owner))
+        {
+            Some(Ok(balances)) => Some(balances),
+            Some(Err(e)) => {
+                log::warn!("Failed to read balances of transaction {signature}: {e}");
+                None
+            }
+            None => None,
+        };
+
+        // Events are matched to the operations in order, each event to one operation.
+        let mut remaining = events.iter();
+
+        for (index, operation) in operations.iter().enumerate() {
+            let changes = balances.as_ref().and_then(|balances| {
+                let changes = if operations.len() == 1 {
+                    balances.changes(&owner, operation)
+                } else {
+                    // The whole transaction fee is charged to the first operation.
+                    let fee = if index == 0 { balances.fee } else { 0 };
+                    remaining
+                        .find(|event| matches_operation(event, operation))
+                        .ok_or(anyhow!(
+                            "no event for the {} operation",
+                            operation.kind.as_str()
+                        ))
+                        .and_then(|event| balances.event_changes(operation, event, fee))
+                };
+                match changes {
+                    Ok(changes) => Some(changes),
+                    Err(e) => {
+                        log::warn!("Failed to derive balance changes for {signature}: {e}");
+                        None
+                    }
+                }
+            });
+
+            if let Err(e) = store.insert(
+                signature,
+                operation,
+                error.map(ToString::to_string),
+                changes.as_ref(),
+            ) {
+                log::error!("Failed to record operation for transaction {signature}: {e}");
+            }
+        }
+    }
+}
+
+/// Sums the owner's balances of a mint in a transaction's token balance list.
+fn owner_balance(
+    balances: &[UiTransactionTokenBalance],
+    owner: &Pubkey,
+    mint: &Pubkey,
+) -> Result<u64> {
+    let owner = owner.to_string();
+    let mint = mint.to_string();
+
+    balances
+        .iter()
+        .filter(|balance| {
+            balance.mint == mint && Option::<&String>::from(balance.owner.as_ref()) == Some(&owner)
+        })
+        .try_fold(0u64, |total, balance| {
+            let amount = balance.ui_token_amount.amount.parse::<u64>()?;
+            total
+                .checked_add(amount)
+                .ok_or(anyhow!("token balance of {mint} overflows u64"))
+        })
+}
+
+/// Returns the change of the owner's lamports in a transaction, adding back the transaction
+/// fee if the owner paid it.
+///
+/// The change includes SOL wrapped or unwrapped by the transaction and rent of accounts the
+/// owner created or closed.
+fn owner_lamport_change(
+    account_keys: &[Pubkey],
+    pre_balances: &[u64],
+    post_balances: &[u64],
+    fee: u64,
+    owner: &Pubkey,
+) -> Result<i64> {
+    let Some(index) = account_keys.iter().position(|key| key == owner) else {
+        return Ok(0);
+    };
+    let pre = pre_balances
+        .get(index)
+        .ok_or(anyhow!("transaction has no balance for {owner}"))?;
+    let post = post_balances
+        .get(index)
+        .ok_or(anyhow!("transaction has no balance for {owner}"))?;
+    // The fee payer is the first account.
+    let fee = if index == 0 { fee } else { 0 };
+
+    i64::try_from(i128::from(*post) + i128::from(fee) - i128::from(*pre))
+        .map_err(|_| anyhow!("lamport change too large for i64"))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::events::{LpChangeEvent, SwapEvent};
+
+    fn token_balance(owner: &Pubkey, mint: &Pubkey, amount: u64) -> UiTransactionTokenBalance {
+        serde_json::from_value(json!({
+            "accountIndex": 1,
+            "mint": mint.to_string(),
+            "uiTokenAmount": {
+                "uiAmount": null,
+                "decimals": 6,
+                "amount": amount.to_string(),
+                "uiAmountString": "",
+            },
+            "owner": owner.to_string(),
+        }))
+        .unwrap()
+    }
+
+    fn balances(
+        pre: Vec<UiTransactionTokenBalance>,
+        post: Vec<UiTransactionTokenBalance>,
+        lamport_change: i64,
+    ) -> TransactionBalances {
+        TransactionBalances {
+            slot: 7,
+            fee: 5_000,
+            pre,
+            post,
+            lamport_change,
+        }
+    }
+
+    fn operation(kind: OperationKind, token_0_mint: Pubkey) -> Operation {
+        Operation {
+            lp_amount: Some(10),
+            ..Operation::new(
+                kind,
+                Pubkey::new_unique(),
+                token_0_mint,
+                Pubkey::new_unique(),
+                Pubkey::new_unique(),
+            )
+        }
+    }
+
+    fn lp_change(change_type: LpChangeType) -> PoolEvent {
+        PoolEvent::LpChange(LpChangeEvent {
+            pool_id: Pubkey::new_unique(),
+            lp_amount_before: 1_000,
+            token_0_vault_before: 5_000,
+            token_1_vault_before: 8_000,
+            token_0_amount: 100,
+            token_1_amount: 200,
+            token_0_transfer_fee: 1,
+            token_1_transfer_fee: 0,
+            change_type,
+        })
+    }
+
+    fn changes_of(changes: &TransactionChanges) -> (i64, i64, i64, u64) {
+        (
+            changes.token_0_change,
+            changes.token_1_change,
+            changes.lp_change,
+            changes.fee,
+        )
+    }
+
+    #[test]
+    fn owner_balance_sums_the_owner_accounts_of_the_mint() {
+        let (owner, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let list = [
+            token_balance(&owner, &mint, 100),
+            token_balance(&owner, &mint, 20),
+            token_balance(&Pubkey::new_unique(), &mint, 1_000),
+            token_balance(&owner, &Pubkey::new_unique(), 1_000),
+        ];
+        assert_eq!(owner_balance(&list, &owner, &mint).unwrap(), 120);
+        assert_eq!(
+            owner_balance(&list, &owner, &Pubkey::new_unique()).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn owner_balance_rejects_overflow() {
+        let (owner, mint) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let list = [
+            token_balance(&owner, &mint, u64::MAX),
+            token_balance(&owner, &mint, 1),
+        ];
+        assert!(owner_balance(&list, &owner, &mint).is_err());
+    }
+
+    #[test]
+    fn lamport_change_adds_back_the_fee_of_the_payer() {
+        let (payer, owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let keys = [payer, owner, Pubkey::new_unique()];
+        let pre = [10_000_000, 3_000_000, 0];
+        let post = [8_995_000, 3_500_000, 0];
+
+        assert_eq!(
+            owner_lamport_change(&keys, &pre, &post, 5_000, &payer).unwrap(),
+            -1_000_000
+        );
+        assert_eq!(
+            owner_lamport_change(&keys, &pre, &post, 5_000, &owner).unwrap(),
+            500_000
+        );
+        assert_eq!(
+            owner_lamport_change(&keys, &pre, &post, 5_000, &Pubkey::new_unique()).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn native_changes_include_lamports() {
+        let owner = Pubkey::new_unique();
+        let operation = operation(OperationKind::Swap, native_mint::id());
+
+        // SOL wrapped into a WSOL account created and closed by the transaction.
+        let wrapped = balances(
+            vec![],
+            vec![token_balance(&owner, &operation.token_1_mint, 500)],
+            -1_000_000,
+        );
+        assert_eq!(
+            changes_of(&wrapped.changes(&owner, &operation).unwrap()),
+            (-1_000_000, 500, 0, 5_000)
+        );
+
+        // WSOL already held stays in its account, next to the SOL wrapped for the swap.
+        let held = balances(
+            vec![token_balance(&owner, &native_mint::id(), 300)],
+            vec![
+                token_balance(&owner, &native_mint::id(), 300),
+                token_balance(&owner, &operation.token_1_mint, 500),
+            ],
+            -1_000_000,
+        );
+        assert_eq!(
+            changes_of(&held.changes(&owner, &operation).unwrap()),
+            (-1_000_000, 500, 0, 5_000)
+        );
+
+        // Lamports are not attributed to other mints.
+        let operation = self::operation(OperationKind::Swap, Pubkey::new_unique());
+        let tokens = balances(
+            vec![token_balance(&owner, &operation.token_0_mint, 700)],
+            vec![token_balance(&owner, &operation.token_0_mint, 200)],
+            -2_039_280,
+        );
+        assert_eq!(
+            changes_of(&tokens.changes(&owner, &operation).unwrap()),
+            (-500, 0, 0, 5_000)
+        );
+    }
+
+    #[test]
+    fn event_changes_include_transfer_fees() {
+        let balances = balances(vec![], vec![], 0);
+
+        let deposit = operation(OperationKind::Deposit, Pubkey::new_unique());
+        let changes = balances
+            .event_changes(&deposit, &lp_change(LpChangeType::Deposit), 5_000)
+            .unwrap();
+        assert_eq!(changes_of(&changes), (-101, -200, 10, 5_000));
+
+        let withdraw = operation(OperationKind::Withdraw, Pubkey::new_unique());
+        let changes = balances
+            .event_changes(&withdraw, &lp_change(LpChangeType::Withdraw), 0)
+            .unwrap();
+        assert_eq!(changes_of(&changes), (99, 200, -10, 0));
+
+        let swap = PoolEvent::Swap(SwapEvent {
+            pool_id: Pubkey::new_unique(),
+            input_vault_before: 5_000,
+            output_vault_before: 8_000,
+            input_amount: 50,
+            output_amount: 40,
+            input_transfer_fee: 2,
+            output_transfer_fee: 1,
+            base_input: true,
+        });
+        let zero_for_one = Operation {
+            token_0_amount: Some(52),
+            ..operation(OperationKind::Swap, Pubkey::new_unique())
+        };
+        let changes = balances.event_changes(&zero_for_one, &swap, 0).unwrap();
+        assert_eq!(changes_of(&changes), (-52, 39, 0, 0));
+
+        let one_for_zero = Operation {
+            token_1_amount: Some(52),
+            ..operation(OperationKind::Swap, Pubkey::new_unique())
+        };
+        let changes = balances.event_changes(&one_for_zero, &swap, 0).unwrap();
+        assert_eq!(changes_of(&changes), (39, -52, 0, 0));
+    }
+
+    #[test]
+    fn exports_recorded_operations() {
+        let store = OperationStore::open(":memory:").unwrap();
+        let deposit = Operation {
+            token_0_bound: Some(110),
+            token_1_bound: Some(220),
+            ..operation(OperationKind::Deposit, Pubkey::new_unique())
+        };
+        let changes = TransactionChanges {
+            slot: 7,
+            fee: 5_000,
+            token_0_change: -101,
+            token_1_change: -200,
+            lp_change: 10,
+        };
+        let signature = Signature::new_unique();
+        store
+            .insert(signature, &deposit, None, Some(&changes))
+            .unwrap();
+        store
+            .insert(
+                Signature::new_unique(),
+                &operation(OperationKind::Withdraw, Pubkey::new_unique()),
+                Some("slippage exceeded".to_string()),
+                None,
+            )
+            .unwrap();
+
+        let filter = OperationFilter {
+            kind: Some(OperationKind::Deposit),
+            ..OperationFilter::default()
+        };
+
+        let mut csv = Vec::new();
+        store.export_csv(&filter, &mut csv).unwrap();
+        let mut reader = csv::Reader::from_reader(csv.as_slice());
+        let headers = reader.headers().unwrap().clone();
+        let rows: Vec<_> = reader.records().collect::<Result<_, _>>().unwrap();
+        assert_eq!(rows.len(), 1);
+        let column = |name: &str| &rows[0][headers.iter().position(|h| h == name).unwrap()];
+        assert_eq!(column("signature"), signature.to_string());
+        assert_eq!(column("kind"), "deposit");
+        assert_eq!(column("status"), "success");
+        assert_eq!(column("token_0_bound"), "110");
+        assert_eq!(column("token_0_change"), "-101");
+        assert_eq!(column("lp_change"), "10");
+        assert_eq!(column("fee"), "5000");
+        assert_eq!(column("error"), "");
+
+        let mut json = Vec::new();
+        store
+            .export_json(&OperationFilter::default(), &mut json)
+            .unwrap();
+        let records: Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(records.as_array().unwrap().len(), 2);
+        assert_eq!(records[0]["token_1_change"], -200);
+        assert_eq!(records[1]["kind"], "withdraw");
+        assert_eq!(records[1]["status"], "failed");
+        assert_eq!(records[1]["error"], "slippage exceeded");
+        assert_eq!(records[1]["slot"], Value::Null);
+    }
+
+    fn queued(slot: u64) -> QueuedSignature {
+        QueuedSignature {
+            signature: Signature::new_unique(),
+            slot,
+            failed: false,
+        }
+    }
+
+    /// Queues the walk's pages, each newest first.
+    fn queue_walk(store: &OperationStore, pool_state: Pubkey, pages: Vec<Vec<QueuedSignature>>) {
+        let mut pages = pages.into_iter();
+        store
+            .queue_signatures(pool_state, || Ok(pages.next()))
+            .unwrap();
+    }
+
+    fn slots(signatures: &[QueuedSignature]) -> Vec<u64> {
+        signatures.iter().map(|queued| queued.slot).collect()
+    }
+
+    #[test]
+    fn queued_signatures_are_listed_oldest_first_across_walks() {
+        let store = OperationStore::open(":memory:").unwrap();
+        let pool_state = Pubkey::new_unique();
+
+        let first_walk = vec![vec![queued(5), queued(4)], vec![queued(3), queued(2)]];
+        let newest = first_walk[0][0].signature;
+        queue_walk(&store, pool_state, first_walk);
+        assert_eq!(
+            store.get_newest_known_signature(pool_state).unwrap(),
+            Some(newest)
+        );
+
+        // A later walk stops at the newest known signature, so its transactions are newer.
+        let second_walk = vec![vec![queued(7), queued(6)]];
+        let newest = second_walk[0][0].signature;
+        queue_walk(&store, pool_state, second_walk);
+        assert_eq!(
+            store.get_newest_known_signature(pool_state).unwrap(),
+            Some(newest)
+        );
+
+        let all = store.list_queued_signatures(pool_state, None).unwrap();
+        assert_eq!(slots(&all), [2, 3, 4, 5, 6, 7]);
+        let oldest = store.list_queued_signatures(pool_state, Some(3)).unwrap();
+        assert_eq!(slots(&oldest), [2, 3, 4]);
+        assert!(store
+            .list_queued_signatures(Pubkey::new_unique(), None)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn indexing_a_transaction_moves_it_from_the_queue_to_the_cursor() {
+        let store = OperationStore::open(":memory:").unwrap();
+        let pool_state = Pubkey::new_unique();
+        queue_walk(&store, pool_state, vec![vec![queued(2), queued(1)]]);
+
+        let oldest = store.list_queued_signatures(pool_state, Some(1)).unwrap()[0];
+        store
+            .insert_indexed_transaction(pool_state, oldest.signature, oldest.slot, &[])
+            .unwrap();
+        assert_eq!(
+            store.get_indexer_cursor(pool_state).unwrap(),
+            Some(oldest.signature)
+        );
+        assert_eq!(store.count_queued_signatures(pool_state).unwrap(), 1);
+
+        let newest = store.list_queued_signatures(pool_state, None).unwrap()[0];
+        store
+            .insert_indexed_transaction(pool_state, newest.signature, newest.slot, &[])
+            .unwrap();
+        assert_eq!(store.count_queued_signatures(pool_state).unwrap(), 0);
+        // With an empty queue the next walk stops at the cursor.
+        assert_eq!(
+            store.get_newest_known_signature(pool_state).unwrap(),
+            Some(newest.signature)
+        );
+    }
+}
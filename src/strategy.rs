@@ -0,0 +1,634 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anchor_client::solana_sdk::pubkey::Pubkey;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    amount::parse_ui_amount, events::SentTransaction, ledger::serde_display, order_tokens, pda,
+    InitializationKeys, PoolLiquidity, RaydiumIntegration,
+};
+
+/// Pools whose LP exposure the strategy maintains.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StrategyConfig {
+    /// Targets by pool.
+    pub pools: Vec<PoolTarget>,
+}
+
+/// Band the value of the LP position in a pool is kept in.
+///
+/// Values are in display units of `mint_b` (e.g. "1000.5") at the pool's price. Below
+/// `min_value` idle balances of both tokens are deposited up to `target_value`, and above
+/// `max_value` LP tokens are withdrawn down to `target_value`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PoolTarget {
+    /// Mint of the first pool token.
+    #[serde(with = "serde_display")]
+    pub mint_a: Pubkey,
+    /// Mint of the second pool token, in which values are measured.
+    #[serde(with = "serde_display")]
+    pub mint_b: Pubkey,
+    /// Index of the AMM config the pool belongs to.
+    #[serde(default)]
+    pub config_index: u16,
+    /// Value below which idle balances are deposited.
+    pub min_value: String,
+    /// Value deposits and withdrawals aim for.
+    pub target_value: String,
+    /// Value above which LP tokens are withdrawn.
+    pub max_value: String,
+}
+
+/// Band of a target in base units of its `mint_b`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ValueBand {
+    min_value: u64,
+    target_value: u64,
+    max_value: u64,
+    /// Decimals of `mint_b`.
+    decimals: u8,
+}
+
+impl ValueBand {
+    /// Parses the values of a target in display units of its `mint_b`.
+    fn parse(target: &PoolTarget, decimals: u8) -> Result<Self> {
+        let parse = |name: &str, value: &str| {
+            parse_ui_amount(value, decimals).with_context(|| {
+                format!("invalid {name} of pool {}/{}", target.mint_a, target.mint_b)
+            })
+        };
+        let band = Self {
+            min_value: parse("min_value", &target.min_value)?,
+            target_value: parse("target_value", &target.target_value)?,
+            max_value: parse("max_value", &target.max_value)?,
+            decimals,
+        };
+
+        if !(band.min_value <= band.target_value && band.target_value <= band.max_value) {
+            return Err(anyhow!(
+                "pool {}/{} must have min_value <= target_value <= max_value",
+                target.mint_a,
+                target.mint_b
+            ));
+        }
+        Ok(band)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StrategyActionKind {
+    Deposit,
+    Withdrawal,
+}
+
+/// Deposit or withdrawal decided by the strategy, or a failure to decide one, as recorded in
+/// the journal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StrategyAction {
+    /// Unix timestamp at which the action was decided.
+    pub timestamp: u64,
+    /// Pool's state account.
+    #[serde(with = "serde_display")]
+    pub pool_state: Pubkey,
+    /// Kind of liquidity operation, or `None` if planning failed.
+    pub kind: Option<StrategyActionKind>,
+    /// Amount of LP tokens to mint or burn, zero if planning failed.
+    pub lp_amount: u64,
+    /// Decimals of the LP mint, zero if planning failed.
+    #[serde(default)]
+    pub lp_decimals: u8,
+    /// Value of the position before the action, in base units of the target's `mint_b`, or
+    /// `None` if planning failed.
+    pub position_value: Option<u64>,
+    /// Value the action aims for, in base units of the target's `mint_b`.
+    pub target_value: u64,
+    /// Decimals of the target's `mint_b`, in which values are measured.
+    #[serde(default)]
+    pub value_decimals: u8,
+    /// Whether the action was only planned and not sent.
+    pub dry_run: bool,
+    /// Signature of the sent transaction.
+    pub signature: Option<String>,
+    /// Error the action failed with.
+    pub error: Option<String>,
+}
+
+/// Journal of strategy actions appended to a JSON Lines file.
+pub struct ActionJournal {
+    path: PathBuf,
+    file: File,
+}
+
+impl ActionJournal {
+    /// Opens the journal at the given path, creating the file if it does not exist.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .context("failed to open action journal")?;
+
+        Ok(Self { path, file })
+    }
+
+    /// Appends an action to the journal.
+    pub fn record(&mut self, action: &StrategyAction) -> Result<()> {
+        let line = serde_json::to_string(action)?;
+        writeln!(self.file, "{line}").context("failed to write action journal")
+    }
+
+    /// Reads every action recorded in the journal, oldest first.
+    pub fn actions(&self) -> Result<Vec<StrategyAction>> {
+        fs::read_to_string(&self.path)
+            .context("failed to read action journal")?
+            .lines()
+            .map(|line| serde_json::from_str(line).context("failed to parse action journal"))
+            .collect()
+    }
+}
+
+impl StrategyConfig {
+    /// Reads and parses a TOML strategy file.
+    ///
+    /// Values are parsed when the runner is created, since that needs the decimals of each
+    /// `mint_b`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let data = fs::read_to_string(path).context("failed to read strategy file")?;
+        toml::from_str(&data).context("failed to parse strategy file")
+    }
+}
+
+/// Keeps the LP positions of the configured pools within their value bands.
+///
+/// Every action is recorded to the journal, including failed ones. A pool that cannot be planned
+/// is recorded as an action with the error and the other pools are still evaluated, so one
+/// failing pool does not stop a long-running runner. In dry-run mode actions are recorded but
+/// not sent.
+pub struct StrategyRunner<'a> {
+    raydium: &'a RaydiumIntegration,
+    config: StrategyConfig,
+    /// Band of each configured pool, in base units.
+    bands: Vec<ValueBand>,
+    journal: ActionJournal,
+    dry_run: bool,
+}
+
+/// Action planned for a pool.
+#[derive(Debug, PartialEq, Eq)]
+struct PlannedAction {
+    kind: StrategyActionKind,
+    lp_amount: u64,
+    lp_decimals: u8,
+    position_value: u64,
+}
+
+/// LP position in a pool, valued at the pool's price.
+#[derive(Debug, Clone, Copy)]
+struct PositionValuation {
+    /// Pool's reserve of the target's `mint_b`.
+    reserve_b: u64,
+    lp_supply: u64,
+    /// LP tokens held by the owner.
+    lp_amount: u64,
+    lp_decimals: u8,
+}
+
+impl PositionValuation {
+    /// Decides whether the position needs a deposit or a withdrawal to stay within the band.
+    ///
+    /// `affordable` returns the largest LP amount the idle balances can deposit and is only
+    /// called when the position is below the band.
+    fn plan(
+        &self,
+        band: &ValueBand,
+        affordable: impl FnOnce() -> Result<u64>,
+    ) -> Result<Option<PlannedAction>> {
+        if self.lp_supply == 0 || self.reserve_b == 0 {
+            return Err(anyhow!("pool has no liquidity"));
+        }
+
+        // Both sides of the pool are worth the same, so an LP token is worth twice its share of
+        // the `mint_b` reserve.
+        let pool_value = 2 * u128::from(self.reserve_b);
+        let lp_supply = u128::from(self.lp_supply);
+        let to_value = |lp_amount: u64| u128::from(lp_amount) * pool_value / lp_supply;
+        let to_lp_amount = |value: u64| u128::from(value) * lp_supply / pool_value;
+
+        let position_value = u64::try_from(to_value(self.lp_amount))?;
+        let planned = |kind, lp_amount| {
+            (lp_amount > 0).then_some(PlannedAction {
+                kind,
+                lp_amount,
+                lp_decimals: self.lp_decimals,
+                position_value,
+            })
+        };
+
+        if position_value > band.max_value {
+            let excess = to_lp_amount(position_value - band.target_value);
+            let lp_amount = u64::try_from(excess)?.min(self.lp_amount);
+            return Ok(planned(StrategyActionKind::Withdrawal, lp_amount));
+        }
+
+        if position_value < band.min_value {
+            let needed = u64::try_from(to_lp_amount(band.target_value - position_value))?;
+            let lp_amount = needed.min(affordable()?);
+            return Ok(planned(StrategyActionKind::Deposit, lp_amount));
+        }
+
+        Ok(None)
+    }
+}
+
+impl<'a> StrategyRunner<'a> {
+    /// Creates a runner for the configured pools recording to the journal, parsing the values
+    /// of each pool in the decimals of its `mint_b`.
+    pub fn new(
+        raydium: &'a RaydiumIntegration,
+        config: StrategyConfig,
+        journal: ActionJournal,
+    ) -> Result<Self> {
+        let bands = config
+            .pools
+            .iter()
+            .map(|target| ValueBand::parse(target, raydium.get_mint_decimals(target.mint_b)?))
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            raydium,
+            config,
+            bands,
+            journal,
+            dry_run: false,
+        })
+    }
+
+    /// Records the actions without sending them.
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Evaluates every configured pool once, returning the actions taken.
+    pub fn run_once(&mut self) -> Result<Vec<StrategyAction>> {
+        let mut actions = Vec::new();
+        for index in 0..self.config.pools.len() {
+            actions.extend(self.run_pool(index)?);
+        }
+        Ok(actions)
+    }
+
+    /// Evaluates every pool at a fixed interval until an error occurs, passing the actions of
+    /// each round to the callback.
+    pub fn run_on_schedule(
+        &mut self,
+        interval: Duration,
+        mut on_actions: impl FnMut(&[StrategyAction]),
+    ) -> Result<()> {
+        loop {
+            on_actions(&self.run_once()?);
+            thread::sleep(interval);
+        }
+    }
+
+    /// Evaluates each pool whenever its liquidity changes until an error occurs, passing the
+    /// actions to the callback.
+    ///
+    /// Every pool is evaluated once on start. Changes arriving while a pool is evaluated are
+    /// coalesced into one evaluation.
+    pub fn run_on_updates(&mut self, mut on_actions: impl FnMut(&[StrategyAction])) -> Result<()> {
+        let (sender, receiver) = mpsc::channel();
+        for (index, target) in self.config.pools.iter().enumerate() {
+            let subscription = self
+                .raydium
+                .subscribe_pool_liquidity(self.pool_state(target))?;
+            let sender = sender.clone();
+            thread::spawn(move || {
+                for snapshot in subscription {
+                    let update = snapshot.map(|_| index).map_err(|e| format!("{e:#}"));
+                    if sender.send(update).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        while let Ok(update) = receiver.recv() {
+            let mut indexes = vec![update.map_err(|e| anyhow!(e))?];
+            indexes.extend(
+                receiver
+                    .try_iter()
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| anyhow!(e))?,
+            );
+            indexes.sort();
+            indexes.dedup();
+
+            for index in indexes {
+                on_actions(&self.run_pool(index)?);
+            }
+        }
+
+        Err(anyhow!("pool subscriptions closed"))
+    }
+
+    /// Plans and, unless in dry-run mode, sends the action for a pool, recording it together
+    /// with a planning or sending error.
+    fn run_pool(&mut self, index: usize) -> Result<Option<StrategyAction>> {
+        let target = self.config.pools[index].clone();
+        let band = self.bands[index];
+        let pool_state = self.pool_state(&target);
+
+        let mut action = StrategyAction {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            pool_state,
+            kind: None,
+            lp_amount: 0,
+            lp_decimals: 0,
+            position_value: None,
+            target_value: band.target_value,
+            value_decimals: band.decimals,
+            dry_run: self.dry_run,
+            signature: None,
+            error: None,
+        };
+
+        match self.plan(&target, &band, pool_state) {
+            Ok(None) => return Ok(None),
+            Ok(Some(planned)) => {
+                action.kind = Some(planned.kind);
+                action.lp_amount = planned.lp_amount;
+                action.lp_decimals = planned.lp_decimals;
+                action.position_value = Some(planned.position_value);
+
+                if !self.dry_run {
+                    match self.execute(&target, pool_state, &planned) {
+                        Ok(sent) => action.signature = Some(sent.signature.to_string()),
+                        Err(e) => action.error = Some(format!("{e:#}")),
+                    }
+                }
+            }
+            Err(e) => action.error = Some(format!("failed to plan action: {e:#}")),
+        }
+
+        self.journal.record(&action)?;
+        Ok(Some(action))
+    }
+
+    /// Decides whether the position in a pool needs a deposit or a withdrawal.
+    fn plan(
+        &self,
+        target: &PoolTarget,
+        band: &ValueBand,
+        pool_state: Pubkey,
+    ) -> Result<Option<PlannedAction>> {
+        let raydium = self.raydium;
+        let pool = raydium.get_pool_snapshot(pool_state)?;
+        let keys = raydium.get_pool_keys(pool_state)?;
+        let liquidity = pool.liquidity;
+
+        let reserve_b = if target.mint_b == pool.pool_data.token_1_mint {
+            liquidity.token_1_amount
+        } else {
+            liquidity.token_0_amount
+        };
+        let valuation = PositionValuation {
+            reserve_b,
+            lp_supply: liquidity.lp_supply,
+            lp_amount: raydium.get_token_balance(&keys.creator_lp_ata)?,
+            lp_decimals: pool.pool_data.lp_mint_decimals,
+        };
+
+        valuation
+            .plan(band, || self.affordable_lp_amount(&keys, &liquidity))
+            .with_context(|| format!("failed to plan pool {pool_state}"))
+    }
+
+    /// Returns the largest LP amount the owner's idle token balances can deposit.
+    fn affordable_lp_amount(
+        &self,
+        keys: &InitializationKeys,
+        liquidity: &PoolLiquidity,
+    ) -> Result<u64> {
+        let token_0_amount = self.raydium.get_token_balance(&keys.creator_token_0)?;
+        let token_1_amount = self.raydium.get_token_balance(&keys.creator_token_1)?;
+        if token_0_amount == 0 || token_1_amount == 0 {
+            return Ok(0);
+        }
+
+        // Balances too small for a single LP token are left idle.
+        Ok(self
+            .raydium
+            .calculate_lp_amount(token_0_amount, token_1_amount, liquidity)
+            .unwrap_or(0))
+    }
+
+    /// Sends the planned deposit or withdrawal.
+    fn execute(
+        &self,
+        target: &PoolTarget,
+        pool_state: Pubkey,
+        planned: &PlannedAction,
+    ) -> Result<SentTransaction> {
+        let keys = self.raydium.get_pool_keys(pool_state)?;
+        let (token_0_mint, token_1_mint) = order_tokens(target.mint_a, target.mint_b);
+
+        let operation = match planned.kind {
+            StrategyActionKind::Deposit => RaydiumIntegration::add_liquidity,
+            StrategyActionKind::Withdrawal => RaydiumIntegration::remove_liquidity,
+        };
+        operation(
+            self.raydium,
+            keys.pool_state,
+            keys.pool_authority,
+            keys.lp_mint,
+            token_0_mint,
+            token_1_mint,
+            keys.token_0_vault,
+            keys.token_1_vault,
+            keys.creator_token_0,
+            keys.creator_token_1,
+            keys.creator_lp_ata,
+            planned.lp_amount,
+        )
+    }
+
+    /// Returns the pool of the target's mints under its AMM config.
+    fn pool_state(&self, target: &PoolTarget) -> Pubkey {
+        let amm_config = pda::amm_config(&self.raydium.program.id(), target.config_index);
+        self.raydium
+            .get_pool_address(amm_config.address, target.mint_a, target.mint_b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BAND: ValueBand = ValueBand {
+        min_value: 500,
+        target_value: 1_000,
+        max_value: 2_000,
+        decimals: 6,
+    };
+
+    /// Position in a pool with a `mint_b` reserve of 10_000 and 1_000 LP tokens, so that an LP
+    /// token is worth 20.
+    fn valuation(lp_amount: u64) -> PositionValuation {
+        PositionValuation {
+            reserve_b: 10_000,
+            lp_supply: 1_000,
+            lp_amount,
+            lp_decimals: 9,
+        }
+    }
+
+    fn unaffordable() -> Result<u64> {
+        panic!("affordable amount fetched outside a deposit")
+    }
+
+    #[test]
+    fn parses_band_in_display_units() {
+        let target: PoolTarget = toml::from_str(
+            r#"
+            mint_a = "So11111111111111111111111111111111111111112"
+            mint_b = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"
+            min_value = "0.5"
+            target_value = "1"
+            max_value = "2.25"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            ValueBand::parse(&target, 6).unwrap(),
+            ValueBand {
+                min_value: 500_000,
+                target_value: 1_000_000,
+                max_value: 2_250_000,
+                decimals: 6,
+            }
+        );
+        assert!(ValueBand::parse(&target, 0).is_err());
+    }
+
+    #[test]
+    fn rejects_unordered_band() {
+        let target = PoolTarget {
+            mint_a: Pubkey::new_unique(),
+            mint_b: Pubkey::new_unique(),
+            config_index: 0,
+            min_value: "2".to_string(),
+            target_value: "1".to_string(),
+            max_value: "3".to_string(),
+        };
+        assert!(ValueBand::parse(&target, 6).is_err());
+    }
+
+    #[test]
+    fn no_action_within_band() {
+        // 50 LP tokens are worth 1_000, and 26 are worth 520.
+        for lp_amount in [26, 50, 100] {
+            assert_eq!(
+                valuation(lp_amount).plan(&BAND, unaffordable).unwrap(),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn withdraws_down_to_target() {
+        // 150 LP tokens are worth 3_000, of which 2_000 are 100 LP tokens.
+        assert_eq!(
+            valuation(150).plan(&BAND, unaffordable).unwrap(),
+            Some(PlannedAction {
+                kind: StrategyActionKind::Withdrawal,
+                lp_amount: 100,
+                lp_decimals: 9,
+                position_value: 3_000,
+            })
+        );
+    }
+
+    #[test]
+    fn deposits_up_to_target() {
+        // 10 LP tokens are worth 200, and 800 more are 40 LP tokens.
+        assert_eq!(
+            valuation(10).plan(&BAND, || Ok(1_000)).unwrap(),
+            Some(PlannedAction {
+                kind: StrategyActionKind::Deposit,
+                lp_amount: 40,
+                lp_decimals: 9,
+                position_value: 200,
+            })
+        );
+    }
+
+    #[test]
+    fn deposit_limited_by_idle_balances() {
+        let planned = valuation(10).plan(&BAND, || Ok(15)).unwrap().unwrap();
+        assert_eq!(planned.lp_amount, 15);
+        assert_eq!(valuation(10).plan(&BAND, || Ok(0)).unwrap(), None);
+    }
+
+    #[test]
+    fn pool_without_liquidity_is_an_error() {
+        let empty = PositionValuation {
+            reserve_b: 0,
+            ..valuation(10)
+        };
+        assert!(empty.plan(&BAND, unaffordable).is_err());
+    }
+
+    #[test]
+    fn journal_round_trip() {
+        let path = std::env::temp_dir().join(format!("strategy-{}.jsonl", Pubkey::new_unique()));
+        let mut journal = ActionJournal::open(&path).unwrap();
+
+        let planned = StrategyAction {
+            timestamp: 1_700_000_000,
+            pool_state: Pubkey::new_unique(),
+            kind: Some(StrategyActionKind::Deposit),
+            lp_amount: 40,
+            lp_decimals: 9,
+            position_value: Some(200),
+            target_value: 1_000,
+            value_decimals: 6,
+            dry_run: false,
+            signature: Some("5VERv8NMvzbJMEkV8xnrLkEaWRtSz9CosKDYjCJjBRnbJLgp8uirBgmQpjKhoR4tjF3ZpRzrFmBV6UjKdiSZkQUW".to_string()),
+            error: None,
+        };
+        let failed = StrategyAction {
+            kind: None,
+            lp_amount: 0,
+            lp_decimals: 0,
+            position_value: None,
+            signature: None,
+            error: Some("failed to plan action: pool has no liquidity".to_string()),
+            ..planned.clone()
+        };
+        journal.record(&planned).unwrap();
+        journal.record(&failed).unwrap();
+
+        let actions = journal.actions().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&actions).unwrap(),
+            serde_json::to_value([planned, failed]).unwrap()
+        );
+    }
+}
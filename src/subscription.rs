@@ -0,0 +1,319 @@
+use std::{
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::Duration,
+};
+
+use anchor_client::{
+    anchor_lang::AccountDeserialize,
+    solana_client::{
+        pubsub_client::{PubsubAccountClientSubscription, PubsubClient},
+        rpc_client::RpcClient,
+        rpc_config::RpcAccountInfoConfig,
+        rpc_response::Response as RpcResponse,
+    },
+    solana_sdk::{account::Account, pubkey::Pubkey},
+};
+use anyhow::{anyhow, Context, Result};
+use raydium_cp_swap::states::PoolState;
+use solana_account_decoder::{UiAccount, UiAccountEncoding};
+use spl_token_2022::extension::StateWithExtensions;
+
+use crate::{PoolLiquidity, RaydiumIntegration};
+
+/// Delay before the first reconnection attempt, doubled after every failed attempt.
+const INITIAL_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+/// Longest delay between reconnection attempts.
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Indexes of the subscribed accounts.
+const POOL_STATE: usize = 0;
+const TOKEN_0_VAULT: usize = 1;
+const TOKEN_1_VAULT: usize = 2;
+
+#[derive(Debug, Clone, Copy)]
+pub struct PoolLiquiditySnapshot {
+    /// Latest slot at which one of the pool's accounts was observed.
+    pub slot: u64,
+    /// Pool liquidity as of the slot.
+    pub liquidity: PoolLiquidity,
+}
+
+/// Notification forwarded from one of the account subscriptions.
+enum Notification {
+    Account(usize, RpcResponse<UiAccount>),
+    /// The subscription's websocket was closed.
+    Closed,
+}
+
+/// Live stream of a pool's liquidity built on `accountSubscribe` notifications for the pool
+/// state and both vaults.
+///
+/// The first item is the pool's current liquidity. A new snapshot follows every batch of account
+/// notifications. When the websocket disconnects the subscriptions are reopened and the accounts
+/// fetched again, so changes made while disconnected are not lost. The stream never ends.
+pub struct PoolSubscription {
+    rpc: RpcClient,
+    ws_url: String,
+    accounts: [Pubkey; 3],
+    config: RpcAccountInfoConfig,
+    subscriptions: Vec<PubsubAccountClientSubscription>,
+    receiver: Option<Receiver<Notification>>,
+    pending: Option<PoolLiquiditySnapshot>,
+    pool_data: PoolState,
+    vault_amounts: [u64; 2],
+    slots: [u64; 3],
+}
+
+impl RaydiumIntegration {
+    /// Subscribes to the pool state and vault accounts, yielding liquidity snapshots as they change.
+    pub fn subscribe_pool_liquidity(&self, pool_state: Pubkey) -> Result<PoolSubscription> {
+        let pool_data = self
+            .program
+            .account::<PoolState>(pool_state)
+            .context("failed to fetch pool state")?;
+        let commitment = self.client_rpc.commitment();
+
+        let mut subscription = PoolSubscription {
+            rpc: RpcClient::new_with_commitment(self.cluster.url().to_string(), commitment),
+            ws_url: self.cluster.ws_url().to_string(),
+            accounts: [pool_state, pool_data.token_0_vault, pool_data.token_1_vault],
+            config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                commitment: Some(commitment),
+                ..RpcAccountInfoConfig::default()
+            },
+            subscriptions: Vec::new(),
+            receiver: None,
+            pending: None,
+            pool_data,
+            vault_amounts: [0; 2],
+            slots: [0; 3],
+        };
+        subscription.pending = Some(subscription.connect()?);
+
+        Ok(subscription)
+    }
+}
+
+impl PoolSubscription {
+    /// Subscribes to the accounts and fetches their current state.
+    ///
+    /// Subscribing first means no change can fall between the fetch and the first notification.
+    fn connect(&mut self) -> Result<PoolLiquiditySnapshot> {
+        self.subscriptions.clear();
+        self.receiver = None;
+
+        let (sender, receiver) = mpsc::channel();
+        for (index, account) in self.accounts.iter().enumerate() {
+            let (subscription, notifications) =
+                PubsubClient::account_subscribe(&self.ws_url, account, Some(self.config.clone()))
+                    .context("failed to subscribe to account")?;
+            self.subscriptions.push(subscription);
+
+            let sender = sender.clone();
+            thread::spawn(move || {
+                for notification in notifications.iter() {
+                    if sender
+                        .send(Notification::Account(index, notification))
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+                // The receiver may already have been replaced by a reconnection.
+                let _ = sender.send(Notification::Closed);
+            });
+        }
+
+        let response = self
+            .rpc
+            .get_multiple_accounts_with_commitment(&self.accounts, self.rpc.commitment())
+            .context("failed to fetch pool accounts")?;
+        for (index, account) in response.value.iter().enumerate() {
+            let account = account
+                .as_ref()
+                .ok_or(anyhow!("pool account {} not found", self.accounts[index]))?;
+            self.apply(index, response.context.slot, &account.data)?;
+        }
+
+        self.receiver = Some(receiver);
+        Ok(self.snapshot())
+    }
+
+    /// Reconnects until it succeeds, backing off between attempts.
+    fn reconnect(&mut self) -> PoolLiquiditySnapshot {
+        let mut delay = INITIAL_RECONNECT_DELAY;
+        loop {
+            match self.connect() {
+                Ok(snapshot) => return snapshot,
+                Err(e) => {
+                    log::warn!(
+                        "Failed to resubscribe to pool accounts, retrying in {delay:?}: {e}"
+                    );
+                    thread::sleep(delay);
+                    delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+                }
+            }
+        }
+    }
+
+    /// Applies a notification, returning false if the subscription's websocket was closed.
+    fn handle(&mut self, notification: Notification) -> Result<bool> {
+        match notification {
+            Notification::Account(index, response) => {
+                let account = response
+                    .value
+                    .decode::<Account>()
+                    .ok_or(anyhow!("failed to decode account notification"))?;
+                self.apply(index, response.context.slot, &account.data)?;
+                Ok(true)
+            }
+            Notification::Closed => Ok(false),
+        }
+    }
+
+    /// Updates the state of one account, ignoring data older than what was already applied.
+    fn apply(&mut self, index: usize, slot: u64, data: &[u8]) -> Result<()> {
+        if slot < self.slots[index] {
+            return Ok(());
+        }
+        self.slots[index] = slot;
+
+        match index {
+            POOL_STATE => {
+                self.pool_data = PoolState::try_deserialize(&mut &data[..])
+                    .context("failed to deserialize pool state")?;
+            }
+            TOKEN_0_VAULT | TOKEN_1_VAULT => {
+                // Vaults of Token-2022 mints share the SPL Token layout followed by extensions.
+                let vault = StateWithExtensions::<spl_token_2022::state::Account>::unpack(data)
+                    .context("failed to unpack vault account")?;
+                self.vault_amounts[index - TOKEN_0_VAULT] = vault.base.amount;
+            }
+            _ => return Err(anyhow!("unknown account index {index}")),
+        }
+
+        Ok(())
+    }
+
+    fn snapshot(&self) -> PoolLiquiditySnapshot {
+        let (token_0_amount, token_1_amount) = self
+            .pool_data
+            .vault_amount_without_fee(self.vault_amounts[0], self.vault_amounts[1]);
+
+        PoolLiquiditySnapshot {
+            slot: self.slots.iter().copied().max().unwrap_or_default(),
+            liquidity: PoolLiquidity {
+                token_0_amount,
+                token_1_amount,
+                lp_supply: self.pool_data.lp_supply,
+            },
+        }
+    }
+}
+
+impl Iterator for PoolSubscription {
+    type Item = Result<PoolLiquiditySnapshot>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(snapshot) = self.pending.take() {
+            return Some(Ok(snapshot));
+        }
+
+        loop {
+            let Some(receiver) = self.receiver.take() else {
+                return Some(Ok(self.reconnect()));
+            };
+
+            let mut connected = match receiver.recv() {
+                Ok(notification) => match self.handle(notification) {
+                    Ok(connected) => connected,
+                    Err(e) => {
+                        self.receiver = Some(receiver);
+                        return Some(Err(e));
+                    }
+                },
+                Err(_) => false,
+            };
+
+            // Apply notifications that are already queued, e.g. both vaults changed by a swap,
+            // so the snapshot does not reflect a partially updated pool.
+            while connected {
+                match receiver.try_recv() {
+                    Ok(notification) => match self.handle(notification) {
+                        Ok(still_connected) => connected = still_connected,
+                        Err(e) => {
+                            self.receiver = Some(receiver);
+                            return Some(Err(e));
+                        }
+                    },
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => connected = false,
+                }
+            }
+
+            if connected {
+                self.receiver = Some(receiver);
+                return Some(Ok(self.snapshot()));
+            }
+            log::warn!("Pool subscription disconnected, resubscribing");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::LocalValidator;
+
+    /// Returns the next snapshot whose token 0 amount is `amount`, skipping earlier ones.
+    fn next_with_token_0_amount(
+        subscription: &mut PoolSubscription,
+        amount: u64,
+    ) -> PoolLiquiditySnapshot {
+        loop {
+            let snapshot = subscription
+                .next()
+                .expect("subscription ended")
+                .expect("failed to receive snapshot");
+            if snapshot.liquidity.token_0_amount == amount {
+                return snapshot;
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "requires a local test validator"]
+    fn snapshots_follow_vault_changes_across_reconnects() -> Result<()> {
+        let validator = LocalValidator::connect()?;
+        let (_, keys) = validator.create_pool()?;
+
+        let mut subscription = validator
+            .integration
+            .subscribe_pool_liquidity(keys.pool_state)?;
+        let initial = subscription.next().unwrap()?;
+        let amount = initial.liquidity.token_0_amount;
+
+        validator.transfer_to_vault(keys.creator_token_0, keys.token_0_vault, 1)?;
+        let changed = next_with_token_0_amount(&mut subscription, amount + 1);
+        assert!(changed.slot >= initial.slot);
+        assert_eq!(changed.liquidity.lp_supply, initial.liquidity.lp_supply);
+
+        // Shutting down the vault's websocket ends its notifications after the next one, which
+        // the iterator sees as a disconnection.
+        let mut closed = subscription.subscriptions.remove(TOKEN_0_VAULT);
+        let shutdown = thread::spawn(move || closed.shutdown());
+        validator.transfer_to_vault(keys.creator_token_0, keys.token_0_vault, 1)?;
+        next_with_token_0_amount(&mut subscription, amount + 2);
+        shutdown.join().unwrap().unwrap();
+
+        // The old websocket is gone, so this change can only arrive through the new subscriptions.
+        validator.transfer_to_vault(keys.creator_token_0, keys.token_0_vault, 1)?;
+        let resumed = next_with_token_0_amount(&mut subscription, amount + 3);
+        assert!(resumed.slot > changed.slot);
+        assert_eq!(subscription.subscriptions.len(), 3);
+
+        Ok(())
+    }
+}
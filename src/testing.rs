@@ -0,0 +1,174 @@
+use std::sync::Arc;
+
+use anchor_client::{
+    solana_sdk::{
+        instruction::Instruction,
+        native_token::LAMPORTS_PER_SOL,
+        program_pack::Pack,
+        pubkey::Pubkey,
+        signature::{Keypair, Signer},
+        system_instruction,
+        transaction::Transaction,
+    },
+    Cluster,
+};
+use anyhow::{anyhow, Context, Result};
+use spl_associated_token_account::{
+    get_associated_token_address, instruction::create_associated_token_account_idempotent,
+};
+
+use crate::{order_tokens, InitializationKeys, PoolInitialization, RaydiumIntegration};
+
+/// Lamports airdropped to the payer of every test.
+const AIRDROP_LAMPORTS: u64 = 100 * LAMPORTS_PER_SOL;
+/// Amount of each test token minted to the payer.
+const MINT_AMOUNT: u64 = 1_000_000_000_000;
+/// Initial amounts of the test pools.
+pub(crate) const POOL_TOKEN_0_AMOUNT: u64 = 1_000_000_000;
+pub(crate) const POOL_TOKEN_1_AMOUNT: u64 = 2_000_000_000;
+
+/// Local validator the ignored tests run against.
+///
+/// The tests expect `solana-test-validator` with the CP-AMM program, its first AMM config and
+/// the pool creation fee receiver cloned from devnet:
+///
+/// ```text
+/// solana-test-validator --url devnet \
+///     --clone-upgradeable-program CPMDWBwJDtYax9qW7AyRuVC19Cc4L4Vcy4n2BHAbHkCW \
+///     --clone <AMM config 0> --clone <create pool fee receiver>
+/// ```
+///
+/// and are run with `cargo test -- --ignored`.
+pub(crate) struct LocalValidator {
+    pub(crate) integration: RaydiumIntegration,
+    pub(crate) payer: Arc<Keypair>,
+}
+
+impl LocalValidator {
+    /// Connects a new payer funded by an airdrop.
+    pub(crate) fn connect() -> Result<Self> {
+        let payer = Arc::new(Keypair::new());
+        let integration = RaydiumIntegration::new(payer.clone(), Cluster::Localnet)?;
+
+        let signature = integration
+            .client_rpc
+            .request_airdrop(&payer.pubkey(), AIRDROP_LAMPORTS)
+            .context("failed to request airdrop")?;
+        integration
+            .client_rpc
+            .poll_for_signature(&signature)
+            .context("failed to confirm airdrop")?;
+
+        Ok(Self { integration, payer })
+    }
+
+    /// Signs and sends a transaction with the payer and additional signers.
+    pub(crate) fn send(&self, instructions: &[Instruction], signers: &[&Keypair]) -> Result<()> {
+        let recent_blockhash = self
+            .integration
+            .client_rpc
+            .get_latest_blockhash()
+            .context("failed to get recent blockhash")?;
+
+        let mut all_signers = vec![self.payer.as_ref()];
+        all_signers.extend_from_slice(signers);
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.payer.pubkey()),
+            &all_signers,
+            recent_blockhash,
+        );
+        self.integration
+            .client_rpc
+            .send_and_confirm_transaction(&transaction)
+            .context("failed to send transaction")?;
+
+        Ok(())
+    }
+
+    /// Creates an SPL Token mint and mints a balance to the payer's associated token account.
+    pub(crate) fn create_mint(&self, decimals: u8) -> Result<Pubkey> {
+        let mint = Keypair::new();
+        let payer = self.payer.pubkey();
+        let rent = self
+            .integration
+            .client_rpc
+            .get_minimum_balance_for_rent_exemption(spl_token::state::Mint::LEN)
+            .context("failed to get mint rent")?;
+
+        let instructions = [
+            system_instruction::create_account(
+                &payer,
+                &mint.pubkey(),
+                rent,
+                spl_token::state::Mint::LEN as u64,
+                &spl_token::id(),
+            ),
+            spl_token::instruction::initialize_mint2(
+                &spl_token::id(),
+                &mint.pubkey(),
+                &payer,
+                None,
+                decimals,
+            )?,
+            create_associated_token_account_idempotent(
+                &payer,
+                &payer,
+                &mint.pubkey(),
+                &spl_token::id(),
+            ),
+            spl_token::instruction::mint_to(
+                &spl_token::id(),
+                &mint.pubkey(),
+                &get_associated_token_address(&payer, &mint.pubkey()),
+                &payer,
+                &[],
+                MINT_AMOUNT,
+            )?,
+        ];
+        self.send(&instructions, &[&mint])
+            .context("failed to create mint")?;
+
+        Ok(mint.pubkey())
+    }
+
+    /// Creates a pool of two new mints under the first AMM config.
+    pub(crate) fn create_pool(&self) -> Result<(Pubkey, InitializationKeys)> {
+        let (token_0_mint, token_1_mint) = order_tokens(self.create_mint(6)?, self.create_mint(9)?);
+        let (amm_config, _) = self.integration.get_amm_config_by_index(0)?;
+
+        match self.integration.initialize_pool(
+            amm_config,
+            token_0_mint,
+            token_1_mint,
+            POOL_TOKEN_0_AMOUNT,
+            POOL_TOKEN_1_AMOUNT,
+            0,
+        )? {
+            PoolInitialization::Created { keys, .. } => Ok((amm_config, keys)),
+            initialization => Err(anyhow!(
+                "pool {} was not created",
+                initialization.keys().pool_state
+            )),
+        }
+    }
+
+    /// Transfers tokens from the payer's account straight into a pool vault.
+    pub(crate) fn transfer_to_vault(
+        &self,
+        source: Pubkey,
+        vault: Pubkey,
+        amount: u64,
+    ) -> Result<()> {
+        let instruction = spl_token::instruction::transfer(
+            &spl_token::id(),
+            &source,
+            &vault,
+            &self.payer.pubkey(),
+            &[],
+            amount,
+        )?;
+        self.send(&[instruction], &[])
+            .context("failed to transfer to vault")
+    }
+}
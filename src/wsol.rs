@@ -0,0 +1,94 @@
+use anchor_client::solana_sdk::{pubkey::Pubkey, signature::Signer, system_instruction};
+use anyhow::Result;
+use solana_program::instruction::Instruction;
+use spl_associated_token_account::{
+    get_associated_token_address, instruction::create_associated_token_account_idempotent,
+};
+use spl_token::native_mint;
+
+use crate::RaydiumIntegration;
+
+impl RaydiumIntegration {
+    /// Returns the owner's WSOL ATA.
+    pub fn get_wsol_account(&self) -> Pubkey {
+        get_associated_token_address(&self.owner(), &native_mint::id())
+    }
+
+    /// Creates instructions funding the owner's WSOL ATA with the amount of every native mint.
+    ///
+    /// Mints other than the native mint are ignored, so no instructions are returned for them.
+    pub(crate) fn create_wrap_sol_instructions(
+        &self,
+        amounts: &[(Pubkey, u64)],
+    ) -> Result<Vec<Instruction>> {
+        wrap_sol_instructions(&self.owner(), amounts)
+    }
+
+    /// Creates an instruction closing the owner's WSOL ATA if any mint is the native mint and
+    /// the owner held no WSOL before the transaction.
+    ///
+    /// Closing the account unwraps its whole balance back to SOL, so an account already holding
+    /// WSOL is left open and the transaction's WSOL stays wrapped in it.
+    pub(crate) fn create_unwrap_sol_instructions(
+        &self,
+        mints: &[Pubkey],
+    ) -> Result<Vec<Instruction>> {
+        unwrap_sol_instructions(&self.owner(), mints, self.held_wsol(mints)?)
+    }
+
+    /// Returns whether the owner's WSOL ATA holds WSOL, checked only if any mint is the native
+    /// mint.
+    pub(crate) fn held_wsol(&self, mints: &[Pubkey]) -> Result<bool> {
+        Ok(mints.contains(&native_mint::id())
+            && self.get_token_balance(&self.get_wsol_account())? > 0)
+    }
+}
+
+/// Creates instructions funding the owner's WSOL ATA with the amount of every native mint.
+pub(crate) fn wrap_sol_instructions(
+    owner: &Pubkey,
+    amounts: &[(Pubkey, u64)],
+) -> Result<Vec<Instruction>> {
+    let lamports: u64 = amounts
+        .iter()
+        .filter(|(mint, _)| *mint == native_mint::id())
+        .map(|(_, amount)| amount)
+        .sum();
+
+    if lamports == 0 {
+        return Ok(Vec::new());
+    }
+
+    let wsol_account = get_associated_token_address(owner, &native_mint::id());
+
+    Ok(vec![
+        create_associated_token_account_idempotent(
+            owner,
+            owner,
+            &native_mint::id(),
+            &spl_token::id(),
+        ),
+        system_instruction::transfer(owner, &wsol_account, lamports),
+        spl_token::instruction::sync_native(&spl_token::id(), &wsol_account)?,
+    ])
+}
+
+/// Creates an instruction closing the owner's WSOL ATA if any mint is the native mint, unless
+/// the owner held WSOL before the transaction.
+pub(crate) fn unwrap_sol_instructions(
+    owner: &Pubkey,
+    mints: &[Pubkey],
+    held_wsol: bool,
+) -> Result<Vec<Instruction>> {
+    if held_wsol || !mints.contains(&native_mint::id()) {
+        return Ok(Vec::new());
+    }
+
+    Ok(vec![spl_token::instruction::close_account(
+        &spl_token::id(),
+        &get_associated_token_address(owner, &native_mint::id()),
+        owner,
+        owner,
+        &[],
+    )?])
+}